@@ -0,0 +1,404 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::str::FromStr;
+
+use crate::atom::{self, AtomHeader};
+use crate::{ClapAtom, SampleDescription, TaptAtom, Track};
+
+/// The part of the picture free of edge artifacts, as stored in a 'clap' atom: its
+/// dimensions and the offset of its center from the center of the picture, in pixels,
+/// each as a fraction (numerator, denominator).
+///
+/// For example, the clean aperture of 720x486 NTSC video is 704x480 once the
+/// overscanned edges and the 6 extra lines are cropped.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct CleanAperture {
+    pub width: (u32, u32),
+    pub height: (u32, u32),
+    pub horizontal_offset: (i32, u32),
+    pub vertical_offset: (i32, u32),
+}
+
+impl CleanAperture {
+    /// Size of the payload of a 'clap' atom.
+    pub(crate) const SIZE: usize = 32;
+
+    /// Decodes the payload of a 'clap' atom.
+    pub(crate) fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        let u32_at = |at: usize| {
+            u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+
+        Self {
+            width: (u32_at(0), u32_at(4)),
+            height: (u32_at(8), u32_at(12)),
+            horizontal_offset: (u32_at(16) as i32, u32_at(20)),
+            vertical_offset: (u32_at(24) as i32, u32_at(28)),
+        }
+    }
+
+    /// Encodes the payload of a 'clap' atom.
+    pub(crate) fn to_bytes(self) -> [u8; Self::SIZE] {
+        let fields = [
+            self.width.0,
+            self.width.1,
+            self.height.0,
+            self.height.1,
+            self.horizontal_offset.0 as u32,
+            self.horizontal_offset.1,
+            self.vertical_offset.0 as u32,
+            self.vertical_offset.1,
+        ];
+        let mut bytes = [0; Self::SIZE];
+        for (i, field) in fields.iter().enumerate() {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&field.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+/// Parses a clean aperture given as `<WIDTH>,<HEIGHT>` or
+/// `<WIDTH>,<HEIGHT>,<HORIZONTAL_OFFSET>,<VERTICAL_OFFSET>` in pixels, each being an
+/// integer or a fraction, e.g. `704,480` or `3996,2160,0,-1/2`. The offsets default to
+/// 0, i.e. a centered clean aperture.
+impl FromStr for CleanAperture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (width, height, horizontal_offset, vertical_offset) = match fields.as_slice() {
+            [width, height] => (*width, *height, "0", "0"),
+            [width, height, horizontal_offset, vertical_offset] => {
+                (*width, *height, *horizontal_offset, *vertical_offset)
+            }
+            _ => {
+                return Err(
+                    "expected <WIDTH>,<HEIGHT> or <WIDTH>,<HEIGHT>,<HORIZONTAL_OFFSET>,<VERTICAL_OFFSET>"
+                        .to_string(),
+                )
+            }
+        };
+
+        let dimension = |field: &str| {
+            fraction(field)
+                .and_then(|(n, d)| Some((u32::try_from(n).ok().filter(|&n| n > 0)?, d)))
+                .ok_or_else(|| format!("`{}` isn't a positive dimension", field))
+        };
+        let offset = |field: &str| {
+            fraction(field)
+                .and_then(|(n, d)| Some((i32::try_from(n).ok()?, d)))
+                .ok_or_else(|| format!("`{}` isn't an offset", field))
+        };
+
+        Ok(Self {
+            width: dimension(width)?,
+            height: dimension(height)?,
+            horizontal_offset: offset(horizontal_offset)?,
+            vertical_offset: offset(vertical_offset)?,
+        })
+    }
+}
+
+impl fmt::Display for CleanAperture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}, offset ({}, {})",
+            Fraction(self.width.0 as i64, self.width.1),
+            Fraction(self.height.0 as i64, self.height.1),
+            Fraction(self.horizontal_offset.0 as i64, self.horizontal_offset.1),
+            Fraction(self.vertical_offset.0 as i64, self.vertical_offset.1)
+        )
+    }
+}
+
+/// The dimensions of a track in each of the aperture modes of a 'tapt' atom, as
+/// width and height in pixels: clean ('clef'), production ('prof') and encoded
+/// ('enof'). A mode without its atom is `None`.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct TrackAperture {
+    pub clean: Option<(f64, f64)>,
+    pub production: Option<(f64, f64)>,
+    pub encoded: Option<(f64, f64)>,
+}
+
+impl TrackAperture {
+    /// Size of the payload of a 'clef', 'prof' or 'enof' atom: version and flags,
+    /// then the width and the height as 16.16 fixed-point numbers.
+    pub(crate) const DIMENSIONS_SIZE: usize = 12;
+
+    /// Sets the dimensions of the aperture mode of the `kind` atom nested in 'tapt'
+    /// from its payload. Other atoms are ignored.
+    pub(crate) fn set_dimensions(&mut self, kind: [u8; 4], payload: &[u8; Self::DIMENSIONS_SIZE]) {
+        let fixed_at = |at: usize| {
+            u32::from_be_bytes([
+                payload[at],
+                payload[at + 1],
+                payload[at + 2],
+                payload[at + 3],
+            ]) as f64
+                / 65536.0
+        };
+        let dimensions = Some((fixed_at(4), fixed_at(8)));
+
+        match kind {
+            atom::CLEF => self.clean = dimensions,
+            atom::PROF => self.production = dimensions,
+            atom::ENOF => self.encoded = dimensions,
+            _ => {}
+        }
+    }
+
+    /// Builds the 'tapt' atom holding an atom for each aperture mode with dimensions.
+    pub(crate) fn to_atom(self) -> Vec<u8> {
+        let modes = [
+            (atom::CLEF, self.clean),
+            (atom::PROF, self.production),
+            (atom::ENOF, self.encoded),
+        ];
+        let children: Vec<u8> = modes
+            .iter()
+            .filter_map(|&(kind, dimensions)| {
+                let (width, height) = dimensions?;
+                let mut payload = [0; Self::DIMENSIONS_SIZE];
+                payload[4..8].copy_from_slice(&to_fixed(width).to_be_bytes());
+                payload[8..12].copy_from_slice(&to_fixed(height).to_be_bytes());
+                Some(atom::build(kind, &payload))
+            })
+            .flatten()
+            .collect();
+        atom::build(atom::TAPT, &children)
+    }
+}
+
+/// Parses track aperture dimensions given as `<CLEAN>,<PRODUCTION>,<ENCODED>`, each
+/// being `<WIDTH>x<HEIGHT>` in pixels, e.g. `704x480,720x480,720x486`.
+impl FromStr for TrackAperture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let dimensions = |field: &str| {
+            let (width, height) = field.trim().split_once('x')?;
+            let dimension = |value: &str| {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| *value > 0.0 && *value < 65536.0)
+            };
+            Some((dimension(width)?, dimension(height)?))
+        };
+
+        match s.split(',').collect::<Vec<_>>().as_slice() {
+            [clean, production, encoded] => Ok(Self {
+                clean: Some(dimensions(clean).ok_or_else(|| invalid_dimensions(clean))?),
+                production: Some(
+                    dimensions(production).ok_or_else(|| invalid_dimensions(production))?,
+                ),
+                encoded: Some(dimensions(encoded).ok_or_else(|| invalid_dimensions(encoded))?),
+            }),
+            _ => Err(
+                "expected <CLEAN>,<PRODUCTION>,<ENCODED>, e.g. 704x480,720x480,720x486".to_string(),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for TrackAperture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modes = [
+            ("clean", self.clean),
+            ("production", self.production),
+            ("encoded", self.encoded),
+        ];
+        for (i, (name, dimensions)) in modes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match dimensions {
+                Some((width, height)) => write!(f, "{} {}x{}", name, width, height)?,
+                None => write!(f, "{} none", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A fraction, displayed as an integer when its denominator is 1.
+struct Fraction(i64, u32);
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fraction(numerator, 1) => write!(f, "{}", numerator),
+            Fraction(numerator, denominator) => write!(f, "{}/{}", numerator, denominator),
+        }
+    }
+}
+
+/// Parses an integer or a fraction with a non-zero denominator, e.g. `-3` or `1/2`.
+fn fraction(field: &str) -> Option<(i64, u32)> {
+    match field.split_once('/') {
+        Some((numerator, denominator)) => Some((
+            numerator.trim().parse().ok()?,
+            denominator.trim().parse().ok().filter(|&d| d > 0)?,
+        )),
+        None => Some((field.parse().ok()?, 1)),
+    }
+}
+
+fn invalid_dimensions(field: &str) -> String {
+    format!("`{}` isn't <WIDTH>x<HEIGHT>", field)
+}
+
+fn to_fixed(value: f64) -> u32 {
+    (value * 65536.0).round() as u32
+}
+
+impl SampleDescription {
+    /// Constructs a clap atom (clean aperture) and sets its offset, size and clean
+    /// aperture. A clap atom too short to hold it is ignored.
+    pub(crate) fn construct_clap_atom(
+        &mut self,
+        file: &mut File,
+        header: &AtomHeader,
+    ) -> io::Result<()> {
+        if let Some(payload) = atom::read_payload(file, header)? {
+            self.clap_atom = ClapAtom {
+                size: header.size as u32,
+                offset: header.offset,
+                clean_aperture: CleanAperture::from_bytes(&payload),
+                matched: true,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl Track {
+    /// Constructs a tapt atom (track aperture mode dimensions) from the clef, prof and
+    /// enof atoms nested in it, and sets its offset and size. Nested atoms too short to
+    /// hold dimensions are ignored.
+    pub(crate) fn construct_tapt_atom(
+        &mut self,
+        file: &mut File,
+        header: &AtomHeader,
+    ) -> io::Result<()> {
+        let mut track_aperture = TrackAperture::default();
+        for child in atom::read_children_of(file, header)? {
+            if let Some(payload) = atom::read_payload(file, &child)? {
+                track_aperture.set_dimensions(child.kind, &payload);
+            }
+        }
+
+        self.tapt_atom = TaptAtom {
+            size: header.size as u32,
+            offset: header.offset,
+            track_aperture,
+            matched: true,
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{ColrPolicy, Selection};
+
+    #[test]
+    fn test_clean_aperture() {
+        let ntsc: CleanAperture = "704,480".parse().unwrap();
+        assert_eq!(
+            ntsc,
+            CleanAperture {
+                width: (704, 1),
+                height: (480, 1),
+                horizontal_offset: (0, 1),
+                vertical_offset: (0, 1),
+            }
+        );
+        assert_eq!(CleanAperture::from_bytes(&ntsc.to_bytes()), ntsc);
+
+        let dci: CleanAperture = "3996,2160,0,-1/2".parse().unwrap();
+        assert_eq!(dci.vertical_offset, (-1, 2));
+        assert_eq!(CleanAperture::from_bytes(&dci.to_bytes()), dci);
+        assert_eq!(dci.to_string(), "3996x2160, offset (0, -1/2)");
+
+        assert!("704".parse::<CleanAperture>().is_err());
+        assert!("0,480".parse::<CleanAperture>().is_err());
+        assert!("704,480,0,1/0".parse::<CleanAperture>().is_err());
+    }
+
+    #[test]
+    fn test_track_aperture() {
+        let aperture: TrackAperture = "704x480,720x480,720x486".parse().unwrap();
+        assert_eq!(aperture.encoded, Some((720.0, 486.0)));
+        assert_eq!(
+            aperture.to_string(),
+            "clean 704x480, production 720x480, encoded 720x486"
+        );
+
+        let tapt = aperture.to_atom();
+        assert_eq!(tapt.len(), 8 + 3 * 20);
+        let mut decoded = TrackAperture::default();
+        for child in tapt[8..].chunks(20) {
+            let kind = child[4..8].try_into().unwrap();
+            decoded.set_dimensions(kind, child[8..20].try_into().unwrap());
+        }
+        assert_eq!(decoded, aperture);
+
+        assert!("704x480,720x480".parse::<TrackAperture>().is_err());
+        assert!("704x480,720x480,720".parse::<TrackAperture>().is_err());
+    }
+
+    #[test]
+    fn test_clean_and_track_apertures() {
+        let (file_path, mut file, mut video) =
+            testing::open_footage("1-1-1_2frames_prores422.mov", "aperture.mov");
+
+        let clean_aperture: CleanAperture = "1888,1062".parse().unwrap();
+        video
+            .set_clean_aperture(&mut file, Selection::default(), &clean_aperture)
+            .unwrap();
+        let track_aperture: TrackAperture = "1888x1062,1920x1080,1920x1080".parse().unwrap();
+        video
+            .set_track_aperture(&mut file, Selection::default(), &track_aperture)
+            .unwrap();
+
+        testing::redecode(&file_path, &video);
+        let track = &video.tracks[0];
+        assert_eq!(track.tapt_atom.track_aperture, track_aperture);
+        assert_eq!(track.tapt_atom.size, 68);
+        let description = &track.sample_descriptions[0];
+        assert_eq!(description.clap_atom.clean_aperture, clean_aperture);
+        assert_eq!(description.sample_entry.size, 130 + 40);
+        assert_eq!(track.frames.len(), 2);
+        let info = video.info(ColrPolicy::default());
+        assert!(info.contains("  tapt: clean 1888x1062, production 1920x1080, encoded 1920x1080\n"));
+        assert!(info.contains("    clap: 1888x1062, offset (0, 0)\n"));
+
+        // Setting the track aperture again replaces the tapt atom.
+        let track_aperture: TrackAperture = "1920x1080,1920x1080,1920x1080".parse().unwrap();
+        video
+            .set_track_aperture(&mut file, Selection::default(), &track_aperture)
+            .unwrap();
+        assert_eq!(video.tracks[0].tapt_atom.track_aperture, track_aperture);
+        assert_eq!(video.tracks[0].tapt_atom.size, 68);
+
+        // Removing them again must give back the original file, byte for byte.
+        video
+            .remove_track_aperture(&mut file, Selection::default())
+            .unwrap();
+        video
+            .remove_clean_aperture(&mut file, Selection::default())
+            .unwrap();
+        assert_eq!(
+            std::fs::read(&file_path).unwrap(),
+            std::fs::read("tests/footages/1-1-1_2frames_prores422.mov").unwrap()
+        );
+    }
+}
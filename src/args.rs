@@ -1,6 +1,12 @@
 use std::ops::RangeInclusive;
 
-use clap::{command, Parser};
+use clap::Parser;
+
+use crate::{
+    timecode, AlphaChannelType, CleanAperture, ColorParameterType, ColrPolicy, ColrSelector,
+    ContentLightLevel, FrameRate, InterlaceMode, MasteringDisplay, PixelAspectRatio, Selection,
+    Timecode, TrackAperture, TrackSelector,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "atom_modifier", author = "thomjiji", version = "0.0.1")]
@@ -14,34 +20,227 @@ pub struct Args {
     #[arg(short, long = "input-file-path", value_name = "FILE", required = true)]
     pub input_file_path: String,
 
-    /// Change the "color primaries index" to <INDEX_VALUE>
-    #[arg(short, long = "color-primaries", value_name = "INDEX_VALUE", required = true, value_parser = color_primaries_value_check)]
-    pub primary_index: u8,
+    /// Change the "color primaries index" to <INDEX_VALUE>. Requires -t and -m
+    #[arg(short, long = "color-primaries", value_name = "INDEX_VALUE", required = false, requires = "transfer_function_index", value_parser = color_primaries_value_check)]
+    pub primary_index: Option<u8>,
 
-    /// Change the "transfer characteristics index" to <INDEX_VALUE>
+    /// Change the "transfer characteristics index" to <INDEX_VALUE>. Requires -p and -m
     #[arg(
         short,
         long = "transfer-characteristics",
         value_name = "INDEX_VALUE",
-        required = true,
+        required = false,
+        requires = "matrix_index",
         value_parser = transfer_characteristics_value_check,
     )]
-    pub transfer_function_index: u8,
+    pub transfer_function_index: Option<u8>,
 
-    /// Change the "matrix coefficients index" to <INDEX_VALUE>
+    /// Change the "matrix coefficients index" to <INDEX_VALUE>. Requires -p and -t
     #[arg(
         short,
         long = "matrix-coefficients",
         value_name = "INDEX_VALUE",
-        required = true,
+        required = false,
+        requires = "primary_index",
         value_parser = matrix_coefficients_value_check,
     )]
-    pub matrix_index: u8,
+    pub matrix_index: Option<u8>,
 
-    /// The gamma value to set. If not present, defaults to -1.0
-    #[arg(short, long = "gama-value", default_value_t = -1.0, required = false)]
+    /// The gamma value to set. If the file has no gama atom, one is inserted. If not present, defaults to -1.0. Requires -p, -t and -m
+    #[arg(
+        short,
+        long = "gama-value",
+        default_value_t = -1.0,
+        required = false,
+        requires = "primary_index"
+    )]
     pub gama_value: f32,
 
+    /// If passed, remove the gama atom from the file
+    #[arg(
+        long = "remove-gama",
+        default_value_t = false,
+        required = false,
+        conflicts_with = "gama_value"
+    )]
+    pub remove_gama: bool,
+
+    /// If passed, set the full range flag of the 'nclx' colr atom (MP4 files only), and of the H.264 and HEVC sequence parameter sets, in the sample description and in the samples: the video uses the full range of values
+    #[arg(
+        long = "full-range",
+        default_value_t = false,
+        required = false,
+        conflicts_with = "limited_range"
+    )]
+    pub full_range: bool,
+
+    /// If passed, clear the full range flag of the 'nclx' colr atom (MP4 files only), and of the H.264 and HEVC sequence parameter sets, in the sample description and in the samples: the video uses the limited ("video") range of values
+    #[arg(long = "limited-range", default_value_t = false, required = false)]
+    pub limited_range: bool,
+
+    /// If passed and the file has no colr atom, insert one into the video sample description instead of refusing to modify the file.
+    #[arg(
+        long = "insert-colr",
+        default_value_t = false,
+        required = false,
+        requires = "primary_index"
+    )]
+    pub insert_colr: bool,
+
+    /// Set the HDR mastering display metadata (mdcv atom, SMPTE ST 2086), inserting it if missing. <SPEC> is <PRESET>,<MAX_NITS>,<MIN_NITS> with BT709, P3D65, P3DCI or BT2020 as preset, e.g. P3D65,1000,0.0001, or the x,y coordinates of the green, blue and red primaries and of the white point followed by <MAX_NITS>,<MIN_NITS>
+    #[arg(long = "mastering-display", value_name = "SPEC", required = false, value_parser = mastering_display_check)]
+    pub mastering_display: Option<MasteringDisplay>,
+
+    /// If passed, remove the HDR mastering display metadata (mdcv atom)
+    #[arg(
+        long = "remove-mastering-display",
+        default_value_t = false,
+        required = false,
+        conflicts_with = "mastering_display"
+    )]
+    pub remove_mastering_display: bool,
+
+    /// Set the HDR content light levels (clli atom), inserting it if missing. <LEVELS> is <MAX_CLL>,<MAX_FALL> in cd/m², e.g. 1000,400
+    #[arg(long = "content-light-level", value_name = "LEVELS", required = false, value_parser = content_light_level_check)]
+    pub content_light_level: Option<ContentLightLevel>,
+
+    /// If passed, remove the HDR content light levels (clli atom)
+    #[arg(
+        long = "remove-content-light-level",
+        default_value_t = false,
+        required = false,
+        conflicts_with = "content_light_level"
+    )]
+    pub remove_content_light_level: bool,
+
+    /// Set the pixel aspect ratio (pasp atom), inserting it if missing, and the aspect ratio code of the ProRes frames to match. <H:V> is the width of a pixel for its height, e.g. 2:1 for a 2x anamorphic squeeze or 10:11 for NTSC DV
+    #[arg(long = "pixel-aspect-ratio", value_name = "H:V", required = false, value_parser = pixel_aspect_ratio_check)]
+    pub pixel_aspect_ratio: Option<PixelAspectRatio>,
+
+    /// Set the interlace mode in the fiel atom, inserting it if missing, and in the ProRes frames: progressive, tff (top field first) or bff (bottom field first). Refused if the frames aren't made of as many pictures as the mode needs
+    #[arg(long = "interlace-mode", value_name = "MODE", required = false, value_parser = interlace_mode_check)]
+    pub interlace_mode: Option<InterlaceMode>,
+
+    /// Set the alpha channel type of the ProRes frames: none, 8 (bits) or 16 (bits). An alpha channel can only be enabled in ProRes 4444 frames whose sample description has a depth of 32
+    #[arg(long = "alpha", value_name = "TYPE", required = false, value_parser = alpha_channel_type_check)]
+    pub alpha_channel_type: Option<AlphaChannelType>,
+
+    /// Set the frame rate code of the ProRes frames: 23.976, 24, 25, 29.97, 30, 50, 59.94, 60, 100, 119.88 or 120, or a fraction such as 24000/1001. Frames whose code then contradicts the timing of their track are reported
+    #[arg(long = "frame-rate", value_name = "FPS", required = false, value_parser = frame_rate_check)]
+    pub frame_rate: Option<FrameRate>,
+
+    /// If passed, also rewrite the media time scale (mdhd atom) and sample durations (stts atom) of the video tracks to match --frame-rate, along with the track, edit and movie durations. Refused if --sample-description leaves out some of their frames. Timecode and audio tracks are left as they are, see --conform
+    #[arg(
+        long = "container-timing",
+        default_value_t = false,
+        required = false,
+        requires = "frame_rate"
+    )]
+    pub container_timing: bool,
+
+    /// Conform the video tracks to <FPS> without re-encoding them: rewrite the media time scale and sample durations, the ProRes frame rate codes, the track, edit and movie durations, and rescale the timecode tracks. Same values as --frame-rate
+    #[arg(
+        long = "conform",
+        value_name = "FPS",
+        required = false,
+        conflicts_with = "frame_rate",
+        value_parser = frame_rate_check
+    )]
+    pub conform: Option<FrameRate>,
+
+    /// If passed, rescale the audio tracks along with --conform, their time scale and sample rate, changing their speed and pitch. Otherwise they are left alone, with a warning
+    #[arg(
+        long = "conform-audio",
+        default_value_t = false,
+        required = false,
+        requires = "conform"
+    )]
+    pub conform_audio: bool,
+
+    /// Set the clean aperture (clap atom), inserting it if missing. <APERTURE> is <WIDTH>,<HEIGHT> or <WIDTH>,<HEIGHT>,<HORIZONTAL_OFFSET>,<VERTICAL_OFFSET> in pixels, as integers or fractions, e.g. 704,480 or 3996,2160,0,-1/2
+    #[arg(long = "clean-aperture", value_name = "APERTURE", required = false, value_parser = clean_aperture_check)]
+    pub clean_aperture: Option<CleanAperture>,
+
+    /// If passed, remove the clean aperture (clap atom)
+    #[arg(
+        long = "remove-clean-aperture",
+        default_value_t = false,
+        required = false,
+        conflicts_with = "clean_aperture"
+    )]
+    pub remove_clean_aperture: bool,
+
+    /// Set the track aperture mode dimensions (tapt atom of the track), inserting it if missing. <DIMENSIONS> is <CLEAN>,<PRODUCTION>,<ENCODED>, each being <WIDTH>x<HEIGHT>, e.g. 704x480,720x480,720x486
+    #[arg(long = "track-aperture", value_name = "DIMENSIONS", required = false, value_parser = track_aperture_check)]
+    pub track_aperture: Option<TrackAperture>,
+
+    /// If passed, remove the track aperture mode dimensions (tapt atom of the track)
+    #[arg(
+        long = "remove-track-aperture",
+        default_value_t = false,
+        required = false,
+        conflicts_with = "track_aperture"
+    )]
+    pub remove_track_aperture: bool,
+
+    /// Set the reel name of the timecode tracks (name atom of their sample description), inserting it if missing, e.g. A001C003
+    #[arg(long = "reel-name", value_name = "NAME", required = false, value_parser = reel_name_check)]
+    pub reel_name: Option<String>,
+
+    /// If passed, remove the reel name from the timecode tracks
+    #[arg(
+        long = "remove-reel-name",
+        default_value_t = false,
+        required = false,
+        conflicts_with = "reel_name"
+    )]
+    pub remove_reel_name: bool,
+
+    /// Set the start timecode of the timecode tracks, i.e. the timecode of their first frame, as HH:MM:SS:FF, e.g. 10:00:00:00. Frames are counted as the timecode track says, dropping frame numbers if it does
+    #[arg(long = "start-timecode", value_name = "TIMECODE", required = false, value_parser = timecode_check)]
+    pub start_timecode: Option<Timecode>,
+
+    /// Print the description, white point and tone curves of the ICC profiles embedded in 'prof' colr atoms
+    #[arg(long = "icc-info", default_value_t = false, required = false)]
+    pub icc_info: bool,
+
+    /// Write the ICC profile embedded in the 'prof' colr atom to <ICC_FILE>
+    #[arg(long = "extract-icc", value_name = "ICC_FILE", required = false)]
+    pub extract_icc: Option<String>,
+
+    /// Embed the ICC profile read from <ICC_FILE> in a 'prof' colr atom, replacing the existing one if any
+    #[arg(long = "icc-profile", value_name = "ICC_FILE", required = false)]
+    pub icc_profile: Option<String>,
+
+    /// Only modify the track with this track ID. Defaults to all video tracks
+    #[arg(
+        long = "track-id",
+        value_name = "ID",
+        required = false,
+        conflicts_with = "track_index"
+    )]
+    pub track_id: Option<u32>,
+
+    /// Only modify the track at this position in the file, starting from 0 and counting all tracks. Defaults to all video tracks
+    #[arg(long = "track-index", value_name = "INDEX", required = false)]
+    pub track_index: Option<usize>,
+
+    /// Only modify the colr atoms of this type: nclc, nclx, prof, rICC, or "honoured" for the one picked by --colr-policy. Defaults to all colr atoms
+    #[arg(long = "colr", value_name = "TYPE", required = false, value_parser = colr_selector_check)]
+    pub colr: Option<ColrSelector>,
+
+    /// Which colr atom a player honours when a sample description has several: first, last, icc (the first ICC profile) or nclx (the first nclx or nclc)
+    #[arg(long = "colr-policy", value_name = "POLICY", default_value = "first", value_parser = colr_policy_check)]
+    pub colr_policy: ColrPolicy,
+
+    /// Print the tracks and their tapt atom, their sample descriptions and their colr, gama, mdcv, clli, clap, fiel and pasp atoms
+    #[arg(long = "info", default_value_t = false, required = false)]
+    pub info: bool,
+
+    /// Only modify this sample description entry of the selected tracks, starting from 1 as in the stsc atom. Defaults to all entries
+    #[arg(long = "sample-description", value_name = "INDEX", required = false)]
+    pub sample_description_index: Option<u32>,
+
     /// If passed, modify the input file in-place. Otherwise, create a backup of input file. Defaults to false (create backup).
     #[arg(
         short = 'I',
@@ -52,6 +251,78 @@ pub struct Args {
     pub modify_in_place: bool,
 }
 
+impl Args {
+    /// Returns the color primaries, transfer characteristics and matrix coefficients
+    /// indexes to write, or `None` if they weren't passed (clap makes sure they are
+    /// passed together).
+    pub fn color_indexes(&self) -> Option<(u8, u8, u8)> {
+        match (
+            self.primary_index,
+            self.transfer_function_index,
+            self.matrix_index,
+        ) {
+            (Some(p), Some(t), Some(m)) => Some((p, t, m)),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the arguments ask for the file to be modified, rather than only
+    /// read.
+    pub fn modifies_file(&self) -> bool {
+        self.color_indexes().is_some()
+            || self.remove_gama
+            || self.full_range().is_some()
+            || self.icc_profile.is_some()
+            || self.mastering_display.is_some()
+            || self.remove_mastering_display
+            || self.content_light_level.is_some()
+            || self.remove_content_light_level
+            || self.pixel_aspect_ratio.is_some()
+            || self.interlace_mode.is_some()
+            || self.alpha_channel_type.is_some()
+            || self.frame_rate.is_some()
+            || self.conform.is_some()
+            || self.clean_aperture.is_some()
+            || self.remove_clean_aperture
+            || self.track_aperture.is_some()
+            || self.remove_track_aperture
+            || self.reel_name.is_some()
+            || self.remove_reel_name
+            || self.start_timecode.is_some()
+    }
+
+    /// Returns the range to write in the 'nclx' colr atom, according to
+    /// `--full-range` and `--limited-range`, or `None` to leave it as is.
+    pub fn full_range(&self) -> Option<bool> {
+        match (self.full_range, self.limited_range) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Returns which tracks, sample descriptions and colr atoms to modify, according to
+    /// `--track-id`, `--track-index`, `--sample-description`, `--colr` and
+    /// `--colr-policy`.
+    pub fn selection(&self) -> Selection {
+        let tracks = match (self.track_id, self.track_index) {
+            (Some(track_id), _) => TrackSelector::Id(track_id),
+            (None, Some(index)) => TrackSelector::Index(index),
+            (None, None) => TrackSelector::AllVideo,
+        };
+        let colr = match self.colr {
+            Some(ColrSelector::Honoured(_)) => ColrSelector::Honoured(self.colr_policy),
+            Some(colr) => colr,
+            None => ColrSelector::All,
+        };
+        Selection {
+            tracks,
+            sample_description_index: self.sample_description_index,
+            colr,
+        }
+    }
+}
+
 const PRIMARIES_RANGE: RangeInclusive<usize> = 0..=12;
 const TRANSFER_FUNCTION_RANGE: RangeInclusive<usize> = 0..=18;
 const MATRIX_RANGE: RangeInclusive<usize> = 0..=14;
@@ -140,6 +411,69 @@ fn matrix_coefficients_value_check(s: &str) -> Result<u8, String> {
     value_check(s, MATRIX_RANGE, "matrix", get_matrix_name)
 }
 
+fn mastering_display_check(s: &str) -> Result<MasteringDisplay, String> {
+    s.parse()
+}
+
+fn content_light_level_check(s: &str) -> Result<ContentLightLevel, String> {
+    s.parse()
+}
+
+fn pixel_aspect_ratio_check(s: &str) -> Result<PixelAspectRatio, String> {
+    s.parse()
+}
+
+fn interlace_mode_check(s: &str) -> Result<InterlaceMode, String> {
+    s.parse()
+}
+
+fn clean_aperture_check(s: &str) -> Result<CleanAperture, String> {
+    s.parse()
+}
+
+fn track_aperture_check(s: &str) -> Result<TrackAperture, String> {
+    s.parse()
+}
+
+fn alpha_channel_type_check(s: &str) -> Result<AlphaChannelType, String> {
+    s.parse()
+}
+
+fn frame_rate_check(s: &str) -> Result<FrameRate, String> {
+    s.parse()
+}
+
+fn reel_name_check(s: &str) -> Result<String, String> {
+    timecode::check_reel_name(s)?;
+    Ok(s.to_string())
+}
+
+fn timecode_check(s: &str) -> Result<Timecode, String> {
+    s.parse()
+}
+
+fn colr_selector_check(s: &str) -> Result<ColrSelector, String> {
+    match s {
+        "nclc" => Ok(ColrSelector::Type(ColorParameterType::Nclc)),
+        "nclx" => Ok(ColrSelector::Type(ColorParameterType::Nclx)),
+        "prof" => Ok(ColrSelector::Type(ColorParameterType::Prof)),
+        "rICC" => Ok(ColrSelector::Type(ColorParameterType::RIcc)),
+        // The policy is given by --colr-policy, see `Args::selection`.
+        "honoured" => Ok(ColrSelector::Honoured(ColrPolicy::default())),
+        _ => Err("valid values are nclc, nclx, prof, rICC and honoured".to_string()),
+    }
+}
+
+fn colr_policy_check(s: &str) -> Result<ColrPolicy, String> {
+    match s {
+        "first" => Ok(ColrPolicy::First),
+        "last" => Ok(ColrPolicy::Last),
+        "icc" => Ok(ColrPolicy::PreferIcc),
+        "nclx" => Ok(ColrPolicy::PreferNclx),
+        _ => Err("valid values are first, last, icc and nclx".to_string()),
+    }
+}
+
 fn value_check<T>(
     s: &str,
     range: RangeInclusive<usize>,
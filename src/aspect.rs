@@ -0,0 +1,206 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::str::FromStr;
+
+use crate::atom::{self, AtomHeader};
+use crate::{PaspAtom, SampleDescription};
+
+/// The aspect_ratio_information codes of a ProRes frame header. They describe the
+/// display aspect ratio of the frame, not the shape of its pixels.
+const PRORES_UNKNOWN: u8 = 0;
+const PRORES_SQUARE_PIXELS: u8 = 1;
+const PRORES_4_3: u8 = 2;
+const PRORES_16_9: u8 = 3;
+
+/// How far a display aspect ratio may be from 4:3 or 16:9 to still be flagged as
+/// such, e.g. 720x486 NTSC DV with 10:11 pixels is 1.3333 × 1.0101.
+const DISPLAY_ASPECT_RATIO_TOLERANCE: f64 = 0.02;
+
+/// The shape of the pixels of a video, as stored in a 'pasp' atom: a pixel is
+/// `h_spacing` wide for `v_spacing` high, e.g. 2:1 for an anamorphic 2x squeeze.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelAspectRatio {
+    pub h_spacing: u32,
+    pub v_spacing: u32,
+}
+
+impl Default for PixelAspectRatio {
+    fn default() -> Self {
+        Self {
+            h_spacing: 1,
+            v_spacing: 1,
+        }
+    }
+}
+
+impl PixelAspectRatio {
+    /// Size of the payload of a 'pasp' atom.
+    pub(crate) const SIZE: usize = 8;
+
+    /// Decodes the payload of a 'pasp' atom.
+    pub(crate) fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            h_spacing: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            v_spacing: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+
+    /// Encodes the payload of a 'pasp' atom.
+    pub(crate) fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.h_spacing.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.v_spacing.to_be_bytes());
+        bytes
+    }
+
+    /// Returns the aspect_ratio_information code of a ProRes frame of `width` x
+    /// `height` pixels of this shape: square pixels, a 4:3 or 16:9 display aspect
+    /// ratio, or unknown for any other display aspect ratio.
+    pub(crate) fn prores_aspect_ratio_information(self, width: u16, height: u16) -> u8 {
+        if self.h_spacing == self.v_spacing {
+            return PRORES_SQUARE_PIXELS;
+        }
+        if width == 0 || height == 0 || self.v_spacing == 0 {
+            return PRORES_UNKNOWN;
+        }
+
+        let display_aspect_ratio =
+            width as f64 * self.h_spacing as f64 / (height as f64 * self.v_spacing as f64);
+        let is_close_to = |ratio: f64| {
+            (display_aspect_ratio / ratio - 1.0).abs() <= DISPLAY_ASPECT_RATIO_TOLERANCE
+        };
+        if is_close_to(4.0 / 3.0) {
+            PRORES_4_3
+        } else if is_close_to(16.0 / 9.0) {
+            PRORES_16_9
+        } else {
+            PRORES_UNKNOWN
+        }
+    }
+}
+
+/// Describes the aspect_ratio_information code of a ProRes frame header.
+pub(crate) fn describe_prores_aspect_ratio_information(code: u8) -> &'static str {
+    match code {
+        PRORES_UNKNOWN => "unknown",
+        PRORES_SQUARE_PIXELS => "square pixels",
+        PRORES_4_3 => "4:3",
+        PRORES_16_9 => "16:9",
+        _ => "reserved",
+    }
+}
+
+/// Parses a pixel aspect ratio given as `<H>:<V>`, e.g. `2:1` or `10:11`.
+impl FromStr for PixelAspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((h_spacing, v_spacing)) = s.split_once(':') else {
+            return Err("expected <H>:<V>, e.g. 2:1".to_string());
+        };
+        let spacing = |field: &str| {
+            field
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|&spacing| spacing > 0)
+                .ok_or_else(|| format!("`{}` isn't a positive integer", field))
+        };
+
+        Ok(Self {
+            h_spacing: spacing(h_spacing)?,
+            v_spacing: spacing(v_spacing)?,
+        })
+    }
+}
+
+impl fmt::Display for PixelAspectRatio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.h_spacing, self.v_spacing)
+    }
+}
+
+impl SampleDescription {
+    /// Constructs a pasp atom (pixel aspect ratio) and sets its offset, size and
+    /// pixel aspect ratio. A pasp atom too short to hold it is ignored.
+    pub(crate) fn construct_pasp_atom(
+        &mut self,
+        file: &mut File,
+        header: &AtomHeader,
+    ) -> io::Result<()> {
+        if let Some(payload) = atom::read_payload(file, header)? {
+            self.pasp_atom = PaspAtom {
+                size: header.size as u32,
+                offset: header.offset,
+                pixel_aspect_ratio: PixelAspectRatio::from_bytes(&payload),
+                matched: true,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{ColrPolicy, Selection};
+
+    #[test]
+    fn test_pixel_aspect_ratio() {
+        let anamorphic: PixelAspectRatio = "2:1".parse().unwrap();
+        assert_eq!(
+            PixelAspectRatio::from_bytes(&anamorphic.to_bytes()),
+            anamorphic
+        );
+        assert_eq!(anamorphic.to_string(), "2:1");
+        assert!("2".parse::<PixelAspectRatio>().is_err());
+        assert!("0:1".parse::<PixelAspectRatio>().is_err());
+
+        let square = PixelAspectRatio::default();
+        assert_eq!(square.prores_aspect_ratio_information(1920, 1080), 1);
+        // 1440x1080 HDV with 4:3 pixels is 16:9, 720x486 NTSC DV with 10:11 pixels
+        // is 4:3.
+        let hdv: PixelAspectRatio = "4:3".parse().unwrap();
+        assert_eq!(hdv.prores_aspect_ratio_information(1440, 1080), 3);
+        let dv: PixelAspectRatio = "10:11".parse().unwrap();
+        assert_eq!(dv.prores_aspect_ratio_information(720, 486), 2);
+        // A 2x squeeze of a 4:3 frame is 8:3, which ProRes can't describe.
+        assert_eq!(anamorphic.prores_aspect_ratio_information(2880, 2160), 0);
+    }
+
+    #[test]
+    fn test_set_pixel_aspect_ratio() {
+        let (file_path, mut file, mut video) =
+            testing::open_footage("1-1-1_2frames_prores422.mov", "pasp.mov");
+        assert!(video.info(ColrPolicy::default()).contains("pasp: 1:1\n"));
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("ProRes aspect ratio: unknown\n"));
+
+        // 1920x1080 with 3:4 pixels is a 4:3 picture. The existing pasp atom is
+        // overwritten and the frame rate code next to the aspect ratio one is kept.
+        let original = std::fs::read(&file_path).unwrap();
+        let pixel_aspect_ratio: PixelAspectRatio = "3:4".parse().unwrap();
+        video
+            .set_pixel_aspect_ratio(&mut file, Selection::default(), &pixel_aspect_ratio)
+            .unwrap();
+        testing::redecode(&file_path, &video);
+        let description = &video.tracks[0].sample_descriptions[0];
+        assert_eq!(description.pasp_atom.pixel_aspect_ratio, pixel_aspect_ratio);
+        assert_eq!(description.sample_entry.size, 130);
+
+        let modified = std::fs::read(&file_path).unwrap();
+        for frame in video.tracks[0].frames.iter() {
+            assert_eq!(frame.aspect_ratio_information, 2);
+            let at = frame.offset as usize + 21;
+            assert_eq!(modified[at], 0x20 | original[at] & 0x0F);
+        }
+        assert!(video.info(ColrPolicy::default()).contains("pasp: 3:4\n"));
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("ProRes aspect ratio: 4:3\n"));
+    }
+}
@@ -0,0 +1,430 @@
+use std::fs::File;
+use std::io::{self, Read, Seek};
+
+pub(crate) const FTYP: [u8; 4] = *b"ftyp";
+pub(crate) const MOOV: [u8; 4] = *b"moov";
+pub(crate) const MVHD: [u8; 4] = *b"mvhd";
+pub(crate) const TRAK: [u8; 4] = *b"trak";
+pub(crate) const TKHD: [u8; 4] = *b"tkhd";
+pub(crate) const MDIA: [u8; 4] = *b"mdia";
+pub(crate) const MDHD: [u8; 4] = *b"mdhd";
+pub(crate) const HDLR: [u8; 4] = *b"hdlr";
+pub(crate) const MINF: [u8; 4] = *b"minf";
+pub(crate) const STBL: [u8; 4] = *b"stbl";
+pub(crate) const STSD: [u8; 4] = *b"stsd";
+pub(crate) const STSZ: [u8; 4] = *b"stsz";
+pub(crate) const STSC: [u8; 4] = *b"stsc";
+pub(crate) const STTS: [u8; 4] = *b"stts";
+pub(crate) const STCO: [u8; 4] = *b"stco";
+pub(crate) const CO64: [u8; 4] = *b"co64";
+pub(crate) const EDTS: [u8; 4] = *b"edts";
+pub(crate) const ELST: [u8; 4] = *b"elst";
+pub(crate) const DINF: [u8; 4] = *b"dinf";
+pub(crate) const UDTA: [u8; 4] = *b"udta";
+pub(crate) const GMHD: [u8; 4] = *b"gmhd";
+pub(crate) const FREE: [u8; 4] = *b"free";
+pub(crate) const SKIP: [u8; 4] = *b"skip";
+pub(crate) const COLR: [u8; 4] = *b"colr";
+pub(crate) const GAMA: [u8; 4] = *b"gama";
+pub(crate) const MDCV: [u8; 4] = *b"mdcv";
+pub(crate) const CLLI: [u8; 4] = *b"clli";
+pub(crate) const PASP: [u8; 4] = *b"pasp";
+pub(crate) const FIEL: [u8; 4] = *b"fiel";
+pub(crate) const CLAP: [u8; 4] = *b"clap";
+pub(crate) const TAPT: [u8; 4] = *b"tapt";
+pub(crate) const CLEF: [u8; 4] = *b"clef";
+pub(crate) const PROF: [u8; 4] = *b"prof";
+pub(crate) const ENOF: [u8; 4] = *b"enof";
+pub(crate) const AVCC: [u8; 4] = *b"avcC";
+pub(crate) const HVCC: [u8; 4] = *b"hvcC";
+pub(crate) const TMCD: [u8; 4] = *b"tmcd";
+pub(crate) const NAME: [u8; 4] = *b"name";
+
+/// The handler types of video, sound and timecode media, found in the `hdlr` atom of
+/// `mdia`.
+pub(crate) const VIDEO_HANDLER: [u8; 4] = *b"vide";
+pub(crate) const SOUND_HANDLER: [u8; 4] = *b"soun";
+pub(crate) const TIMECODE_HANDLER: [u8; 4] = *b"tmcd";
+
+/// Size of the header of a box: 4 bytes of size followed by 4 bytes of type.
+pub(crate) const ATOM_HEADER_SIZE: u64 = 8;
+
+/// Size of a video sample description entry before its first child atom (`fiel`,
+/// `colr`, `gama`, `pasp`...), header included.
+pub(crate) const VISUAL_SAMPLE_ENTRY_SIZE: u64 = 86;
+
+/// Size of a timecode ('tmcd') sample description entry before its first child atom
+/// (`name`), header included.
+pub(crate) const TIMECODE_SAMPLE_ENTRY_SIZE: u64 = 34;
+
+/// How the size of an atom is stored in its header.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SizeForm {
+    /// A 32-bit size, the header is 8 bytes long.
+    #[default]
+    Compact,
+    /// A 32-bit size of 1 followed by a 64-bit "largesize" after the type, the header
+    /// is 16 bytes long. Used by mdat of files over 4 GB.
+    Large,
+    /// A 32-bit size of 0: the atom extends to the end of the file (or of its parent).
+    ToEnd,
+}
+
+/// The header of an atom (box) of a QuickTime file, i.e. where it lives in the file,
+/// how big it is and what its type is.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct AtomHeader {
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+    pub(crate) kind: [u8; 4],
+    pub(crate) size_form: SizeForm,
+}
+
+impl AtomHeader {
+    /// Reads the header of the atom located at `offset`, in a parent (or file) ending
+    /// at `end`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` of kind `InvalidData` if the size field is smaller than
+    /// the header itself, which happens when `offset` doesn't point to an atom.
+    pub(crate) fn read(file: &mut File, offset: u64, end: u64) -> io::Result<Self> {
+        let mut buf = [0; 8];
+        file.seek(io::SeekFrom::Start(offset))?;
+        file.read_exact(&mut buf)?;
+
+        let size = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as u64;
+        let kind = [buf[4], buf[5], buf[6], buf[7]];
+
+        let (size, size_form) = match size {
+            0 => (end.saturating_sub(offset), SizeForm::ToEnd),
+            1 => {
+                let mut largesize_buf = [0; 8];
+                file.read_exact(&mut largesize_buf)?;
+                (u64::from_be_bytes(largesize_buf), SizeForm::Large)
+            }
+            _ => (size, SizeForm::Compact),
+        };
+
+        let header = Self {
+            offset,
+            size,
+            kind,
+            size_form,
+        };
+        if size < header.header_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "atom '{}' at offset {} has an invalid size of {}",
+                    fourcc(&kind),
+                    offset,
+                    size
+                ),
+            ));
+        }
+
+        Ok(header)
+    }
+
+    /// The size of the header: 16 bytes with a largesize, 8 bytes otherwise.
+    pub(crate) fn header_size(&self) -> u64 {
+        match self.size_form {
+            SizeForm::Large => ATOM_HEADER_SIZE + 8,
+            _ => ATOM_HEADER_SIZE,
+        }
+    }
+
+    /// The offset of the first byte after the header, i.e. the payload of the atom.
+    pub(crate) fn body_offset(&self) -> u64 {
+        self.offset + self.header_size()
+    }
+
+    /// The offset of the first byte after the atom.
+    pub(crate) fn end(&self) -> u64 {
+        self.offset + self.size
+    }
+}
+
+/// Reads the headers of all the atoms laid out one after another between `start` and
+/// `end`, which is typically the payload of a container atom such as `moov` or `trak`.
+///
+/// Trailing bytes that are too small to hold an atom header (e.g. the 32-bit
+/// terminator some writers put at the end of a sample description) are ignored.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file can't be read, or if a child atom claims to be
+/// bigger than its parent.
+pub(crate) fn read_children(file: &mut File, start: u64, end: u64) -> io::Result<Vec<AtomHeader>> {
+    let mut children = Vec::new();
+    let mut offset = start;
+
+    while offset + ATOM_HEADER_SIZE <= end {
+        let header = AtomHeader::read(file, offset, end)?;
+        if header.end() > end {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "atom '{}' at offset {} overflows its parent (ends at {}, parent ends at {})",
+                    fourcc(&header.kind),
+                    offset,
+                    header.end(),
+                    end
+                ),
+            ));
+        }
+        offset = header.end();
+        children.push(header);
+    }
+
+    Ok(children)
+}
+
+/// Reads the headers of the children of a container atom.
+///
+/// The children of `stsd` are its sample description entries, which come after its
+/// version, flags and number of entries.
+pub(crate) fn read_children_of(
+    file: &mut File,
+    parent: &AtomHeader,
+) -> io::Result<Vec<AtomHeader>> {
+    let start = match parent.kind {
+        STSD => parent.body_offset() + 8,
+        _ => parent.body_offset(),
+    };
+    read_children(file, start, parent.end())
+}
+
+/// Returns where the first child atom of a sample description entry may start: after
+/// the fields of a timecode entry, or after the ones of a video entry for any other
+/// format.
+pub(crate) fn sample_entry_children_offset(entry: &AtomHeader) -> u64 {
+    match entry.kind {
+        TMCD => entry.offset + TIMECODE_SAMPLE_ENTRY_SIZE,
+        _ => entry.offset + VISUAL_SAMPLE_ENTRY_SIZE,
+    }
+}
+
+/// Reads the headers of the atoms (`fiel`, `colr`, `gama`, `pasp`...) nested in a
+/// video sample description entry, or of the `name` atom nested in a timecode one.
+pub(crate) fn read_sample_entry_children(
+    file: &mut File,
+    entry: &AtomHeader,
+) -> io::Result<Vec<AtomHeader>> {
+    let start = sample_entry_children_offset(entry);
+    if start > entry.end() {
+        return Ok(Vec::new());
+    }
+    read_children(file, start, entry.end())
+}
+
+/// Returns the chain of atoms leading to the atom located at `offset`, from the
+/// top-level atom down to that atom itself, e.g. `moov, trak, mdia, minf, stbl,
+/// stsd, apcn` for a video sample description entry.
+///
+/// These are the atoms whose size changes when something is inserted into or removed
+/// from the target atom.
+///
+/// # Errors
+///
+/// Returns an `io::Error` of kind `NotFound` if no atom starts at `offset`.
+pub(crate) fn path_to(file: &mut File, offset: u64) -> io::Result<Vec<AtomHeader>> {
+    let file_size = file.metadata()?.len();
+    let mut path: Vec<AtomHeader> = Vec::new();
+    let mut children = read_children(file, 0, file_size)?;
+
+    while let Some(atom) = children
+        .iter()
+        .find(|a| a.offset <= offset && offset < a.end())
+        .copied()
+    {
+        let parent_kind = path.last().map(|parent| parent.kind);
+        path.push(atom);
+        if atom.offset == offset {
+            return Ok(path);
+        }
+
+        match read_children_in_path(file, &atom, parent_kind)? {
+            Some(atoms) => children = atoms,
+            None => break,
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no atom starts at offset {}", offset),
+    ))
+}
+
+/// Reads the headers of the children of `atom`, whose parent is of type
+/// `parent_kind`, as found on the way to a nested atom: containers such as `moov` or
+/// `stbl`, and sample description entries. Returns `None` for any other atom.
+pub(crate) fn read_children_in_path(
+    file: &mut File,
+    atom: &AtomHeader,
+    parent_kind: Option<[u8; 4]>,
+) -> io::Result<Option<Vec<AtomHeader>>> {
+    match atom.kind {
+        MOOV | TRAK | EDTS | MDIA | MINF | DINF | STBL | STSD | UDTA | GMHD => {
+            read_children_of(file, atom).map(Some)
+        }
+        _ if parent_kind == Some(STSD) => read_sample_entry_children(file, atom).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Returns the headers of every `trak` atom of the file, in the order they appear in
+/// moov.
+pub(crate) fn traks(file: &mut File) -> io::Result<Vec<AtomHeader>> {
+    let file_size = file.metadata()?.len();
+    let mut traks = Vec::new();
+
+    for moov in read_children(file, 0, file_size)?
+        .iter()
+        .filter(|a| a.kind == MOOV)
+    {
+        traks.extend(
+            read_children_of(file, moov)?
+                .into_iter()
+                .filter(|a| a.kind == TRAK),
+        );
+    }
+
+    Ok(traks)
+}
+
+/// Returns the headers of every chunk offset table (`stco` and `co64`) of the file,
+/// one per track.
+pub(crate) fn chunk_offset_tables(file: &mut File) -> io::Result<Vec<AtomHeader>> {
+    let mut tables = Vec::new();
+
+    for trak in traks(file)? {
+        let mut atoms = read_children_of(file, &trak)?;
+        for kind in [MDIA, MINF, STBL] {
+            atoms = match find(&atoms, kind) {
+                Some(atom) => read_children_of(file, &atom)?,
+                None => Vec::new(),
+            };
+        }
+        tables.extend(atoms.iter().filter(|a| a.kind == STCO || a.kind == CO64));
+    }
+
+    Ok(tables)
+}
+
+/// Reads the whole payload of an atom in memory. Only meant for the atoms of moov,
+/// never for `mdat`.
+pub(crate) fn read_body(file: &mut File, header: &AtomHeader) -> io::Result<Vec<u8>> {
+    let mut body = vec![0; (header.end() - header.body_offset()) as usize];
+    file.seek(io::SeekFrom::Start(header.body_offset()))?;
+    file.read_exact(&mut body)?;
+    Ok(body)
+}
+
+/// Reads the first `N` bytes of the payload of an atom, the fixed-size fields of an
+/// atom such as `pasp` or `mdcv`. Returns `None` if the payload is too short to hold
+/// them, in which case the atom is ignored.
+pub(crate) fn read_payload<const N: usize>(
+    file: &mut File,
+    header: &AtomHeader,
+) -> io::Result<Option<[u8; N]>> {
+    if header.end() - header.body_offset() < N as u64 {
+        return Ok(None);
+    }
+    let mut payload = [0; N];
+    file.seek(io::SeekFrom::Start(header.body_offset()))?;
+    file.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+/// Builds an atom with a compact header from its type and payload.
+pub(crate) fn build(kind: [u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut atom = Vec::with_capacity(ATOM_HEADER_SIZE as usize + payload.len());
+    atom.extend_from_slice(&(ATOM_HEADER_SIZE as u32 + payload.len() as u32).to_be_bytes());
+    atom.extend_from_slice(&kind);
+    atom.extend_from_slice(payload);
+    atom
+}
+
+/// Returns the first atom of the given type in `atoms`, if any.
+pub(crate) fn find(atoms: &[AtomHeader], kind: [u8; 4]) -> Option<AtomHeader> {
+    atoms.iter().find(|atom| atom.kind == kind).copied()
+}
+
+/// Formats a four character code for error messages and logs.
+pub(crate) fn fourcc(kind: &[u8; 4]) -> String {
+    kind.iter().map(|&b| b as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_read_children() {
+        // ftyp, an mdat with a largesize whose essence looks like a colr atom, moov,
+        // and a free atom extending to the end of the file.
+        let ftyp = testing::atom(b"ftyp", b"qt  \x00\x00\x02\x00qt  ");
+        let essence = testing::nclc_colr_atom(9, 16, 9);
+        let mdat = [
+            &1u32.to_be_bytes()[..],
+            b"mdat",
+            &(16 + essence.len() as u64).to_be_bytes(),
+            &essence,
+        ]
+        .concat();
+        let udta = testing::atom(b"udta", &[]);
+        let moov = testing::atom(b"moov", &testing::atom(b"trak", &udta));
+        let free = [&0u32.to_be_bytes()[..], b"free", &[0; 10]].concat();
+        let bytes = [&ftyp[..], &mdat, &moov, &free].concat();
+        let path = testing::write_file("read_children.mov", &bytes);
+        let mut file = File::open(&path).unwrap();
+
+        let atoms = read_children(&mut file, 0, bytes.len() as u64).unwrap();
+        let kinds: Vec<[u8; 4]> = atoms.iter().map(|atom| atom.kind).collect();
+        assert_eq!(kinds, [FTYP, *b"mdat", MOOV, FREE]);
+        assert_eq!(atoms[1].size_form, SizeForm::Large);
+        assert_eq!(atoms[1].size, mdat.len() as u64);
+        assert_eq!(atoms[1].body_offset(), atoms[1].offset + 16);
+        assert_eq!(atoms[3].size_form, SizeForm::ToEnd);
+        assert_eq!(atoms[3].size, free.len() as u64);
+
+        // Fixed-size fields are only read from a payload big enough to hold them.
+        assert_eq!(read_payload(&mut file, &atoms[0]).unwrap(), Some(*b"qt  "));
+        assert_eq!(read_payload::<21>(&mut file, &atoms[0]).unwrap(), None);
+
+        // Containers are descended into, but mdat isn't: the colr atom of the
+        // essence isn't an atom of the file.
+        let udta_offset = (ftyp.len() + mdat.len() + 16) as u64;
+        let path_kinds: Vec<[u8; 4]> = path_to(&mut file, udta_offset)
+            .unwrap()
+            .iter()
+            .map(|atom| atom.kind)
+            .collect();
+        assert_eq!(path_kinds, [MOOV, TRAK, UDTA]);
+        let colr_offset = atoms[1].body_offset();
+        assert_eq!(
+            path_to(&mut file, colr_offset).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        // A size smaller than the header, or overflowing the parent, is an error.
+        let too_small = [&4u32.to_be_bytes()[..], b"free"].concat();
+        let path = testing::write_file("invalid_size.mov", &too_small);
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(
+            read_children(&mut file, 0, 8).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        let path = testing::write_file("overflow.mov", &moov);
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(
+            read_children(&mut file, 0, moov.len() as u64 - 1)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
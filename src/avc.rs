@@ -0,0 +1,989 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+
+use crate::atom::{self, AtomHeader};
+use crate::hevc::{self, HevcConfiguration};
+use crate::{ConfigurationAtom, SampleDescription};
+
+/// The nal_unit_type of an H.264 sequence parameter set.
+const SPS_NAL_UNIT_TYPE: u8 = 7;
+
+/// The profile_idc of the profiles whose sequence parameter set carries the chroma
+/// format, the bit depths and the scaling matrices (High, High 10, High 4:2:2, High
+/// 4:4:4 and their intra, scalable and multiview variants).
+const HIGH_PROFILES: [u8; 13] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+/// The video_format written when a video signal type is added to a VUI: unspecified.
+const UNSPECIFIED_VIDEO_FORMAT: u8 = 5;
+
+/// The coding formats whose sequence parameter sets carry a colour description in
+/// their VUI.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Codec {
+    /// H.264, in 'avc1' to 'avc4' sample entries with an 'avcC' atom.
+    #[default]
+    Avc,
+    /// HEVC (H.265), in 'hvc1' and 'hev1' sample entries with an 'hvcC' atom.
+    Hevc,
+}
+
+impl Codec {
+    /// Returns whether the samples of a sample entry format may repeat the parameter
+    /// sets of its decoder configuration: 'avc3', 'avc4' and 'hev1'.
+    pub(crate) fn has_parameter_sets_in_samples(format: [u8; 4]) -> bool {
+        matches!(&format, b"avc3" | b"avc4" | b"hev1")
+    }
+
+    /// Returns the size of the NAL unit header.
+    pub(crate) fn nal_header_size(self) -> usize {
+        match self {
+            Codec::Avc => 1,
+            Codec::Hevc => 2,
+        }
+    }
+
+    /// Returns whether a NAL unit header is the one of a sequence parameter set.
+    pub(crate) fn is_sps(self, header: &[u8]) -> bool {
+        match self {
+            Codec::Avc => header
+                .first()
+                .is_some_and(|byte| byte & 0x1F == SPS_NAL_UNIT_TYPE),
+            Codec::Hevc => header
+                .first()
+                .is_some_and(|byte| byte >> 1 & 0x3F == hevc::SPS_NAL_UNIT_TYPE),
+        }
+    }
+
+    /// How many bits of a VUI follow its video signal type when it only has flags:
+    /// the chroma location, timing, HRD (NAL and VCL for H.264), picture structure and
+    /// bitstream restriction flags, plus the neutral chroma, field sequence and
+    /// default display window flags for HEVC.
+    fn vui_flags_after_video_signal_type(self) -> usize {
+        match self {
+            Codec::Avc => 6,
+            Codec::Hevc => 7,
+        }
+    }
+}
+
+/// Removes the emulation prevention bytes of a NAL unit, i.e. the 0x03 following two
+/// zero bytes, giving its raw byte sequence payload (RBSP).
+pub(crate) fn unescape(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+/// Inserts emulation prevention bytes into a raw byte sequence payload, so that it
+/// never contains a start code (two zero bytes followed by a byte up to 0x03).
+pub(crate) fn escape(rbsp: &[u8]) -> Vec<u8> {
+    let mut nal = Vec::with_capacity(rbsp.len() + rbsp.len() / 64);
+    let mut zeros = 0;
+    for &byte in rbsp {
+        if zeros >= 2 && byte <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        nal.push(byte);
+    }
+    nal
+}
+
+/// Reads the bits of a raw byte sequence payload, most significant first.
+pub(crate) struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, position: 0 }
+    }
+
+    pub(crate) fn bit(&mut self) -> Option<bool> {
+        let byte = self.bytes.get(self.position / 8)?;
+        let bit = byte >> (7 - self.position % 8) & 1;
+        self.position += 1;
+        Some(bit == 1)
+    }
+
+    /// Reads an unsigned integer of `count` bits, u(n) in the specification.
+    pub(crate) fn bits(&mut self, count: usize) -> Option<u32> {
+        (0..count).try_fold(0, |value, _| Some(value << 1 | self.bit()? as u32))
+    }
+
+    /// Reads an unsigned Exp-Golomb-coded integer, ue(v) in the specification.
+    pub(crate) fn ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while !self.bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        let value = (1u64 << leading_zeros) - 1 + self.bits(leading_zeros)? as u64;
+        u32::try_from(value).ok()
+    }
+
+    /// Reads a signed Exp-Golomb-coded integer, se(v) in the specification.
+    pub(crate) fn se(&mut self) -> Option<i32> {
+        let code = self.ue()? as i64;
+        let value = if code % 2 == 1 {
+            (code + 1) / 2
+        } else {
+            -code / 2
+        };
+        Some(value as i32)
+    }
+
+    /// Skips `count` bits.
+    pub(crate) fn skip(&mut self, count: usize) -> Option<()> {
+        self.position += count;
+        (self.position <= self.bytes.len() * 8).then_some(())
+    }
+
+    /// Skips a scaling_list of the given size, whose deltas stop as soon as the next
+    /// scale is 0.
+    fn skip_scaling_list(&mut self, size: usize) -> Option<()> {
+        let (mut last_scale, mut next_scale) = (8, 8);
+        for _ in 0..size {
+            if next_scale != 0 {
+                let delta_scale = self.se()?;
+                next_scale = (last_scale + delta_scale + 256) % 256;
+            }
+            if next_scale != 0 {
+                last_scale = next_scale;
+            }
+        }
+        Some(())
+    }
+}
+
+/// The video signal type of the VUI of a sequence parameter set: the colour
+/// description (the same code points as an 'nclx' colr atom) and the range.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct VideoSignalType {
+    pub(crate) video_format: u8,
+    pub(crate) full_range: bool,
+    /// The colour primaries, transfer characteristics and matrix coefficients, if
+    /// colour_description_present_flag is set.
+    pub(crate) colour_description: Option<[u8; 3]>,
+}
+
+impl VideoSignalType {
+    /// Returns the bits of the video signal type in a VUI, starting with
+    /// video_signal_type_present_flag.
+    fn to_bits(self) -> Vec<bool> {
+        let mut bits = vec![true];
+        push_bits(&mut bits, self.video_format as u32, 3);
+        bits.push(self.full_range);
+        bits.push(self.colour_description.is_some());
+        for code_point in self.colour_description.into_iter().flatten() {
+            push_bits(&mut bits, code_point as u32, 8);
+        }
+        bits
+    }
+}
+
+impl fmt::Display for VideoSignalType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.colour_description {
+            Some([primaries, transfer, matrix]) => write!(
+                f,
+                "primaries {}, transfer function {}, matrix {}",
+                primaries, transfer, matrix
+            )?,
+            None => write!(f, "no colour description")?,
+        }
+        let range = if self.full_range { "full" } else { "limited" };
+        write!(f, ", {} range", range)
+    }
+}
+
+/// What a sequence parameter set (SPS) says about colour, and where it says it: the
+/// positions are in bits from the start of its RBSP, after the NAL unit header.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SequenceParameterSet {
+    pub(crate) codec: Codec,
+    /// The profile_idc of H.264, the general_profile_idc of HEVC.
+    pub(crate) profile_idc: u8,
+    /// `None` if the SPS has no VUI or its VUI has no video signal type.
+    pub(crate) video_signal_type: Option<VideoSignalType>,
+    // The position of vui_parameters_present_flag.
+    vui_flag_position: usize,
+    // The position of video_signal_type_present_flag, if the SPS has a VUI.
+    video_signal_type_position: Option<usize>,
+    // The position of the rbsp_stop_one_bit ending the SPS.
+    stop_bit_position: usize,
+}
+
+impl SequenceParameterSet {
+    /// Parses a sequence parameter set NAL unit, NAL unit header included, up to the
+    /// video signal type of its VUI. Returns `None` if it isn't an SPS or is
+    /// truncated.
+    pub(crate) fn parse(codec: Codec, nal: &[u8]) -> Option<Self> {
+        if !codec.is_sps(nal) {
+            return None;
+        }
+        let rbsp = unescape(nal.get(codec.nal_header_size()..)?);
+        let mut reader = BitReader::new(&rbsp);
+
+        let profile_idc = match codec {
+            Codec::Avc => skip_to_vui(&mut reader)?,
+            Codec::Hevc => hevc::skip_to_vui(&mut reader)?,
+        };
+
+        // The VUI of both codecs starts the same way.
+        let vui_flag_position = reader.position;
+        let mut video_signal_type_position = None;
+        let mut video_signal_type = None;
+        if reader.bit()? {
+            if reader.bit()? && reader.bits(8)? == 255 {
+                reader.bits(32)?; // sar_width, sar_height
+            }
+            if reader.bit()? {
+                reader.bit()?; // overscan_appropriate_flag
+            }
+            video_signal_type_position = Some(reader.position);
+            if reader.bit()? {
+                let video_format = reader.bits(3)? as u8;
+                let full_range = reader.bit()?;
+                let colour_description = if reader.bit()? {
+                    Some([
+                        reader.bits(8)? as u8,
+                        reader.bits(8)? as u8,
+                        reader.bits(8)? as u8,
+                    ])
+                } else {
+                    None
+                };
+                video_signal_type = Some(VideoSignalType {
+                    video_format,
+                    full_range,
+                    colour_description,
+                });
+            }
+        }
+
+        // Everything read so far must come before the rbsp_stop_one_bit.
+        let stop_bit_position = stop_bit_position(&rbsp)?;
+        if reader.position > stop_bit_position {
+            return None;
+        }
+
+        Some(SequenceParameterSet {
+            codec,
+            profile_idc,
+            video_signal_type,
+            vui_flag_position,
+            video_signal_type_position,
+            stop_bit_position,
+        })
+    }
+}
+
+/// Reads the fields of an H.264 SPS up to vui_parameters_present_flag, and returns
+/// its profile_idc.
+fn skip_to_vui(reader: &mut BitReader) -> Option<u8> {
+    let profile_idc = reader.bits(8)? as u8;
+    reader.bits(16)?; // constraint_set flags, level_idc
+    reader.ue()?; // seq_parameter_set_id
+    if HIGH_PROFILES.contains(&profile_idc) {
+        let chroma_format_idc = reader.ue()?;
+        if chroma_format_idc == 3 {
+            reader.bit()?; // separate_colour_plane_flag
+        }
+        reader.ue()?; // bit_depth_luma_minus8
+        reader.ue()?; // bit_depth_chroma_minus8
+        reader.bit()?; // qpprime_y_zero_transform_bypass_flag
+        if reader.bit()? {
+            let scaling_lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for index in 0..scaling_lists {
+                if reader.bit()? {
+                    reader.skip_scaling_list(if index < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    reader.ue()?; // log2_max_frame_num_minus4
+    match reader.ue()? {
+        0 => {
+            reader.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            reader.bit()?; // delta_pic_order_always_zero_flag
+            reader.se()?; // offset_for_non_ref_pic
+            reader.se()?; // offset_for_top_to_bottom_field
+            let cycle = reader.ue()?;
+            if cycle > 255 {
+                return None;
+            }
+            for _ in 0..cycle {
+                reader.se()?; // offset_for_ref_frame
+            }
+        }
+        _ => {}
+    }
+    reader.ue()?; // max_num_ref_frames
+    reader.bit()?; // gaps_in_frame_num_value_allowed_flag
+    reader.ue()?; // pic_width_in_mbs_minus1
+    reader.ue()?; // pic_height_in_map_units_minus1
+    if !reader.bit()? {
+        reader.bit()?; // mb_adaptive_frame_field_flag
+    }
+    reader.bit()?; // direct_8x8_inference_flag
+    if reader.bit()? {
+        for _ in 0..4 {
+            reader.ue()?; // frame_crop offsets
+        }
+    }
+    Some(profile_idc)
+}
+
+/// Rewrites the video signal type of a sequence parameter set NAL unit: the colour
+/// description and the range flag are replaced by the given ones, or kept as they are
+/// if `None`. A missing video signal type, or a missing VUI, is added, in which case
+/// the SPS grows. The rest of the SPS is copied bit for bit, and the emulation
+/// prevention bytes are inserted again.
+///
+/// Returns `None` if the NAL unit can't be parsed (see
+/// [`SequenceParameterSet::parse`]).
+pub(crate) fn rewrite_sps(
+    codec: Codec,
+    nal: &[u8],
+    colour_description: Option<[u8; 3]>,
+    full_range: Option<bool>,
+) -> Option<Vec<u8>> {
+    let sps = SequenceParameterSet::parse(codec, nal)?;
+    let existing = sps.video_signal_type;
+    let video_signal_type = VideoSignalType {
+        video_format: existing.map_or(UNSPECIFIED_VIDEO_FORMAT, |e| e.video_format),
+        full_range: full_range
+            .or(existing.map(|e| e.full_range))
+            .unwrap_or(false),
+        colour_description: colour_description.or(existing.and_then(|e| e.colour_description)),
+    };
+
+    let (header, payload) = nal.split_at(codec.nal_header_size());
+    let mut bits = to_bits(&unescape(payload));
+    bits.truncate(sps.stop_bit_position);
+    match sps.video_signal_type_position {
+        Some(position) => {
+            let end = position + existing.map_or(1, |e| e.to_bits().len());
+            bits.splice(position..end, video_signal_type.to_bits());
+        }
+        None => {
+            // A VUI with nothing but the video signal type, followed by the fields of
+            // the SPS that come after the VUI in HEVC.
+            bits[sps.vui_flag_position] = true;
+            let mut vui = vec![false, false]; // aspect ratio and overscan info flags
+            vui.extend(video_signal_type.to_bits());
+            vui.extend(vec![false; codec.vui_flags_after_video_signal_type()]);
+            let position = sps.vui_flag_position + 1;
+            bits.splice(position..position, vui);
+        }
+    }
+
+    // rbsp_trailing_bits: the stop bit, then zero bits up to the end of the byte.
+    bits.push(true);
+    while !bits.len().is_multiple_of(8) {
+        bits.push(false);
+    }
+    let rbsp: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |value, &bit| value << 1 | bit as u8))
+        .collect();
+
+    let mut rewritten = header.to_vec();
+    rewritten.extend(escape(&rbsp));
+    Some(rewritten)
+}
+
+/// Returns the position, in bits, of the rbsp_stop_one_bit: the last bit set.
+fn stop_bit_position(rbsp: &[u8]) -> Option<usize> {
+    let index = rbsp.iter().rposition(|&byte| byte != 0)?;
+    Some(index * 8 + 7 - rbsp[index].trailing_zeros() as usize)
+}
+
+fn to_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |shift| byte >> shift & 1 == 1))
+        .collect()
+}
+
+fn push_bits(bits: &mut Vec<bool>, value: u32, count: usize) {
+    bits.extend((0..count).rev().map(|shift| value >> shift & 1 == 1));
+}
+
+/// The decoder configuration record of an 'avcC' or an 'hvcC' atom.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DecoderConfiguration {
+    Avc(AvcConfiguration),
+    Hevc(HevcConfiguration),
+}
+
+impl DecoderConfiguration {
+    /// Decodes the payload of the configuration atom of a codec. Returns `None` if it
+    /// is truncated.
+    pub(crate) fn from_bytes(codec: Codec, payload: &[u8]) -> Option<Self> {
+        match codec {
+            Codec::Avc => AvcConfiguration::from_bytes(payload).map(DecoderConfiguration::Avc),
+            Codec::Hevc => HevcConfiguration::from_bytes(payload).map(DecoderConfiguration::Hevc),
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            DecoderConfiguration::Avc(configuration) => configuration.to_bytes(),
+            DecoderConfiguration::Hevc(configuration) => configuration.to_bytes(),
+        }
+    }
+
+    /// Returns the size of the length preceding each NAL unit in the samples: 1, 2 or
+    /// 4 bytes.
+    pub(crate) fn nal_length_size(&self) -> usize {
+        let length_size_minus_one = match self {
+            DecoderConfiguration::Avc(configuration) => configuration.header[4],
+            DecoderConfiguration::Hevc(configuration) => configuration.header[21],
+        };
+        (length_size_minus_one & 0x03) as usize + 1
+    }
+
+    pub(crate) fn sequence_parameter_sets(&self) -> Vec<&Vec<u8>> {
+        match self {
+            DecoderConfiguration::Avc(configuration) => {
+                configuration.sequence_parameter_sets.iter().collect()
+            }
+            DecoderConfiguration::Hevc(configuration) => configuration.sequence_parameter_sets(),
+        }
+    }
+
+    pub(crate) fn sequence_parameter_sets_mut(&mut self) -> Vec<&mut Vec<u8>> {
+        match self {
+            DecoderConfiguration::Avc(configuration) => {
+                configuration.sequence_parameter_sets.iter_mut().collect()
+            }
+            DecoderConfiguration::Hevc(configuration) => {
+                configuration.sequence_parameter_sets_mut()
+            }
+        }
+    }
+}
+
+/// An AVC decoder configuration record, the payload of an 'avcC' atom: the sequence
+/// and picture parameter sets an H.264 decoder needs before the first sample.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub(crate) struct AvcConfiguration {
+    // configurationVersion, AVCProfileIndication, profile_compatibility,
+    // AVCLevelIndication, lengthSizeMinusOne and numOfSequenceParameterSets.
+    header: [u8; 6],
+    pub(crate) sequence_parameter_sets: Vec<Vec<u8>>,
+    picture_parameter_sets: Vec<Vec<u8>>,
+    // The chroma format, bit depths and SPS extensions of the high profiles, kept as
+    // they are.
+    extensions: Vec<u8>,
+}
+
+impl AvcConfiguration {
+    /// Decodes the payload of an 'avcC' atom. Returns `None` if it is truncated.
+    pub(crate) fn from_bytes(payload: &[u8]) -> Option<Self> {
+        let header: [u8; 6] = payload.get(..6)?.try_into().ok()?;
+        let mut rest = &payload[6..];
+        let sequence_parameter_sets = read_parameter_sets(&mut rest, (header[5] & 0x1F) as usize)?;
+        let (&count, mut rest) = rest.split_first()?;
+        let picture_parameter_sets = read_parameter_sets(&mut rest, count as usize)?;
+
+        Some(AvcConfiguration {
+            header,
+            sequence_parameter_sets,
+            picture_parameter_sets,
+            extensions: rest.to_vec(),
+        })
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = self.header.to_vec();
+        payload[5] = payload[5] & 0xE0 | self.sequence_parameter_sets.len() as u8;
+        write_parameter_sets(&mut payload, &self.sequence_parameter_sets);
+        payload.push(self.picture_parameter_sets.len() as u8);
+        write_parameter_sets(&mut payload, &self.picture_parameter_sets);
+        payload.extend_from_slice(&self.extensions);
+        payload
+    }
+}
+
+/// Reads `count` parameter sets, each preceded by its 16-bit length, from the start
+/// of `bytes` and advances it past them.
+pub(crate) fn read_parameter_sets(bytes: &mut &[u8], count: usize) -> Option<Vec<Vec<u8>>> {
+    let mut parameter_sets = Vec::with_capacity(count);
+    for _ in 0..count {
+        let length = u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]) as usize;
+        parameter_sets.push(bytes.get(2..2 + length)?.to_vec());
+        *bytes = &bytes[2 + length..];
+    }
+    Some(parameter_sets)
+}
+
+pub(crate) fn write_parameter_sets(payload: &mut Vec<u8>, parameter_sets: &[Vec<u8>]) {
+    for parameter_set in parameter_sets {
+        payload.extend_from_slice(&(parameter_set.len() as u16).to_be_bytes());
+        payload.extend_from_slice(parameter_set);
+    }
+}
+
+impl SampleDescription {
+    /// Constructs an avcC or hvcC atom (AVC or HEVC decoder configuration record) and
+    /// parses the video signal type of its sequence parameter sets. A configuration
+    /// atom that can't be decoded is ignored.
+    pub(crate) fn construct_configuration_atom(
+        &mut self,
+        file: &mut File,
+        header: &AtomHeader,
+        codec: Codec,
+    ) -> io::Result<()> {
+        let payload = atom::read_body(file, header)?;
+
+        if let Some(configuration) = DecoderConfiguration::from_bytes(codec, &payload) {
+            self.configuration_atom = ConfigurationAtom {
+                size: header.size as u32,
+                offset: header.offset,
+                codec,
+                nal_length_size: configuration.nal_length_size(),
+                sequence_parameter_sets: configuration
+                    .sequence_parameter_sets()
+                    .into_iter()
+                    .map(|nal| SequenceParameterSet::parse(codec, nal))
+                    .collect(),
+                matched: true,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_table::SampleTable;
+    use crate::testing;
+    use crate::timing::TrakTiming;
+    use crate::{atom, ColrPolicy, Error, Selection};
+    use std::io::{self, Read, Seek};
+
+    #[test]
+    fn test_emulation_prevention() {
+        let rbsp = [0x64, 0, 0, 0, 1, 0, 0, 3, 0, 0, 0x10];
+        let nal = escape(&rbsp);
+        assert_eq!(nal, [0x64, 0, 0, 3, 0, 1, 0, 0, 3, 3, 0, 0, 0x10]);
+        assert_eq!(unescape(&nal), rbsp);
+    }
+
+    #[test]
+    fn test_exp_golomb() {
+        // 1, 010, 011, 00100, 00111: 0, 1, 2, 3, 6.
+        let bytes = [0b1010_0110, 0b0100_0011, 0b1000_0000];
+        let mut reader = BitReader::new(&bytes);
+        let values: Vec<u32> = (0..5).map(|_| reader.ue().unwrap()).collect();
+        assert_eq!(values, [0, 1, 2, 3, 6]);
+        let mut reader = BitReader::new(&bytes);
+        let values: Vec<i32> = (0..5).map(|_| reader.se().unwrap()).collect();
+        assert_eq!(values, [0, 1, -1, 2, -3]);
+    }
+
+    #[test]
+    fn test_rewrite_sps() {
+        let bt709 = Some(VideoSignalType {
+            video_format: 5,
+            full_range: false,
+            colour_description: Some([1, 1, 1]),
+        });
+        // The timing info following the video signal type needs emulation
+        // prevention bytes.
+        let sps = testing::h264_sps(bt709, true);
+        assert!(sps.windows(3).any(|bytes| bytes == [0, 0, 3]));
+        let parsed = SequenceParameterSet::parse(Codec::Avc, &sps).unwrap();
+        assert_eq!(parsed.profile_idc, 100);
+        assert_eq!(parsed.video_signal_type, bt709);
+
+        // Same length: only the code points and the flag change.
+        let rewritten = rewrite_sps(Codec::Avc, &sps, Some([9, 16, 9]), Some(true)).unwrap();
+        assert_eq!(rewritten.len(), sps.len());
+        let bt2100_pq = Some(VideoSignalType {
+            video_format: 5,
+            full_range: true,
+            colour_description: Some([9, 16, 9]),
+        });
+        assert_eq!(rewritten, testing::h264_sps(bt2100_pq, true));
+        assert_eq!(
+            rewrite_sps(Codec::Avc, &rewritten, Some([1, 1, 1]), Some(false)).unwrap(),
+            sps
+        );
+
+        // Without a colour description, or a video signal type, or a VUI, the SPS
+        // grows.
+        let range_only = Some(VideoSignalType {
+            video_format: 5,
+            full_range: false,
+            colour_description: None,
+        });
+        for (video_signal_type, vui_extras) in [(range_only, true), (None, true), (None, false)] {
+            let sps = testing::h264_sps(video_signal_type, vui_extras);
+            let rewritten = rewrite_sps(Codec::Avc, &sps, Some([9, 16, 9]), Some(true)).unwrap();
+            assert!(rewritten.len() > sps.len());
+            assert_eq!(rewritten, testing::h264_sps(bt2100_pq, vui_extras));
+        }
+
+        // Setting only the range keeps the colour description.
+        let rewritten = rewrite_sps(Codec::Avc, &sps, None, Some(true)).unwrap();
+        let parsed = SequenceParameterSet::parse(Codec::Avc, &rewritten).unwrap();
+        assert_eq!(
+            parsed.video_signal_type.unwrap().colour_description,
+            Some([1, 1, 1])
+        );
+        assert!(parsed.video_signal_type.unwrap().full_range);
+
+        // An SPS written by x264, with a sample aspect ratio and timing info but no
+        // video signal type.
+        let x264 = [
+            0x67, 0x64, 0x00, 0x28, 0xAC, 0xD9, 0x40, 0x78, 0x02, 0x27, 0xE5, 0xC0, 0x44, 0x00,
+            0x00, 0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xC8, 0x3C, 0x60, 0xC6, 0x58,
+        ];
+        let parsed = SequenceParameterSet::parse(Codec::Avc, &x264).unwrap();
+        assert_eq!(parsed.video_signal_type, None);
+        let rewritten = rewrite_sps(Codec::Avc, &x264, Some([1, 1, 1]), None).unwrap();
+        assert_eq!(
+            SequenceParameterSet::parse(Codec::Avc, &rewritten)
+                .unwrap()
+                .video_signal_type,
+            bt709
+        );
+        assert!(rewritten.len() > x264.len());
+
+        assert_eq!(SequenceParameterSet::parse(Codec::Avc, &sps[..6]), None);
+        assert_eq!(
+            SequenceParameterSet::parse(Codec::Avc, &[0x68, 0xEE, 0x3C, 0x80]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_avc_configuration() {
+        let sps = testing::h264_sps(None, false);
+        let payload = testing::avcc_payload(&sps);
+        let configuration = AvcConfiguration::from_bytes(&payload).unwrap();
+        assert_eq!(configuration.sequence_parameter_sets, [sps]);
+        assert_eq!(configuration.to_bytes(), payload);
+        assert_eq!(
+            AvcConfiguration::from_bytes(&payload[..payload.len() - 6]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_avc_colour_description() {
+        // Two H.264 tracks, whose sequence parameter sets have no video signal type and
+        // a BT.709 one.
+        let bt709 = VideoSignalType {
+            video_format: 5,
+            full_range: false,
+            colour_description: Some([1, 1, 1]),
+        };
+        let h264_track = |video_signal_type| testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(
+                b"avc1",
+                &[
+                    testing::atom(
+                        b"avcC",
+                        &testing::avcc_payload(&testing::h264_sps(video_signal_type, true)),
+                    ),
+                    testing::nclx_colr_atom(1, 1, 1, false),
+                ],
+            )]
+            .to_vec(),
+            samples: [vec![0; 400]].to_vec(),
+            ..Default::default()
+        };
+        let tracks = [h264_track(None), h264_track(Some(bt709))];
+        let layout = testing::Layout {
+            moov_first: true,
+            mp4: true,
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) = testing::open_mov("avc.mp4", &tracks, layout);
+        let info = video.info(ColrPolicy::default());
+        assert!(info.contains("    SPS 1: no video signal type\n"));
+        assert!(
+            info.contains("    SPS 1: primaries 1, transfer function 1, matrix 1, limited range\n")
+        );
+
+        // The first SPS gets a video signal type without colour description.
+        video
+            .set_full_range_flag(&mut file, Selection::default(), true)
+            .unwrap();
+        let info = video.info(ColrPolicy::default());
+        assert!(info.contains("    SPS 1: no colour description, full range\n"));
+        assert!(
+            info.contains("    SPS 1: primaries 1, transfer function 1, matrix 1, full range\n")
+        );
+
+        // Then both get the same colour description as their colr atom.
+        video
+            .encode(&mut file, Selection::default(), 9, 16, 9, -1.0)
+            .unwrap();
+        testing::redecode(&file_path, &video);
+        let bt2100_pq = VideoSignalType {
+            video_format: 5,
+            full_range: true,
+            colour_description: Some([9, 16, 9]),
+        };
+        let expected = testing::atom(
+            b"avcC",
+            &testing::avcc_payload(&testing::h264_sps(Some(bt2100_pq), true)),
+        );
+        for track in video.tracks.iter() {
+            let description = &track.sample_descriptions[0];
+            let sps = description.configuration_atom.sequence_parameter_sets[0].unwrap();
+            assert_eq!(sps.video_signal_type, Some(bt2100_pq));
+            let colr_atom = &description.colr_atoms[0];
+            assert_eq!(
+                (
+                    colr_atom.primary_index,
+                    colr_atom.transfer_function_index,
+                    colr_atom.matrix_index,
+                    colr_atom.full_range_flag
+                ),
+                (9, 16, 9, true)
+            );
+
+            let mut avcc_atom = vec![0; description.configuration_atom.size as usize];
+            file.seek(io::SeekFrom::Start(description.configuration_atom.offset))
+                .unwrap();
+            file.read_exact(&mut avcc_atom).unwrap();
+            assert_eq!(avcc_atom, expected);
+        }
+
+        // Nothing is written if an SPS can't be parsed.
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(
+                b"avc1",
+                &[
+                    testing::atom(b"avcC", &testing::avcc_payload(&[0x67, 0x64, 0, 0x28])),
+                    testing::nclx_colr_atom(1, 1, 1, false),
+                ],
+            )]
+            .to_vec(),
+            samples: [vec![0; 400]].to_vec(),
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) =
+            testing::open_mov("avc_truncated.mp4", &[track], layout);
+        let original = std::fs::read(&file_path).unwrap();
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("    SPS 1: not understood\n"));
+        let result = video.encode(&mut file, Selection::default(), 9, 16, 9, -1.0);
+        assert!(matches!(
+            result,
+            Err(Error::UnsupportedSequenceParameterSet { track_id: 1 })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), original);
+    }
+
+    #[test]
+    fn test_in_band_sps() {
+        // An 'avc3' track whose first and third samples start with an SPS, without a
+        // video signal type and with a BT.709 one, and an 'hev1' track whose first
+        // sample starts with an SPS without VUI. The avc3 samples come first in mdat,
+        // so the hev1 ones move when the SPSs grow.
+        let bt709 = VideoSignalType {
+            video_format: 5,
+            full_range: false,
+            colour_description: Some([1, 1, 1]),
+        };
+        let aud = vec![0x09, 0xF0];
+        let pps = vec![0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0];
+        let idr = [&[0x65, 0x88, 0x84][..], &[0x5A; 300]].concat();
+        let slice = [&[0x41, 0x9A, 0x02][..], &[0xA5; 120]].concat();
+        let h264_samples = |first: Vec<u8>, third: Vec<u8>| {
+            [
+                testing::length_prefixed(&[aud.clone(), first, pps.clone(), idr.clone()]),
+                testing::length_prefixed(&[aud.clone(), slice.clone()]),
+                testing::length_prefixed(&[third, pps.clone(), idr.clone()]),
+            ]
+            .to_vec()
+        };
+        let hevc_idr = [&[0x26, 0x01, 0xAF][..], &[0x3C; 200]].concat();
+        let hevc_slice = [&[0x02, 0x01, 0xD0][..], &[0xC3; 80]].concat();
+        let hevc_samples = |sps: Vec<u8>| {
+            [
+                testing::length_prefixed(&[sps, hevc_idr.clone()]),
+                testing::length_prefixed(std::slice::from_ref(&hevc_slice)),
+            ]
+            .to_vec()
+        };
+        let tracks = [
+            testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(
+                    b"avc3",
+                    &[
+                        testing::atom(
+                            b"avcC",
+                            &testing::avcc_payload(&testing::h264_sps(Some(bt709), false)),
+                        ),
+                        testing::nclx_colr_atom(1, 1, 1, false),
+                    ],
+                )]
+                .to_vec(),
+                samples: h264_samples(
+                    testing::h264_sps(None, true),
+                    testing::h264_sps(Some(bt709), true),
+                ),
+                ..Default::default()
+            },
+            testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(
+                    b"hev1",
+                    &[
+                        testing::atom(
+                            b"hvcC",
+                            &testing::hvcc_payload(&testing::hevc_sps(None, false)),
+                        ),
+                        testing::nclx_colr_atom(1, 1, 1, false),
+                    ],
+                )]
+                .to_vec(),
+                samples: hevc_samples(testing::hevc_sps(None, false)),
+                ..Default::default()
+            },
+        ];
+
+        let bt2100_pq = VideoSignalType {
+            video_format: 5,
+            full_range: false,
+            colour_description: Some([9, 16, 9]),
+        };
+        let expected_samples = [
+            h264_samples(
+                testing::h264_sps(Some(bt2100_pq), true),
+                testing::h264_sps(Some(bt2100_pq), true),
+            ),
+            hevc_samples(testing::hevc_sps(Some(bt2100_pq), false)),
+        ];
+
+        for moov_first in [true, false] {
+            let layout = testing::Layout {
+                moov_first,
+                mp4: true,
+                ..Default::default()
+            };
+            let (file_path, mut file, mut video) =
+                testing::open_mov("in_band_sps.mp4", &tracks, layout);
+            assert_eq!(video.tracks[0].in_band_sps.len(), 2);
+            assert_eq!(video.tracks[0].in_band_sps[1].sample_index, 2);
+            assert_eq!(video.tracks[1].in_band_sps.len(), 1);
+            let info = video.info(ColrPolicy::default());
+            assert!(info.contains("    SPS in 1 sample: no video signal type\n"));
+            assert!(info.contains(
+                "    SPS in 1 sample: primaries 1, transfer function 1, matrix 1, limited range\n"
+            ));
+
+            video
+                .encode(&mut file, Selection::default(), 9, 16, 9, -1.0)
+                .unwrap();
+            testing::redecode(&file_path, &video);
+            assert!(video
+                .info(ColrPolicy::default())
+                .contains("    SPS in 2 samples: primaries 9, transfer function 16, matrix 9, limited range\n"));
+
+            // Every sample, located through the new sample tables, holds the rewritten
+            // SPS and the rest of its NAL units untouched.
+            for (track_index, expected) in expected_samples.iter().enumerate() {
+                let trak = atom::traks(&mut file).unwrap()[track_index];
+                let timing = TrakTiming::read(&mut file, &trak).unwrap().unwrap();
+                let samples = SampleTable::read(&mut file, &timing.stbl_children)
+                    .unwrap()
+                    .samples();
+                assert_eq!(samples.len(), expected.len());
+                for (sample, expected) in samples.iter().zip(expected) {
+                    let mut bytes = vec![0; sample.size as usize];
+                    file.seek(io::SeekFrom::Start(sample.offset)).unwrap();
+                    file.read_exact(&mut bytes).unwrap();
+                    assert_eq!(&bytes, expected);
+                }
+            }
+        }
+
+        // Nothing is written if a longer SPS would push a chunk offset past 4 GB: the
+        // last sample of the hev1 track starts right below, after a hole in mdat.
+        let layout = testing::Layout {
+            moov_first: true,
+            mp4: true,
+            mdat_to_end: true,
+            ..Default::default()
+        };
+        let last_chunk_offset = |file: &mut File| {
+            let trak = atom::traks(file).unwrap()[1];
+            let timing = TrakTiming::read(file, &trak).unwrap().unwrap();
+            let sample_table = SampleTable::read(file, &timing.stbl_children).unwrap();
+            *sample_table.chunk_offsets.last().unwrap()
+        };
+        let (_, mut file, _) = testing::open_mov("in_band_sps_near_4_gb.mp4", &tracks, layout);
+        let mdat_gap = u32::MAX as u64 - 1 - last_chunk_offset(&mut file);
+        let layout = testing::Layout { mdat_gap, ..layout };
+        let (_file_path, mut file, mut video) =
+            testing::open_mov("in_band_sps_near_4_gb.mp4", &tracks, layout);
+        assert_eq!(last_chunk_offset(&mut file), u32::MAX as u64 - 1);
+        // The file is mostly a hole: compare its start, holding moov, and its end.
+        let ends = |file: &mut File| {
+            let file_size = file.metadata().unwrap().len();
+            let mut bytes = vec![0; 2 << 16];
+            file.seek(io::SeekFrom::Start(0)).unwrap();
+            file.read_exact(&mut bytes[..1 << 16]).unwrap();
+            file.seek(io::SeekFrom::Start(file_size - (1 << 16)))
+                .unwrap();
+            file.read_exact(&mut bytes[1 << 16..]).unwrap();
+            (file_size, bytes)
+        };
+        let original = ends(&mut file);
+        let result = video.encode(&mut file, Selection::default(), 9, 16, 9, -1.0);
+        assert!(matches!(result, Err(Error::Io(_))));
+        assert!(ends(&mut file) == original);
+
+        // Nothing is written if an SPS of the samples can't be parsed.
+        let mut tracks = tracks;
+        tracks[0].samples[2] = testing::length_prefixed(&[vec![0x67, 0x64, 0, 0x28]]);
+        let layout = testing::Layout {
+            moov_first: true,
+            mp4: true,
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) =
+            testing::open_mov("in_band_sps_truncated.mp4", &tracks, layout);
+        let original = std::fs::read(&file_path).unwrap();
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("    SPS in 1 sample: not understood\n"));
+        let result = video.set_full_range_flag(&mut file, Selection::default(), true);
+        assert!(matches!(
+            result,
+            Err(Error::UnsupportedSequenceParameterSet { track_id: 1 })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), original);
+    }
+}
@@ -0,0 +1,580 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+
+use crate::atom::{self, AtomHeader, SizeForm, ATOM_HEADER_SIZE};
+
+/// How many bytes are moved at once when shifting the end of a file.
+const SHIFT_BUFFER_SIZE: u64 = 1 << 20;
+
+/// A range of bytes to replace, as its offset, its length and the bytes put in its
+/// place.
+pub(crate) type Replacement = (u64, u64, Vec<u8>);
+
+/// How a splice moved the bytes of the file: those between `start` (included) and
+/// `end` (excluded) moved by `delta` bytes, the others stayed where they were.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Shift {
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) delta: i64,
+}
+
+impl Shift {
+    /// Returns where the byte that was at `offset` is now.
+    pub(crate) fn apply(&self, offset: u64) -> u64 {
+        if (self.start..self.end).contains(&offset) {
+            offset.saturating_add_signed(self.delta)
+        } else {
+            offset
+        }
+    }
+}
+
+/// A change of length one of several splices is to make, to check them all at once
+/// before any is made (see [`check_growths`]): `delta` bytes at `tail_start`, the end
+/// of the replaced range, inside `ancestors`.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub(crate) struct Growth {
+    pub(crate) ancestors: Vec<AtomHeader>,
+    pub(crate) tail_start: u64,
+    pub(crate) delta: i64,
+    /// Whether a padding atom may absorb the change, as [`splice`] looks for one while
+    /// [`splice_many`] doesn't.
+    pub(crate) padded: bool,
+}
+
+impl Growth {
+    /// The change of length of the splice replacing the `remove_len` bytes located at
+    /// `at` with `len` bytes.
+    pub(crate) fn new(
+        ancestors: Vec<AtomHeader>,
+        at: u64,
+        remove_len: u64,
+        len: usize,
+        padded: bool,
+    ) -> Self {
+        Self {
+            ancestors,
+            tail_start: at + remove_len,
+            delta: len as i64 - remove_len as i64,
+            padded,
+        }
+    }
+
+    /// The change of length assumed when checking: a padding atom may or may not be
+    /// left to absorb it once the splices before it are made, so a growth is assumed
+    /// to shift the rest of the file and a shrink to move nothing.
+    fn worst_delta(&self) -> i64 {
+        if self.padded {
+            self.delta.max(0)
+        } else {
+            self.delta
+        }
+    }
+}
+
+/// Checks that the splices making the `growths`, one after the other and each located
+/// in the file as it is before the first one, leave every size and chunk offset within
+/// its field. Nothing is written.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file can't be read, or if a size or a chunk offset
+/// wouldn't fit in its 32-bit field anymore.
+pub(crate) fn check_growths(file: &mut File, growths: &[Growth]) -> io::Result<()> {
+    let mut resized: Vec<(AtomHeader, i64)> = Vec::new();
+    for growth in growths.iter() {
+        for ancestor in growth.ancestors.iter() {
+            match resized
+                .iter_mut()
+                .find(|(atom, _)| atom.offset == ancestor.offset)
+            {
+                Some((_, delta)) => *delta += growth.worst_delta(),
+                None => resized.push((*ancestor, growth.worst_delta())),
+            }
+        }
+    }
+    for (ancestor, delta) in resized {
+        let size = add(ancestor.size, delta)?;
+        if ancestor.size_form == SizeForm::Compact {
+            to_u32(size)?;
+        }
+    }
+    relocated_chunk_offset_tables(file, |offset| {
+        growths
+            .iter()
+            .filter(|growth| growth.tail_start <= offset)
+            .map(Growth::worst_delta)
+            .sum()
+    })?;
+
+    Ok(())
+}
+
+/// Replaces the `remove_len` bytes located at `at` with `bytes`, keeping the file a
+/// valid QuickTime file.
+///
+/// `ancestors` are the atoms containing the replaced range, from the top-level atom
+/// down to its direct parent (see [`atom::path_to`]). When one of them, or the
+/// top-level atom right after them, is a `free` or `skip` atom located after the
+/// replaced range and big enough, the difference in length is absorbed by it: only
+/// the bytes between the replaced range and that padding are moved, and only the
+/// ancestors nested deeper than the padding change size. The essence never moves in
+/// that case.
+///
+/// Otherwise, everything after the replaced range up to the end of the file is
+/// shifted, the size of every ancestor is grown or shrunk, and the chunk offsets
+/// pointing after the replaced range are fixed up, which matters when moov sits
+/// before mdat.
+///
+/// Inserting is done with a `remove_len` of 0, removing with an empty `bytes`.
+/// Returns how the bytes after the replaced range moved.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file can't be read or written, or if a size or a
+/// chunk offset doesn't fit in its 32-bit field anymore (`co64` tables and largesize
+/// atoms have 64-bit fields). Nothing is written in the latter case.
+pub(crate) fn splice(
+    file: &mut File,
+    ancestors: &[AtomHeader],
+    at: u64,
+    remove_len: u64,
+    bytes: &[u8],
+) -> io::Result<Shift> {
+    let delta = bytes.len() as i64 - remove_len as i64;
+    let file_size = file.metadata()?.len();
+    let tail_start = at + remove_len;
+
+    let padding = plan(file, ancestors, tail_start, delta)?;
+
+    let (tail_end, resized_ancestors) = match padding {
+        Some((free, depth)) => (free.offset, &ancestors[depth..]),
+        None => (file_size, ancestors),
+    };
+
+    shift(file, tail_start, tail_end, delta)?;
+    file.seek(io::SeekFrom::Start(at))?;
+    file.write_all(bytes)?;
+
+    match padding {
+        Some((free, _)) => {
+            // An atom that shrinks to nothing simply disappears.
+            let free_size = add(free.size, -delta)?;
+            if free_size > 0 {
+                write_u32(file, add(free.offset, delta)?, free_size)?;
+                file.write_all(&free.kind)?;
+            }
+        }
+        None if delta < 0 => file.set_len(file_size - delta.unsigned_abs())?,
+        None => {}
+    }
+
+    // The ancestors all start before `at`, so their headers haven't moved.
+    for ancestor in resized_ancestors.iter() {
+        write_size(file, ancestor, add(ancestor.size, delta)?)?;
+    }
+
+    let shift = Shift {
+        start: tail_start,
+        end: tail_end,
+        delta,
+    };
+    fix_chunk_offsets(file, shift)?;
+
+    Ok(shift)
+}
+
+/// Finds where a change of `delta` bytes at `tail_start` is absorbed (see
+/// [`find_padding`]), and checks that the sizes and the chunk offsets it changes still
+/// fit their fields.
+fn plan(
+    file: &mut File,
+    ancestors: &[AtomHeader],
+    tail_start: u64,
+    delta: i64,
+) -> io::Result<Option<(AtomHeader, usize)>> {
+    if delta == 0 {
+        return Ok(None);
+    }
+
+    let padding = find_padding(file, ancestors, tail_start, delta)?;
+    let (tail_end, resized_ancestors) = match padding {
+        Some((free, depth)) => {
+            to_u32(add(free.size, -delta)?)?;
+            (free.offset, &ancestors[depth..])
+        }
+        None => (file.metadata()?.len(), ancestors),
+    };
+    for ancestor in resized_ancestors.iter() {
+        let size = add(ancestor.size, delta)?;
+        if ancestor.size_form == SizeForm::Compact {
+            to_u32(size)?;
+        }
+    }
+    relocated_chunk_offset_tables(file, |offset| {
+        if (tail_start..tail_end).contains(&offset) {
+            delta
+        } else {
+            0
+        }
+    })?;
+
+    Ok(padding)
+}
+
+/// Replaces several ranges of bytes at once, each given as the offset and the length
+/// of the removed bytes and the bytes put in their place, in ascending order and
+/// without overlap. Meant for the essence, where a change of length in many samples
+/// would otherwise shift the rest of the file once per sample.
+///
+/// `ancestors` are the atoms containing all the ranges, typically the mdat atom
+/// alone. Everything after the first range is shifted in a single pass, by the sum
+/// of the changes of length before it, the sizes of the ancestors are changed by the
+/// total, and each chunk offset is moved like the byte it points to. No padding atom
+/// is looked for.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the file can't be read or written, or if a size or a
+/// chunk offset doesn't fit in its 32-bit field anymore. Nothing is written in the
+/// latter case.
+pub(crate) fn splice_many(
+    file: &mut File,
+    ancestors: &[AtomHeader],
+    replacements: &[Replacement],
+) -> io::Result<()> {
+    let growths: Vec<Growth> = replacements
+        .iter()
+        .map(|(at, remove_len, bytes)| {
+            Growth::new(ancestors.to_vec(), *at, *remove_len, bytes.len(), false)
+        })
+        .collect();
+    check_growths(file, &growths)?;
+
+    let file_size = file.metadata()?.len();
+
+    // The bytes between the end of a replaced range and the start of the next one (or
+    // the end of the file) move by the sum of the changes up to that range.
+    let mut segments = Vec::with_capacity(replacements.len());
+    let mut total_delta = 0i64;
+    for (index, (at, remove_len, bytes)) in replacements.iter().enumerate() {
+        total_delta += bytes.len() as i64 - *remove_len as i64;
+        let end = replacements
+            .get(index + 1)
+            .map_or(file_size, |(next_at, _, _)| *next_at);
+        segments.push((at + remove_len, end, total_delta));
+    }
+
+    // A segment moved towards the end of the file must not overwrite the segments after
+    // it before they are moved, and the other way around.
+    for &(start, end, delta) in segments.iter().filter(|segment| segment.2 < 0) {
+        shift(file, start, end, delta)?;
+    }
+    for &(start, end, delta) in segments.iter().rev().filter(|segment| segment.2 > 0) {
+        shift(file, start, end, delta)?;
+    }
+
+    let mut delta_before = 0i64;
+    for ((at, _, bytes), (_, _, delta)) in replacements.iter().zip(segments.iter()) {
+        file.seek(io::SeekFrom::Start(add(*at, delta_before)?))?;
+        file.write_all(bytes)?;
+        delta_before = *delta;
+    }
+    if total_delta < 0 {
+        file.set_len(file_size - total_delta.unsigned_abs())?;
+    }
+
+    for ancestor in ancestors.iter() {
+        write_size(file, ancestor, add(ancestor.size, total_delta)?)?;
+    }
+
+    relocate_chunk_offsets(file, |offset| {
+        segments
+            .iter()
+            .rev()
+            .find(|(start, _, _)| *start <= offset)
+            .map_or(0, |(_, _, delta)| *delta)
+    })
+}
+
+/// Looks for a `free` or `skip` atom after `from` that can absorb a change of `delta`
+/// bytes: either inside one of `ancestors`, the deepest one first, or right after the
+/// top-level ancestor (typically moov).
+///
+/// Returns the padding atom and how many ancestors contain it, i.e. the index of the
+/// first ancestor that has to be resized.
+fn find_padding(
+    file: &mut File,
+    ancestors: &[AtomHeader],
+    from: u64,
+    delta: i64,
+) -> io::Result<Option<(AtomHeader, usize)>> {
+    // A padding atom can shrink down to its header, or vanish completely.
+    let fits = |free: &AtomHeader| {
+        delta < 0 || free.size == delta as u64 || free.size >= delta as u64 + ATOM_HEADER_SIZE
+    };
+    let is_padding = |a: &AtomHeader| a.kind == atom::FREE || a.kind == atom::SKIP;
+
+    for depth in (0..ancestors.len()).rev() {
+        let parent_kind = depth.checked_sub(1).map(|i| ancestors[i].kind);
+        let Some(children) = atom::read_children_in_path(file, &ancestors[depth], parent_kind)?
+        else {
+            continue;
+        };
+        if let Some(free) = children
+            .iter()
+            .find(|a| is_padding(a) && a.offset >= from && fits(a))
+        {
+            return Ok(Some((*free, depth + 1)));
+        }
+    }
+
+    if let Some(top_level) = ancestors.first() {
+        let file_size = file.metadata()?.len();
+        if top_level.end() + ATOM_HEADER_SIZE <= file_size {
+            let next = AtomHeader::read(file, top_level.end(), file_size)?;
+            if is_padding(&next) && fits(&next) {
+                return Ok(Some((next, 0)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Moves the bytes between `start` and `end` by `delta` bytes, towards the end of the
+/// file if `delta` is positive and towards its beginning otherwise.
+fn shift(file: &mut File, start: u64, end: u64, delta: i64) -> io::Result<()> {
+    let mut buf = vec![0; SHIFT_BUFFER_SIZE as usize];
+
+    if delta > 0 {
+        // Copy backwards so that no byte is overwritten before being moved.
+        let mut pos = end;
+        while pos > start {
+            let len = SHIFT_BUFFER_SIZE.min(pos - start);
+            pos -= len;
+            copy(file, &mut buf[..len as usize], pos, pos + delta as u64)?;
+        }
+    } else if delta < 0 {
+        let mut pos = start;
+        while pos < end {
+            let len = SHIFT_BUFFER_SIZE.min(end - pos);
+            copy(
+                file,
+                &mut buf[..len as usize],
+                pos,
+                pos - delta.unsigned_abs(),
+            )?;
+            pos += len;
+        }
+    }
+
+    Ok(())
+}
+
+fn copy(file: &mut File, buf: &mut [u8], from: u64, to: u64) -> io::Result<()> {
+    file.seek(io::SeekFrom::Start(from))?;
+    file.read_exact(buf)?;
+    file.seek(io::SeekFrom::Start(to))?;
+    file.write_all(buf)
+}
+
+/// Moves every chunk offset pointing into the shifted bytes, in every `stco` and
+/// `co64` table of the file.
+fn fix_chunk_offsets(file: &mut File, shift: Shift) -> io::Result<()> {
+    if shift.delta == 0 {
+        return Ok(());
+    }
+
+    relocate_chunk_offsets(file, |offset| {
+        if (shift.start..shift.end).contains(&offset) {
+            shift.delta
+        } else {
+            0
+        }
+    })
+}
+
+/// Adds to every chunk offset, in every `stco` and `co64` table of the file, the
+/// number of bytes `delta_of` says the byte it points to has moved.
+fn relocate_chunk_offsets(file: &mut File, delta_of: impl Fn(u64) -> i64) -> io::Result<()> {
+    for (table, body) in relocated_chunk_offset_tables(file, delta_of)? {
+        file.seek(io::SeekFrom::Start(table.body_offset()))?;
+        file.write_all(&body)?;
+    }
+
+    Ok(())
+}
+
+/// Returns every `stco` and `co64` table of the file along with its new body, where
+/// each chunk offset is moved by the number of bytes `delta_of` gives for it.
+fn relocated_chunk_offset_tables(
+    file: &mut File,
+    delta_of: impl Fn(u64) -> i64,
+) -> io::Result<Vec<(AtomHeader, Vec<u8>)>> {
+    let mut tables = Vec::new();
+    for table in atom::chunk_offset_tables(file)? {
+        let mut body = atom::read_body(file, &table)?;
+
+        let entry_size = if table.kind == atom::CO64 { 8 } else { 4 };
+        let count = u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize;
+        let entries = body
+            .get_mut(8..8 + count * entry_size)
+            .ok_or_else(|| invalid_data("chunk offset table is truncated".to_string()))?;
+
+        for entry in entries.chunks_exact_mut(entry_size) {
+            if entry_size == 8 {
+                let offset = u64::from_be_bytes(entry.try_into().unwrap());
+                let delta = delta_of(offset);
+                if delta != 0 {
+                    entry.copy_from_slice(&add(offset, delta)?.to_be_bytes());
+                }
+            } else {
+                let offset = u32::from_be_bytes(entry.try_into().unwrap()) as u64;
+                let delta = delta_of(offset);
+                if delta != 0 {
+                    entry.copy_from_slice(&to_u32(add(offset, delta)?)?.to_be_bytes());
+                }
+            }
+        }
+
+        tables.push((table, body));
+    }
+
+    Ok(tables)
+}
+
+/// Writes the new size of an atom in its header, in the same form as before: a
+/// largesize stays a largesize, and an atom extending to the end of the file keeps a
+/// size of 0.
+fn write_size(file: &mut File, atom: &AtomHeader, size: u64) -> io::Result<()> {
+    match atom.size_form {
+        SizeForm::Compact => write_u32(file, atom.offset, size),
+        SizeForm::Large => {
+            file.seek(io::SeekFrom::Start(atom.offset + ATOM_HEADER_SIZE))?;
+            file.write_all(&size.to_be_bytes())
+        }
+        SizeForm::ToEnd => Ok(()),
+    }
+}
+
+fn write_u32(file: &mut File, offset: u64, value: u64) -> io::Result<()> {
+    file.seek(io::SeekFrom::Start(offset))?;
+    file.write_all(&to_u32(value)?.to_be_bytes())
+}
+
+fn add(value: u64, delta: i64) -> io::Result<u64> {
+    value
+        .checked_add_signed(delta)
+        .ok_or_else(|| invalid_data(format!("cannot add {} to {}", delta, value)))
+}
+
+fn to_u32(value: u64) -> io::Result<u32> {
+    u32::try_from(value)
+        .map_err(|_| invalid_data(format!("{} doesn't fit in a 32-bit field", value)))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{testing, Selection};
+
+    #[test]
+    fn test_structural_edits_use_free_atom() {
+        for free in [testing::Free::InsideMoov(64), testing::Free::AfterMoov(64)] {
+            let track = testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(b"apcn", &[])].to_vec(),
+                samples: [testing::prores_frame(400, [1, 1, 1])].to_vec(),
+                ..Default::default()
+            };
+            let layout = testing::Layout {
+                moov_first: true,
+                free,
+                ..Default::default()
+            };
+            let (file_path, mut file, mut video) = testing::open_mov("free.mov", &[track], layout);
+            let original_size = std::fs::metadata(&file_path).unwrap().len();
+            let frame_offset = video.tracks[0].frames[0].offset;
+
+            // The free atom absorbs the new atoms: the file keeps its size and the
+            // essence doesn't move.
+            video
+                .insert_colr_atom(&mut file, Selection::default(), 1, 1, 1, None)
+                .unwrap();
+            video
+                .insert_gama_atom(&mut file, Selection::default(), 2.4)
+                .unwrap();
+            assert_eq!(file.metadata().unwrap().len(), original_size);
+            assert_eq!(video.tracks[0].frames[0].offset, frame_offset);
+            assert!(video.tracks[0].has_colr_atom() && video.tracks[0].has_gama_atom());
+
+            // And gets its space back when they are removed.
+            video
+                .remove_gama_atom(&mut file, Selection::default())
+                .unwrap();
+            assert_eq!(file.metadata().unwrap().len(), original_size);
+            assert_eq!(video.tracks[0].frames[0].offset, frame_offset);
+            assert!(video.tracks[0].has_colr_atom() && !video.tracks[0].has_gama_atom());
+        }
+    }
+
+    #[test]
+    fn test_files_over_4_gb() {
+        // mdat with a largesize header and co64 chunk offsets, then mdat extending to
+        // the end of the file. The 5 GB of essence are a hole of the sparse file.
+        let layouts = [
+            testing::Layout {
+                co64: true,
+                mdat_gap: 5 << 30,
+                ..Default::default()
+            },
+            testing::Layout {
+                moov_first: true,
+                free: testing::Free::InsideMoov(64),
+                co64: true,
+                mdat_gap: 5 << 30,
+                mdat_to_end: true,
+                ..Default::default()
+            },
+        ];
+
+        for layout in layouts {
+            let track = testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(b"apcn", &[])].to_vec(),
+                samples: [
+                    testing::prores_frame(400, [1, 1, 1]),
+                    testing::prores_frame(500, [1, 1, 1]),
+                ]
+                .to_vec(),
+                ..Default::default()
+            };
+            let (file_path, mut file, mut video) =
+                testing::open_mov("over_4_gb.mov", &[track], layout);
+            let file_size = std::fs::metadata(&file_path).unwrap().len();
+            assert_eq!(video.tracks[0].frames.len(), 2);
+            assert!(video.tracks[0].frames[0].offset > u32::MAX as u64);
+            assert_eq!(video.tracks[0].frames[1].frame_size, 500);
+
+            let frame_offset = video.tracks[0].frames[0].offset;
+            video
+                .insert_colr_atom(&mut file, Selection::default(), 9, 16, 9, None)
+                .unwrap();
+            assert!(video.tracks[0].has_colr_atom());
+            assert_eq!(
+                video.tracks[0].sample_descriptions[0].colr_atoms[0].transfer_function_index,
+                16
+            );
+            assert_eq!(video.tracks[0].frames.len(), 2);
+            assert_eq!(video.tracks[0].frames[0].offset, frame_offset);
+            if layout.mdat_to_end {
+                assert_eq!(file.metadata().unwrap().len(), file_size);
+            }
+        }
+    }
+}
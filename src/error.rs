@@ -0,0 +1,239 @@
+use std::fmt;
+use std::io;
+
+use crate::atom;
+use crate::prores;
+use crate::{AlphaChannelType, InterlaceMode};
+
+/// Errors that can happen while writing the modifications to a file.
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O error occurred while reading or writing the file.
+    Io(io::Error),
+    /// The file has no video track.
+    NoVideoTrack,
+    /// No track of the file has the requested track ID.
+    TrackIdNotFound(u32),
+    /// The file has fewer tracks than the requested index.
+    TrackIndexNotFound(usize),
+    /// The requested track exists but isn't a video track.
+    NotAVideoTrack {
+        track_id: u32,
+        handler_type: [u8; 4],
+    },
+    /// The track has no colr atom in its video sample description, so there is no
+    /// offset to patch.
+    ColrAtomNotFound { track_id: u32 },
+    /// The colr atom of the track isn't of type 'nclc' or 'nclx' (e.g. it embeds an
+    /// ICC profile), so it has no primaries, transfer function and matrix indexes.
+    ColrAtomWithoutIndexes { track_id: u32 },
+    /// The full range flag can't be set because the colr atom of the track isn't of
+    /// type 'nclx'.
+    NclxColrAtomNotFound { track_id: u32 },
+    /// The track has no sample description to modify.
+    VideoSampleEntryNotFound { track_id: u32 },
+    /// The track has no sample description entry at the requested (1-based) index.
+    SampleDescriptionNotFound { track_id: u32, index: u32 },
+    /// None of the selected sample descriptions embeds an ICC profile.
+    IccProfileNotFound,
+    /// The bytes given as an ICC profile aren't one.
+    InvalidIccProfile(String),
+    /// The video sample description says the essence is ProRes, but no ProRes frame
+    /// was found in the samples of the track.
+    ProResFramesNotFound { track_id: u32, format: [u8; 4] },
+    /// A ProRes frame of the track isn't made of as many pictures as the requested
+    /// interlace mode needs: one for a progressive frame, two for an interlaced one.
+    InterlaceModeNotSupported {
+        track_id: u32,
+        interlace_mode: InterlaceMode,
+        picture_count: u8,
+    },
+    /// An alpha channel can only be enabled in ProRes 4444 frames (chroma format
+    /// 4:4:4) of a sample description whose depth is 32.
+    AlphaChannelNotSupported {
+        track_id: u32,
+        alpha_channel_type: AlphaChannelType,
+        depth: u16,
+        chroma_format: u8,
+    },
+    /// The track has no media header (mdhd) or no sample table (stbl) holding its
+    /// timing.
+    MediaTimingNotFound { track_id: u32 },
+    /// The file has no timecode track.
+    NoTimecodeTrack,
+    /// The requested track exists but isn't a timecode track.
+    NotATimecodeTrack {
+        track_id: u32,
+        handler_type: [u8; 4],
+    },
+    /// The string given as a reel name can't be one.
+    InvalidReelName(String),
+    /// The timecode track has no sample holding a frame number.
+    TimecodeSampleNotFound { track_id: u32 },
+    /// The timecode sample description isn't one frames can be counted in, e.g. it
+    /// describes a tape counter.
+    UnsupportedTimecodeFormat { track_id: u32 },
+    /// The timecode can't be counted in the timecode track.
+    InvalidTimecode(String),
+    /// A sequence parameter set of an H.264 or HEVC sample description, in its
+    /// configuration atom or in its samples, can't be parsed, so its colour
+    /// description can't be rewritten.
+    UnsupportedSequenceParameterSet { track_id: u32 },
+    /// A rewritten sequence parameter set no longer fits the length field preceding
+    /// the NAL units in the samples.
+    NalUnitLengthOverflow { track_id: u32 },
+    /// The new duration of the media doesn't fit the 32 bits of its version 0 mdhd
+    /// atom.
+    MediaDurationOverflow { track_id: u32 },
+    /// The new sample rate of an audio sample description doesn't fit its 16.16 fixed
+    /// point field.
+    SampleRateOverflow { track_id: u32 },
+    /// The timing of the track can't be rewritten for a frame rate code set in only
+    /// some of its frames, as it is shared by all of them.
+    PartialContainerTiming { track_id: u32 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::NoVideoTrack => write!(f, "the file has no video track"),
+            Error::TrackIdNotFound(track_id) => {
+                write!(f, "no track with the track ID {}", track_id)
+            }
+            Error::TrackIndexNotFound(index) => write!(f, "no track at index {}", index),
+            Error::NotAVideoTrack {
+                track_id,
+                handler_type,
+            } => write!(
+                f,
+                "track {} is a '{}' track, not a video track",
+                track_id,
+                atom::fourcc(handler_type)
+            ),
+            Error::ColrAtomNotFound { track_id } => write!(
+                f,
+                "no colr atom found in the video sample description of track {}, refusing to write",
+                track_id
+            ),
+            Error::ColrAtomWithoutIndexes { track_id } => write!(
+                f,
+                "the colr atom of track {} is neither 'nclc' nor 'nclx', refusing to write",
+                track_id
+            ),
+            Error::NclxColrAtomNotFound { track_id } => write!(
+                f,
+                "no 'nclx' colr atom found in track {}, so it has no full range flag",
+                track_id
+            ),
+            Error::VideoSampleEntryNotFound { track_id } => {
+                write!(f, "no sample description found in track {}", track_id)
+            }
+            Error::SampleDescriptionNotFound { track_id, index } => write!(
+                f,
+                "track {} has no sample description entry {}",
+                track_id, index
+            ),
+            Error::IccProfileNotFound => {
+                write!(
+                    f,
+                    "no 'prof' colr atom found in the selected sample descriptions"
+                )
+            }
+            Error::InvalidIccProfile(reason) => write!(f, "invalid ICC profile: {}", reason),
+            Error::ProResFramesNotFound { track_id, format } => write!(
+                f,
+                "track {} is '{}' but no ProRes frame was found in its samples, refusing to write",
+                track_id,
+                atom::fourcc(format)
+            ),
+            Error::InterlaceModeNotSupported {
+                track_id,
+                interlace_mode,
+                picture_count,
+            } => write!(
+                f,
+                "a ProRes frame of track {} is made of {} picture(s), it can't be {}, refusing to write",
+                track_id, picture_count, interlace_mode
+            ),
+            Error::AlphaChannelNotSupported {
+                track_id,
+                alpha_channel_type,
+                depth,
+                chroma_format,
+            } => write!(
+                f,
+                "a {} alpha channel needs 4:4:4 frames and a depth of 32, track {} has {} frames and a depth of {}, refusing to write",
+                alpha_channel_type,
+                track_id,
+                prores::describe_chroma_format(*chroma_format),
+                depth
+            ),
+            Error::NoTimecodeTrack => write!(f, "the file has no timecode track"),
+            Error::NotATimecodeTrack {
+                track_id,
+                handler_type,
+            } => write!(
+                f,
+                "track {} is a '{}' track, not a timecode track",
+                track_id,
+                atom::fourcc(handler_type)
+            ),
+            Error::InvalidReelName(reason) => write!(f, "invalid reel name: {}", reason),
+            Error::TimecodeSampleNotFound { track_id } => {
+                write!(f, "no timecode sample found in track {}", track_id)
+            }
+            Error::UnsupportedTimecodeFormat { track_id } => write!(
+                f,
+                "the timecode of track {} isn't a frame count, refusing to write",
+                track_id
+            ),
+            Error::InvalidTimecode(reason) => write!(f, "invalid timecode: {}", reason),
+            Error::UnsupportedSequenceParameterSet { track_id } => write!(
+                f,
+                "a sequence parameter set of track {} can't be parsed, refusing to write",
+                track_id
+            ),
+            Error::NalUnitLengthOverflow { track_id } => write!(
+                f,
+                "a rewritten sequence parameter set of track {} doesn't fit the NAL unit length field of its samples, refusing to write",
+                track_id
+            ),
+            Error::MediaTimingNotFound { track_id } => write!(
+                f,
+                "no mdhd atom or sample table found in track {}, refusing to write",
+                track_id
+            ),
+            Error::MediaDurationOverflow { track_id } => write!(
+                f,
+                "the media duration of track {} doesn't fit its version 0 mdhd atom, refusing to write",
+                track_id
+            ),
+            Error::SampleRateOverflow { track_id } => write!(
+                f,
+                "the new sample rate of track {} doesn't fit its sample description, refusing to write",
+                track_id
+            ),
+            Error::PartialContainerTiming { track_id } => write!(
+                f,
+                "the timing of track {} is shared by frames of sample descriptions that aren't selected, refusing to write",
+                track_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::str::FromStr;
+
+use crate::atom::{self, AtomHeader};
+use crate::{FielAtom, SampleDescription};
+
+/// How the pictures of a video are scanned, as told by the interlace_mode of a ProRes
+/// frame header and by the 'fiel' atom of the sample description.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum InterlaceMode {
+    /// One picture per frame.
+    #[default]
+    Progressive,
+    /// Two fields per frame, the top one displayed first.
+    TopFieldFirst,
+    /// Two fields per frame, the bottom one displayed first.
+    BottomFieldFirst,
+}
+
+impl InterlaceMode {
+    /// Size of the payload of a 'fiel' atom.
+    pub(crate) const FIEL_SIZE: usize = 2;
+
+    /// Decodes the 2-bit interlace_mode of a ProRes frame header. The reserved value 3
+    /// gives `None`.
+    pub(crate) fn from_prores_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(InterlaceMode::Progressive),
+            1 => Some(InterlaceMode::TopFieldFirst),
+            2 => Some(InterlaceMode::BottomFieldFirst),
+            _ => None,
+        }
+    }
+
+    /// The 2-bit interlace_mode of a ProRes frame header.
+    pub(crate) fn prores_code(self) -> u8 {
+        match self {
+            InterlaceMode::Progressive => 0,
+            InterlaceMode::TopFieldFirst => 1,
+            InterlaceMode::BottomFieldFirst => 2,
+        }
+    }
+
+    /// The number of pictures a ProRes frame of this mode is made of: the frame
+    /// itself, or its two fields.
+    pub(crate) fn picture_count(self) -> u8 {
+        match self {
+            InterlaceMode::Progressive => 1,
+            _ => 2,
+        }
+    }
+
+    /// Decodes the payload of a 'fiel' atom: a field count followed by a field
+    /// ordering, which is 1 or 9 (separated or interleaved fields) when the top field
+    /// comes first, and 6 or 14 when the bottom one does. Any other combination gives
+    /// `None`.
+    pub(crate) fn from_fiel_bytes(bytes: &[u8; Self::FIEL_SIZE]) -> Option<Self> {
+        match bytes {
+            [1, _] => Some(InterlaceMode::Progressive),
+            [2, 1 | 9] => Some(InterlaceMode::TopFieldFirst),
+            [2, 6 | 14] => Some(InterlaceMode::BottomFieldFirst),
+            _ => None,
+        }
+    }
+
+    /// Encodes the payload of a 'fiel' atom. ProRes stores the two fields one after
+    /// the other, hence the separated field orderings.
+    pub(crate) fn to_fiel_bytes(self) -> [u8; Self::FIEL_SIZE] {
+        match self {
+            InterlaceMode::Progressive => [1, 0],
+            InterlaceMode::TopFieldFirst => [2, 1],
+            InterlaceMode::BottomFieldFirst => [2, 6],
+        }
+    }
+}
+
+impl FromStr for InterlaceMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "progressive" => Ok(InterlaceMode::Progressive),
+            "tff" => Ok(InterlaceMode::TopFieldFirst),
+            "bff" => Ok(InterlaceMode::BottomFieldFirst),
+            _ => Err("valid values are progressive, tff and bff".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for InterlaceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterlaceMode::Progressive => write!(f, "progressive"),
+            InterlaceMode::TopFieldFirst => write!(f, "interlaced, top field first"),
+            InterlaceMode::BottomFieldFirst => write!(f, "interlaced, bottom field first"),
+        }
+    }
+}
+
+impl SampleDescription {
+    /// Constructs a fiel atom (field handling) and sets its offset, size, field count
+    /// and field ordering. A fiel atom too short to hold them is ignored.
+    pub(crate) fn construct_fiel_atom(
+        &mut self,
+        file: &mut File,
+        header: &AtomHeader,
+    ) -> io::Result<()> {
+        if let Some(payload) = atom::read_payload(file, header)? {
+            self.fiel_atom = FielAtom {
+                size: header.size as u32,
+                offset: header.offset,
+                fields: payload,
+                matched: true,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{ColrPolicy, Error, Selection};
+
+    #[test]
+    fn test_interlace_mode() {
+        for mode in [
+            InterlaceMode::Progressive,
+            InterlaceMode::TopFieldFirst,
+            InterlaceMode::BottomFieldFirst,
+        ] {
+            assert_eq!(
+                InterlaceMode::from_prores_code(mode.prores_code()),
+                Some(mode)
+            );
+            assert_eq!(
+                InterlaceMode::from_fiel_bytes(&mode.to_fiel_bytes()),
+                Some(mode)
+            );
+        }
+        assert_eq!(InterlaceMode::from_prores_code(3), None);
+        assert_eq!(
+            InterlaceMode::from_fiel_bytes(&[2, 14]),
+            Some(InterlaceMode::BottomFieldFirst)
+        );
+        assert_eq!(InterlaceMode::from_fiel_bytes(&[2, 0]), None);
+        assert_eq!("tff".parse(), Ok(InterlaceMode::TopFieldFirst));
+        assert!("interlaced".parse::<InterlaceMode>().is_err());
+    }
+
+    #[test]
+    fn test_set_interlace_mode() {
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(
+                b"apcn",
+                &[testing::nclc_colr_atom(1, 1, 1)],
+            )]
+            .to_vec(),
+            samples: [
+                testing::interlaced_prores_frame(400, [1, 1, 1]),
+                testing::interlaced_prores_frame(400, [1, 1, 1]),
+            ]
+            .to_vec(),
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) =
+            testing::open_mov("fiel.mov", &[track], Default::default());
+        let original = std::fs::read(&file_path).unwrap();
+        for frame in video.tracks[0].frames.iter() {
+            assert_eq!((frame.interlace_mode, frame.picture_count), (1, 2));
+        }
+
+        // Two fields can't make a progressive frame, nothing is written.
+        assert!(matches!(
+            video.set_interlace_mode(&mut file, Selection::default(), InterlaceMode::Progressive),
+            Err(Error::InterlaceModeNotSupported {
+                track_id: 1,
+                picture_count: 2,
+                ..
+            })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), original);
+
+        // A fiel atom is inserted, and the frames get the new order of the fields.
+        video
+            .set_interlace_mode(
+                &mut file,
+                Selection::default(),
+                InterlaceMode::BottomFieldFirst,
+            )
+            .unwrap();
+        testing::redecode(&file_path, &video);
+        let fiel_atom = &video.tracks[0].sample_descriptions[0].fiel_atom;
+        assert_eq!((fiel_atom.size, fiel_atom.fields), (10, [2, 6]));
+        for frame in video.tracks[0].frames.iter() {
+            assert_eq!(frame.interlace_mode, 2);
+        }
+        let info = video.info(ColrPolicy::default());
+        assert!(info.contains("fiel: 2 fields, ordering 6 (interlaced, bottom field first)"));
+        assert!(info.contains("ProRes interlace mode: interlaced, bottom field first\n"));
+    }
+}
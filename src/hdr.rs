@@ -0,0 +1,500 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::str::FromStr;
+
+use crate::atom::{self, AtomHeader};
+use crate::{ClliAtom, MdcvAtom, SampleDescription};
+
+/// Chromaticity coordinates are stored in increments of 0.00002.
+const CHROMATICITY_UNITS: f64 = 50000.0;
+
+/// Luminances are stored in increments of 0.0001 cd/m².
+const LUMINANCE_UNITS: f64 = 10000.0;
+
+/// x and y chromaticity coordinates.
+type Chromaticity = (f64, f64);
+
+/// The display primaries and white points of the usual mastering displays, as
+/// (name, [green, blue, red], white point) chromaticity coordinates.
+const PRESETS: [(&str, [Chromaticity; 3], Chromaticity); 4] = [
+    (
+        "BT709",
+        [(0.300, 0.600), (0.150, 0.060), (0.640, 0.330)],
+        (0.3127, 0.3290),
+    ),
+    (
+        "P3D65",
+        [(0.265, 0.690), (0.150, 0.060), (0.680, 0.320)],
+        (0.3127, 0.3290),
+    ),
+    (
+        "P3DCI",
+        [(0.265, 0.690), (0.150, 0.060), (0.680, 0.320)],
+        (0.314, 0.351),
+    ),
+    (
+        "BT2020",
+        [(0.170, 0.797), (0.131, 0.046), (0.708, 0.292)],
+        (0.3127, 0.3290),
+    ),
+];
+
+/// The color volume of the display a video was mastered on (SMPTE ST 2086), as stored
+/// in an 'mdcv' atom: 24 bytes in the units of the standard.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct MasteringDisplay {
+    /// The green, blue and red display primaries (in this order), as x and y
+    /// chromaticity coordinates in increments of 0.00002.
+    pub primaries: [(u16, u16); 3],
+    /// The white point, as x and y chromaticity coordinates in increments of 0.00002.
+    pub white_point: (u16, u16),
+    /// The maximum display mastering luminance, in increments of 0.0001 cd/m².
+    pub max_luminance: u32,
+    /// The minimum display mastering luminance, in increments of 0.0001 cd/m².
+    pub min_luminance: u32,
+}
+
+impl MasteringDisplay {
+    /// Size of the payload of an 'mdcv' atom.
+    pub(crate) const SIZE: usize = 24;
+
+    /// Decodes the payload of an 'mdcv' atom.
+    pub(crate) fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        let u16_at = |at: usize| u16::from_be_bytes([bytes[at], bytes[at + 1]]);
+        let u32_at = |at: usize| {
+            u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+
+        Self {
+            primaries: [
+                (u16_at(0), u16_at(2)),
+                (u16_at(4), u16_at(6)),
+                (u16_at(8), u16_at(10)),
+            ],
+            white_point: (u16_at(12), u16_at(14)),
+            max_luminance: u32_at(16),
+            min_luminance: u32_at(20),
+        }
+    }
+
+    /// Encodes the payload of an 'mdcv' atom.
+    pub(crate) fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        let coordinates = self.primaries.iter().chain([&self.white_point]);
+        for (i, (x, y)) in coordinates.enumerate() {
+            bytes[i * 4..i * 4 + 2].copy_from_slice(&x.to_be_bytes());
+            bytes[i * 4 + 2..i * 4 + 4].copy_from_slice(&y.to_be_bytes());
+        }
+        bytes[16..20].copy_from_slice(&self.max_luminance.to_be_bytes());
+        bytes[20..24].copy_from_slice(&self.min_luminance.to_be_bytes());
+        bytes
+    }
+
+    /// Returns the name of the preset whose primaries and white point these are, if
+    /// any.
+    fn preset_name(&self) -> Option<&'static str> {
+        PRESETS.iter().find_map(|&(name, primaries, white_point)| {
+            let preset = Self::from_chromaticities(primaries, white_point, 0.0, 0.0);
+            (preset.primaries == self.primaries && preset.white_point == self.white_point)
+                .then_some(name)
+        })
+    }
+
+    fn from_chromaticities(
+        primaries: [Chromaticity; 3],
+        white_point: Chromaticity,
+        max_luminance: f64,
+        min_luminance: f64,
+    ) -> Self {
+        let chromaticity = |(x, y): Chromaticity| {
+            (
+                (x * CHROMATICITY_UNITS).round() as u16,
+                (y * CHROMATICITY_UNITS).round() as u16,
+            )
+        };
+
+        Self {
+            primaries: primaries.map(chromaticity),
+            white_point: chromaticity(white_point),
+            max_luminance: (max_luminance * LUMINANCE_UNITS).round() as u32,
+            min_luminance: (min_luminance * LUMINANCE_UNITS).round() as u32,
+        }
+    }
+}
+
+/// Parses a mastering display in human units, either as
+/// `<PRESET>,<MAX_NITS>,<MIN_NITS>` where the preset is one of BT709, P3D65, P3DCI
+/// and BT2020, e.g. `P3D65,1000,0.0001`, or as the chromaticity coordinates of the
+/// green, blue and red primaries and of the white point followed by the luminances,
+/// e.g. `0.265,0.69,0.15,0.06,0.68,0.32,0.3127,0.329,1000,0.0001`.
+impl FromStr for MasteringDisplay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(',').map(str::trim).collect();
+        let number = |field: &str| {
+            field
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite() && *value >= 0.0)
+                .ok_or_else(|| format!("`{}` isn't a positive number", field))
+        };
+
+        let (primaries, white_point, luminances) = match fields.as_slice() {
+            [preset, max, min] => {
+                let (_, primaries, white_point) = PRESETS
+                    .iter()
+                    .find(|(name, _, _)| name.eq_ignore_ascii_case(preset))
+                    .ok_or_else(|| {
+                        format!(
+                            "unknown mastering display `{}`, valid values are BT709, P3D65, P3DCI and BT2020",
+                            preset
+                        )
+                    })?;
+                (*primaries, *white_point, (number(max)?, number(min)?))
+            }
+            [gx, gy, bx, by, rx, ry, wx, wy, max, min] => (
+                [
+                    (number(gx)?, number(gy)?),
+                    (number(bx)?, number(by)?),
+                    (number(rx)?, number(ry)?),
+                ],
+                (number(wx)?, number(wy)?),
+                (number(max)?, number(min)?),
+            ),
+            _ => {
+                return Err(
+                    "expected <PRESET>,<MAX_NITS>,<MIN_NITS> or the 8 chromaticity coordinates followed by <MAX_NITS>,<MIN_NITS>"
+                        .to_string(),
+                )
+            }
+        };
+
+        let all_coordinates = primaries.iter().chain([&white_point]);
+        if all_coordinates.flat_map(|&(x, y)| [x, y]).any(|c| c > 1.0) {
+            return Err("chromaticity coordinates must be between 0 and 1".to_string());
+        }
+        let (max_luminance, min_luminance) = luminances;
+        if min_luminance >= max_luminance {
+            return Err(
+                "the minimum luminance must be lower than the maximum luminance".to_string(),
+            );
+        }
+        if max_luminance * LUMINANCE_UNITS > u32::MAX as f64 {
+            return Err(format!(
+                "the maximum luminance must be at most {} cd/m²",
+                u32::MAX as f64 / LUMINANCE_UNITS
+            ));
+        }
+
+        Ok(Self::from_chromaticities(
+            primaries,
+            white_point,
+            max_luminance,
+            min_luminance,
+        ))
+    }
+}
+
+impl fmt::Display for MasteringDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chromaticity = |(x, y): (u16, u16)| {
+            format!(
+                "({:.4}, {:.4})",
+                x as f64 / CHROMATICITY_UNITS,
+                y as f64 / CHROMATICITY_UNITS
+            )
+        };
+        let [green, blue, red] = self.primaries;
+
+        if let Some(name) = self.preset_name() {
+            write!(f, "{}", name)?;
+        } else {
+            write!(
+                f,
+                "R{} G{} B{} white point {}",
+                chromaticity(red),
+                chromaticity(green),
+                chromaticity(blue),
+                chromaticity(self.white_point)
+            )?;
+        }
+        write!(
+            f,
+            ", {} to {} cd/m²",
+            self.min_luminance as f64 / LUMINANCE_UNITS,
+            self.max_luminance as f64 / LUMINANCE_UNITS
+        )
+    }
+}
+
+/// The content light levels of a video (CEA-861.3), as stored in a 'clli' atom: its
+/// MaxCLL and MaxFALL, both in cd/m².
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct ContentLightLevel {
+    /// The maximum content light level: the luminance of the brightest pixel of the
+    /// whole video.
+    pub max_cll: u16,
+    /// The maximum frame-average light level: the highest average luminance of a
+    /// single frame.
+    pub max_fall: u16,
+}
+
+impl ContentLightLevel {
+    /// Size of the payload of a 'clli' atom.
+    pub(crate) const SIZE: usize = 4;
+
+    /// Decodes the payload of a 'clli' atom.
+    pub(crate) fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            max_cll: u16::from_be_bytes([bytes[0], bytes[1]]),
+            max_fall: u16::from_be_bytes([bytes[2], bytes[3]]),
+        }
+    }
+
+    /// Encodes the payload of a 'clli' atom.
+    pub(crate) fn to_bytes(self) -> [u8; Self::SIZE] {
+        let [cll_high, cll_low] = self.max_cll.to_be_bytes();
+        let [fall_high, fall_low] = self.max_fall.to_be_bytes();
+        [cll_high, cll_low, fall_high, fall_low]
+    }
+}
+
+/// Parses content light levels given as `<MAX_CLL>,<MAX_FALL>` in cd/m², e.g.
+/// `1000,400`.
+impl FromStr for ContentLightLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((max_cll, max_fall)) = s.split_once(',') else {
+            return Err("expected <MAX_CLL>,<MAX_FALL>".to_string());
+        };
+        let level = |field: &str| {
+            field
+                .trim()
+                .parse::<u16>()
+                .map_err(|_| format!("`{}` isn't a light level between 0 and 65535", field))
+        };
+        let content_light_level = Self {
+            max_cll: level(max_cll)?,
+            max_fall: level(max_fall)?,
+        };
+
+        if content_light_level.max_fall > content_light_level.max_cll {
+            return Err("MaxFALL can't be higher than MaxCLL".to_string());
+        }
+
+        Ok(content_light_level)
+    }
+}
+
+impl fmt::Display for ContentLightLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MaxCLL {} cd/m², MaxFALL {} cd/m²",
+            self.max_cll, self.max_fall
+        )
+    }
+}
+
+impl SampleDescription {
+    /// Constructs an mdcv atom (SMPTE ST 2086 mastering display color volume) and sets
+    /// its offset, size and mastering display. An mdcv atom too short to hold the
+    /// mastering display is ignored.
+    pub(crate) fn construct_mdcv_atom(
+        &mut self,
+        file: &mut File,
+        header: &AtomHeader,
+    ) -> io::Result<()> {
+        if let Some(payload) = atom::read_payload(file, header)? {
+            self.mdcv_atom = MdcvAtom {
+                size: header.size as u32,
+                offset: header.offset,
+                mastering_display: MasteringDisplay::from_bytes(&payload),
+                matched: true,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Constructs a clli atom (content light level) and sets its offset, size and
+    /// light levels. A clli atom too short to hold them is ignored.
+    pub(crate) fn construct_clli_atom(
+        &mut self,
+        file: &mut File,
+        header: &AtomHeader,
+    ) -> io::Result<()> {
+        if let Some(payload) = atom::read_payload(file, header)? {
+            self.clli_atom = ClliAtom {
+                size: header.size as u32,
+                offset: header.offset,
+                content_light_level: ContentLightLevel::from_bytes(&payload),
+                matched: true,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{ColrPolicy, Selection};
+
+    #[test]
+    fn test_mastering_display() {
+        let p3: MasteringDisplay = "P3D65,1000,0.0001".parse().unwrap();
+        assert_eq!(
+            p3,
+            MasteringDisplay {
+                primaries: [(13250, 34500), (7500, 3000), (34000, 16000)],
+                white_point: (15635, 16450),
+                max_luminance: 10000000,
+                min_luminance: 1,
+            }
+        );
+        assert_eq!(MasteringDisplay::from_bytes(&p3.to_bytes()), p3);
+        assert_eq!(p3.to_string(), "P3D65, 0.0001 to 1000 cd/m²");
+
+        let explicit: MasteringDisplay = "0.265,0.69,0.15,0.06,0.68,0.32,0.3127,0.329,1000,0.0001"
+            .parse()
+            .unwrap();
+        assert_eq!(explicit, p3);
+
+        assert!("P3D65,1000".parse::<MasteringDisplay>().is_err());
+        assert!("Rec601,1000,0.0001".parse::<MasteringDisplay>().is_err());
+        assert!("BT2020,0.0001,1000".parse::<MasteringDisplay>().is_err());
+        assert_eq!(
+            "BT2020,500000,0.0001"
+                .parse::<MasteringDisplay>()
+                .unwrap_err(),
+            "the maximum luminance must be at most 429496.7295 cd/m²"
+        );
+    }
+
+    #[test]
+    fn test_content_light_level() {
+        let level: ContentLightLevel = "1000, 400".parse().unwrap();
+        assert_eq!(
+            level,
+            ContentLightLevel {
+                max_cll: 1000,
+                max_fall: 400,
+            }
+        );
+        assert_eq!(level.to_bytes(), [0x03, 0xE8, 0x01, 0x90]);
+        assert_eq!(ContentLightLevel::from_bytes(&level.to_bytes()), level);
+        assert_eq!(level.to_string(), "MaxCLL 1000 cd/m², MaxFALL 400 cd/m²");
+
+        assert!("1000".parse::<ContentLightLevel>().is_err());
+        assert!("100000,400".parse::<ContentLightLevel>().is_err());
+        assert!("400,1000".parse::<ContentLightLevel>().is_err());
+    }
+
+    #[test]
+    fn test_insert_replace_and_remove_mdcv_atom() {
+        let (file_path, mut file, mut video) =
+            testing::open_footage("1-1-1_2frames_prores422.mov", "mdcv.mov");
+        assert!(!video.tracks[0].sample_descriptions[0].mdcv_atom.matched);
+
+        let p3: MasteringDisplay = "P3D65,1000,0.0001".parse().unwrap();
+        video
+            .set_mastering_display(&mut file, Selection::default(), &p3)
+            .unwrap();
+        let mdcv_atom = &video.tracks[0].sample_descriptions[0].mdcv_atom;
+        assert!(mdcv_atom.matched);
+        assert_eq!(mdcv_atom.size, 32);
+        assert_eq!(mdcv_atom.mastering_display, p3);
+        assert_eq!(
+            video.tracks[0].sample_descriptions[0].sample_entry.size,
+            130 + 32
+        );
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("mdcv: P3D65, 0.0001 to 1000 cd/m²"));
+
+        // Setting it again replaces the atom in place.
+        let bt2020: MasteringDisplay = "BT2020,4000,0.005".parse().unwrap();
+        video
+            .set_mastering_display(&mut file, Selection::default(), &bt2020)
+            .unwrap();
+        let mdcv_atom = &video.tracks[0].sample_descriptions[0].mdcv_atom;
+        assert_eq!(mdcv_atom.mastering_display, bt2020);
+        assert_eq!(
+            video.tracks[0].sample_descriptions[0].sample_entry.size,
+            130 + 32
+        );
+        assert_eq!(video.tracks[0].frames.len(), 2);
+
+        // Removing it again must give back the original file, byte for byte.
+        video
+            .remove_mastering_display(&mut file, Selection::default())
+            .unwrap();
+        assert!(!video.tracks[0].sample_descriptions[0].mdcv_atom.matched);
+        assert_eq!(
+            std::fs::read(&file_path).unwrap(),
+            std::fs::read("tests/footages/1-1-1_2frames_prores422.mov").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_set_content_light_level() {
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(
+                b"apch",
+                &[testing::nclc_colr_atom(9, 16, 9)],
+            )]
+            .to_vec(),
+            samples: [testing::prores_frame(400, [9, 16, 9])].to_vec(),
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) =
+            testing::open_mov("clli.mov", &[track], Default::default());
+        let original = std::fs::read(&file_path).unwrap();
+        assert_eq!(
+            video
+                .pq_without_content_light_level(Selection::default(), ColrPolicy::default())
+                .unwrap(),
+            [(1, 1)]
+        );
+
+        let level: ContentLightLevel = "1000,400".parse().unwrap();
+        video
+            .set_content_light_level(&mut file, Selection::default(), &level)
+            .unwrap();
+        let clli_atom = &video.tracks[0].sample_descriptions[0].clli_atom;
+        assert!(clli_atom.matched);
+        assert_eq!(clli_atom.size, 12);
+        assert_eq!(clli_atom.content_light_level, level);
+        assert!(video
+            .pq_without_content_light_level(Selection::default(), ColrPolicy::default())
+            .unwrap()
+            .is_empty());
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("clli: MaxCLL 1000 cd/m², MaxFALL 400 cd/m²"));
+
+        // Setting it again replaces the atom in place.
+        let level: ContentLightLevel = "4000,1000".parse().unwrap();
+        video
+            .set_content_light_level(&mut file, Selection::default(), &level)
+            .unwrap();
+        let description = &video.tracks[0].sample_descriptions[0];
+        assert_eq!(description.clli_atom.content_light_level, level);
+        assert_eq!(description.sample_entry.size, 86 + 18 + 12);
+        assert_eq!(video.tracks[0].frames.len(), 1);
+
+        // Removing it again must give back the original file, byte for byte.
+        video
+            .remove_content_light_level(&mut file, Selection::default())
+            .unwrap();
+        assert!(!video.tracks[0].sample_descriptions[0].clli_atom.matched);
+        assert_eq!(std::fs::read(&file_path).unwrap(), original);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::avc::{self, BitReader};
+
+/// The nal_unit_type of an HEVC sequence parameter set.
+pub(crate) const SPS_NAL_UNIT_TYPE: u8 = 33;
+
+/// The size of the fixed fields of an HEVC decoder configuration record, before its
+/// arrays of NAL units.
+const CONFIGURATION_HEADER_SIZE: usize = 22;
+
+/// The number of bits of the profile of a (sub-)layer in profile_tier_level: profile
+/// space, tier and profile (8), compatibility flags (32), source and constraint flags
+/// (48).
+const PROFILE_BITS: usize = 88;
+
+/// Reads the fields of an HEVC SPS up to vui_parameters_present_flag, and returns its
+/// general_profile_idc.
+pub(crate) fn skip_to_vui(reader: &mut BitReader) -> Option<u8> {
+    reader.bits(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = reader.bits(3)? as usize;
+    reader.bit()?; // sps_temporal_id_nesting_flag
+
+    // profile_tier_level, with the general profile and level.
+    reader.bits(3)?; // general_profile_space, general_tier_flag
+    let profile_idc = reader.bits(5)? as u8;
+    reader.skip(PROFILE_BITS - 8 + 8)?; // the rest of the profile, general_level_idc
+    let mut sub_layers = Vec::with_capacity(max_sub_layers_minus1);
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers.push((reader.bit()?, reader.bit()?)); // profile and level present
+    }
+    if max_sub_layers_minus1 > 0 {
+        reader.skip(2 * (8 - max_sub_layers_minus1))?; // reserved_zero_2bits
+    }
+    for (profile_present, level_present) in sub_layers {
+        if profile_present {
+            reader.skip(PROFILE_BITS)?;
+        }
+        if level_present {
+            reader.skip(8)?;
+        }
+    }
+
+    reader.ue()?; // sps_seq_parameter_set_id
+    if reader.ue()? == 3 {
+        reader.bit()?; // separate_colour_plane_flag
+    }
+    reader.ue()?; // pic_width_in_luma_samples
+    reader.ue()?; // pic_height_in_luma_samples
+    if reader.bit()? {
+        for _ in 0..4 {
+            reader.ue()?; // conf_win offsets
+        }
+    }
+    reader.ue()?; // bit_depth_luma_minus8
+    reader.ue()?; // bit_depth_chroma_minus8
+    let log2_max_pic_order_cnt_lsb = reader.ue()? as usize + 4;
+    if log2_max_pic_order_cnt_lsb > 16 {
+        return None;
+    }
+    let first_sub_layer = if reader.bit()? {
+        0
+    } else {
+        max_sub_layers_minus1
+    };
+    for _ in first_sub_layer..=max_sub_layers_minus1 {
+        reader.ue()?; // sps_max_dec_pic_buffering_minus1
+        reader.ue()?; // sps_max_num_reorder_pics
+        reader.ue()?; // sps_max_latency_increase_plus1
+    }
+    for _ in 0..6 {
+        reader.ue()?; // coding and transform block sizes, transform hierarchy depths
+    }
+    if reader.bit()? && reader.bit()? {
+        skip_scaling_list_data(reader)?;
+    }
+    reader.bit()?; // amp_enabled_flag
+    reader.bit()?; // sample_adaptive_offset_enabled_flag
+    if reader.bit()? {
+        reader.skip(8)?; // pcm_sample_bit_depth_luma_minus1, chroma_minus1
+        reader.ue()?; // log2_min_pcm_luma_coding_block_size_minus3
+        reader.ue()?; // log2_diff_max_min_pcm_luma_coding_block_size
+        reader.bit()?; // pcm_loop_filter_disabled_flag
+    }
+    skip_short_term_ref_pic_sets(reader)?;
+    if reader.bit()? {
+        let count = reader.ue()?;
+        if count > 32 {
+            return None;
+        }
+        for _ in 0..count {
+            reader.skip(log2_max_pic_order_cnt_lsb)?; // lt_ref_pic_poc_lsb_sps
+            reader.bit()?; // used_by_curr_pic_lt_sps_flag
+        }
+    }
+    reader.bit()?; // sps_temporal_mvp_enabled_flag
+    reader.bit()?; // strong_intra_smoothing_enabled_flag
+    Some(profile_idc)
+}
+
+/// Skips a scaling_list_data: for each size of transform, the matrices that are
+/// either predicted from another one or coded as deltas.
+fn skip_scaling_list_data(reader: &mut BitReader) -> Option<()> {
+    for size_id in 0..4 {
+        let coefficients = 64.min(1 << (4 + (size_id << 1)));
+        let matrices = if size_id == 3 { 2 } else { 6 };
+        for _ in 0..matrices {
+            if !reader.bit()? {
+                reader.ue()?; // scaling_list_pred_matrix_id_delta
+                continue;
+            }
+            if size_id > 1 {
+                reader.se()?; // scaling_list_dc_coef_minus8
+            }
+            for _ in 0..coefficients {
+                reader.se()?; // scaling_list_delta_coef
+            }
+        }
+    }
+    Some(())
+}
+
+/// Skips the short-term reference picture sets of an SPS. A set predicted from the
+/// previous one has a flag per picture of that one, so the number of pictures of each
+/// set is tracked.
+fn skip_short_term_ref_pic_sets(reader: &mut BitReader) -> Option<()> {
+    let count = reader.ue()? as usize;
+    if count > 64 {
+        return None;
+    }
+    let mut delta_pocs: Vec<u32> = Vec::with_capacity(count);
+    for index in 0..count {
+        if index != 0 && reader.bit()? {
+            reader.bit()?; // delta_rps_sign
+            reader.ue()?; // abs_delta_rps_minus1
+            let mut pictures = 0;
+            for _ in 0..=delta_pocs[index - 1] {
+                // used_by_curr_pic_flag, else use_delta_flag
+                if reader.bit()? || reader.bit()? {
+                    pictures += 1;
+                }
+            }
+            delta_pocs.push(pictures);
+        } else {
+            let negative = reader.ue()?;
+            let positive = reader.ue()?;
+            if negative > 16 || positive > 16 {
+                return None;
+            }
+            for _ in 0..negative + positive {
+                reader.ue()?; // delta_poc_minus1
+                reader.bit()?; // used_by_curr_pic_flag
+            }
+            delta_pocs.push(negative + positive);
+        }
+    }
+    Some(())
+}
+
+/// An HEVC decoder configuration record, the payload of an 'hvcC' atom: arrays of
+/// video, sequence and picture parameter sets and SEI NAL units.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub(crate) struct HevcConfiguration {
+    // From configurationVersion to lengthSizeMinusOne.
+    pub(crate) header: [u8; CONFIGURATION_HEADER_SIZE],
+    // Each array: array_completeness and NAL_unit_type, and its NAL units.
+    arrays: Vec<(u8, Vec<Vec<u8>>)>,
+    // Bytes after the arrays, kept as they are.
+    extensions: Vec<u8>,
+}
+
+impl HevcConfiguration {
+    /// Decodes the payload of an 'hvcC' atom. Returns `None` if it is truncated.
+    pub(crate) fn from_bytes(payload: &[u8]) -> Option<Self> {
+        let header = payload.get(..CONFIGURATION_HEADER_SIZE)?.try_into().ok()?;
+        let (&count, mut rest) = payload[CONFIGURATION_HEADER_SIZE..].split_first()?;
+        let mut arrays = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let nal_unit_type = *rest.first()?;
+            let nal_units = u16::from_be_bytes([*rest.get(1)?, *rest.get(2)?]) as usize;
+            rest = &rest[3..];
+            arrays.push((
+                nal_unit_type,
+                avc::read_parameter_sets(&mut rest, nal_units)?,
+            ));
+        }
+
+        Some(HevcConfiguration {
+            header,
+            arrays,
+            extensions: rest.to_vec(),
+        })
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = self.header.to_vec();
+        payload.push(self.arrays.len() as u8);
+        for (nal_unit_type, nal_units) in self.arrays.iter() {
+            payload.push(*nal_unit_type);
+            payload.extend_from_slice(&(nal_units.len() as u16).to_be_bytes());
+            avc::write_parameter_sets(&mut payload, nal_units);
+        }
+        payload.extend_from_slice(&self.extensions);
+        payload
+    }
+
+    fn is_sps_array(nal_unit_type: u8) -> bool {
+        nal_unit_type & 0x3F == SPS_NAL_UNIT_TYPE
+    }
+
+    pub(crate) fn sequence_parameter_sets(&self) -> Vec<&Vec<u8>> {
+        self.arrays
+            .iter()
+            .filter(|(nal_unit_type, _)| Self::is_sps_array(*nal_unit_type))
+            .flat_map(|(_, nal_units)| nal_units.iter())
+            .collect()
+    }
+
+    pub(crate) fn sequence_parameter_sets_mut(&mut self) -> Vec<&mut Vec<u8>> {
+        self.arrays
+            .iter_mut()
+            .filter(|(nal_unit_type, _)| Self::is_sps_array(*nal_unit_type))
+            .flat_map(|(_, nal_units)| nal_units.iter_mut())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avc::{Codec, SequenceParameterSet, VideoSignalType};
+    use crate::testing;
+
+    #[test]
+    fn test_rewrite_sps() {
+        let bt709 = Some(VideoSignalType {
+            video_format: 5,
+            full_range: false,
+            colour_description: Some([1, 1, 1]),
+        });
+        let bt2100_pq = Some(VideoSignalType {
+            video_format: 5,
+            full_range: true,
+            colour_description: Some([9, 16, 9]),
+        });
+        let sps = testing::hevc_sps(bt709, true);
+        let parsed = SequenceParameterSet::parse(Codec::Hevc, &sps).unwrap();
+        assert_eq!(parsed.profile_idc, 2);
+        assert_eq!(parsed.video_signal_type, bt709);
+        // An H.264 SPS has another NAL unit header.
+        assert_eq!(SequenceParameterSet::parse(Codec::Avc, &sps), None);
+
+        let rewritten = avc::rewrite_sps(Codec::Hevc, &sps, Some([9, 16, 9]), Some(true));
+        assert_eq!(rewritten, Some(testing::hevc_sps(bt2100_pq, true)));
+
+        // The fields following the VUI are kept when one is added.
+        for (video_signal_type, vui_extras) in [(None, true), (None, false)] {
+            let sps = testing::hevc_sps(video_signal_type, vui_extras);
+            let rewritten = avc::rewrite_sps(Codec::Hevc, &sps, Some([9, 16, 9]), Some(true));
+            assert_eq!(rewritten, Some(testing::hevc_sps(bt2100_pq, vui_extras)));
+        }
+    }
+
+    #[test]
+    fn test_hevc_configuration() {
+        let sps = testing::hevc_sps(None, false);
+        let payload = testing::hvcc_payload(&sps);
+        let configuration = HevcConfiguration::from_bytes(&payload).unwrap();
+        assert_eq!(configuration.sequence_parameter_sets(), [&sps]);
+        assert_eq!(configuration.to_bytes(), payload);
+        assert_eq!(
+            HevcConfiguration::from_bytes(&payload[..payload.len() - 1]),
+            None
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use std::fmt;
+
+use crate::atom;
+use crate::Error;
+
+/// Size of the header of an ICC profile, before its tag table.
+const HEADER_SIZE: usize = 128;
+
+/// The tone reproduction curves a profile can have: one per RGB channel, or a single
+/// one for a gray profile.
+const TRC_TAGS: [[u8; 4]; 4] = [*b"rTRC", *b"gTRC", *b"bTRC", *b"kTRC"];
+
+/// An ICC profile, as embedded in a colr atom of type 'prof'.
+///
+/// Only the header and the few tags worth reporting are decoded (see
+/// [`IccProfile::description`], [`IccProfile::white_point`] and
+/// [`IccProfile::tone_curves`]); the profile itself is kept as is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IccProfile {
+    bytes: Vec<u8>,
+}
+
+/// A tone reproduction curve (`curv` or `para` tag) of an ICC profile.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToneCurve {
+    /// A `curv` tag without entries: the identity function.
+    Identity,
+    /// A `curv` tag with a single entry: a pure power function.
+    Gamma(f64),
+    /// A `curv` tag with a sampled curve of the given number of entries.
+    Table(usize),
+    /// A `para` tag: a parametric curve whose first parameter is the gamma.
+    Parametric {
+        function_type: u16,
+        params: Vec<f64>,
+    },
+}
+
+impl IccProfile {
+    /// Wraps the bytes of an ICC profile, checking that they look like one: the
+    /// profile must be at least as big as its header and tag count, have the 'acsp'
+    /// signature, and its size field must match the number of bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidIccProfile` if one of these checks fails.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE + 4 {
+            return Err(Error::InvalidIccProfile(format!(
+                "{} bytes is too short for an ICC profile",
+                bytes.len()
+            )));
+        }
+        if &bytes[36..40] != b"acsp" {
+            return Err(Error::InvalidIccProfile(
+                "the 'acsp' signature is missing".to_string(),
+            ));
+        }
+        let size = be_u32(&bytes, 0).unwrap_or_default() as usize;
+        if size != bytes.len() {
+            return Err(Error::InvalidIccProfile(format!(
+                "the profile says it is {} bytes long but it is {} bytes long",
+                size,
+                bytes.len()
+            )));
+        }
+
+        Ok(Self { bytes })
+    }
+
+    /// Returns the raw bytes of the profile, e.g. to write them to a .icc file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the version of the profile, e.g. (2, 1) or (4, 3).
+    pub fn version(&self) -> (u8, u8) {
+        (self.bytes[8], self.bytes[9] >> 4)
+    }
+
+    /// Returns the color space of the data the profile applies to, e.g. "RGB ".
+    pub fn color_space(&self) -> [u8; 4] {
+        self.bytes[16..20].try_into().unwrap()
+    }
+
+    /// Returns the profile description ('desc' tag), either a version 2
+    /// `textDescriptionType` or a version 4 `multiLocalizedUnicodeType`, in which case
+    /// the first record is returned.
+    pub fn description(&self) -> Option<String> {
+        let tag = self.tag(*b"desc")?;
+        match tag.get(0..4)? {
+            b"desc" => {
+                let count = be_u32(tag, 8)? as usize;
+                let ascii = tag.get(12..12 + count)?;
+                let text = ascii.split(|&b| b == 0).next().unwrap_or_default();
+                Some(String::from_utf8_lossy(text).into_owned())
+            }
+            b"mluc" => {
+                if be_u32(tag, 8)? == 0 {
+                    return None;
+                }
+                let length = be_u32(tag, 20)? as usize;
+                let offset = be_u32(tag, 24)? as usize;
+                let utf16: Vec<u16> = tag
+                    .get(offset..offset + length)?
+                    .chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    .collect();
+                Some(String::from_utf16_lossy(&utf16))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the media white point ('wtpt' tag) as CIE XYZ values.
+    pub fn white_point(&self) -> Option<[f64; 3]> {
+        let tag = self.tag(*b"wtpt")?;
+        if tag.get(0..4)? != b"XYZ " {
+            return None;
+        }
+        Some([
+            s15_fixed16(tag, 8)?,
+            s15_fixed16(tag, 12)?,
+            s15_fixed16(tag, 16)?,
+        ])
+    }
+
+    /// Returns the tone reproduction curves of the profile, with the signature of
+    /// their tag ('rTRC', 'gTRC', 'bTRC' or 'kTRC').
+    pub fn tone_curves(&self) -> Vec<([u8; 4], ToneCurve)> {
+        TRC_TAGS
+            .iter()
+            .filter_map(|&signature| {
+                let tag = self.tag(signature)?;
+                let curve = match tag.get(0..4)? {
+                    b"curv" => match be_u32(tag, 8)? {
+                        0 => ToneCurve::Identity,
+                        1 => {
+                            let gamma = u16::from_be_bytes([*tag.get(12)?, *tag.get(13)?]);
+                            ToneCurve::Gamma(gamma as f64 / 256.0)
+                        }
+                        count => ToneCurve::Table(count as usize),
+                    },
+                    b"para" => {
+                        let function_type = u16::from_be_bytes([*tag.get(8)?, *tag.get(9)?]);
+                        let count = match function_type {
+                            0 => 1,
+                            1 => 3,
+                            2 => 4,
+                            3 => 5,
+                            4 => 7,
+                            _ => return None,
+                        };
+                        let params = (0..count)
+                            .map(|i| s15_fixed16(tag, 12 + i * 4))
+                            .collect::<Option<_>>()?;
+                        ToneCurve::Parametric {
+                            function_type,
+                            params,
+                        }
+                    }
+                    _ => return None,
+                };
+                Some((signature, curve))
+            })
+            .collect()
+    }
+
+    /// Returns the data of the tag with the given signature, as listed in the tag
+    /// table that follows the header.
+    fn tag(&self, signature: [u8; 4]) -> Option<&[u8]> {
+        let count = be_u32(&self.bytes, HEADER_SIZE)? as usize;
+        (0..count).find_map(|i| {
+            let entry = HEADER_SIZE + 4 + i * 12;
+            if self.bytes.get(entry..entry + 4)? != signature {
+                return None;
+            }
+            let offset = be_u32(&self.bytes, entry + 4)? as usize;
+            let size = be_u32(&self.bytes, entry + 8)? as usize;
+            self.bytes.get(offset..offset.checked_add(size)?)
+        })
+    }
+}
+
+impl fmt::Display for IccProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (major, minor) = self.version();
+        writeln!(
+            f,
+            "ICC profile v{}.{}, {} bytes, color space '{}'",
+            major,
+            minor,
+            self.bytes.len(),
+            atom::fourcc(&self.color_space())
+        )?;
+        match self.description() {
+            Some(description) => writeln!(f, "  description: {}", description)?,
+            None => writeln!(f, "  description: none")?,
+        }
+        match self.white_point() {
+            Some([x, y, z]) => writeln!(f, "  white point: X {:.4}, Y {:.4}, Z {:.4}", x, y, z)?,
+            None => writeln!(f, "  white point: none")?,
+        }
+        let curves = self.tone_curves();
+        if curves.is_empty() {
+            writeln!(f, "  TRC: none")?;
+        }
+        for (signature, curve) in curves {
+            writeln!(f, "  {}: {}", atom::fourcc(&signature), curve)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ToneCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToneCurve::Identity => write!(f, "identity"),
+            ToneCurve::Gamma(gamma) => write!(f, "gamma {:.4}", gamma),
+            ToneCurve::Table(count) => write!(f, "sampled curve of {} entries", count),
+            ToneCurve::Parametric {
+                function_type,
+                params,
+            } => write!(
+                f,
+                "parametric curve of type {}, gamma {:.4}",
+                function_type, params[0]
+            ),
+        }
+    }
+}
+
+fn be_u32(buf: &[u8], at: usize) -> Option<u32> {
+    buf.get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn s15_fixed16(buf: &[u8], at: usize) -> Option<f64> {
+    be_u32(buf, at).map(|value| value as i32 as f64 / 65536.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::Selection;
+
+    #[test]
+    fn test_icc_profile() {
+        let profile = IccProfile::from_bytes(testing::icc_profile("Display P3", 2.4)).unwrap();
+        assert_eq!(profile.description().as_deref(), Some("Display P3"));
+        let [x, y, z] = profile.white_point().unwrap();
+        assert!((x - 0.9505).abs() < 1e-4 && (y - 1.0).abs() < 1e-4 && (z - 1.0891).abs() < 1e-4);
+        assert_eq!(
+            profile.tone_curves(),
+            [
+                (*b"rTRC", ToneCurve::Gamma(2.3984375)),
+                (*b"gTRC", ToneCurve::Gamma(2.3984375)),
+                (*b"bTRC", ToneCurve::Gamma(2.3984375)),
+            ]
+        );
+
+        let mut truncated = testing::icc_profile("Display P3", 2.4);
+        truncated.pop();
+        assert!(matches!(
+            IccProfile::from_bytes(truncated),
+            Err(Error::InvalidIccProfile(_))
+        ));
+        assert!(matches!(
+            IccProfile::from_bytes(vec![0; 200]),
+            Err(Error::InvalidIccProfile(_))
+        ));
+    }
+
+    #[test]
+    fn test_set_icc_profile() {
+        // The first track embeds an ICC profile, the second has no colr atom.
+        let animation = testing::icc_profile("Animation", 2.2);
+        let tracks = [
+            testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(
+                    b"ap4h",
+                    &[testing::prof_colr_atom(&animation)],
+                )]
+                .to_vec(),
+                samples: [testing::prores_frame(400, [1, 1, 1])].to_vec(),
+                ..Default::default()
+            },
+            testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(b"ap4h", &[])].to_vec(),
+                samples: [testing::prores_frame(400, [1, 1, 1])].to_vec(),
+                ..Default::default()
+            },
+        ];
+        let layout = testing::Layout {
+            moov_first: true,
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) =
+            testing::open_mov("icc_profile.mov", &tracks, layout);
+        let profiles = video.icc_profiles(&mut file, Selection::default()).unwrap();
+        assert_eq!(profiles.len(), 1);
+        assert_eq!((profiles[0].0, profiles[0].1), (1, 1));
+        assert_eq!(profiles[0].2.bytes(), animation);
+        assert_eq!(profiles[0].2.description().as_deref(), Some("Animation"));
+
+        // The embedded profile is replaced by a longer one, and the second track gets
+        // one too.
+        let p3 = IccProfile::from_bytes(testing::icc_profile("Display P3 D65", 2.4)).unwrap();
+        video
+            .set_icc_profile(&mut file, Selection::default(), &p3)
+            .unwrap();
+
+        let decoded = testing::redecode(&file_path, &video);
+        let profiles = decoded
+            .icc_profiles(&mut file, Selection::default())
+            .unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert!(profiles.iter().all(|(_, _, profile)| *profile == p3));
+
+        // The frames moved along with the end of moov and are still found.
+        for track in decoded.tracks.iter() {
+            assert_eq!(
+                track.sample_descriptions[0].colr_atoms[0].size as usize,
+                12 + p3.bytes().len()
+            );
+            assert_eq!(track.frames.len(), 1);
+        }
+    }
+}
@@ -1,57 +1,305 @@
+use std::fmt::{self, Write as _};
 use std::fs::{File, OpenOptions};
 use std::io::{self, Read, Seek, Write};
 
-use aho_corasick::AhoCorasick;
+use crate::atom::AtomHeader;
+use crate::avc::{Codec, DecoderConfiguration, SequenceParameterSet};
+use crate::edit::{Replacement, Shift};
+use crate::sample_table::{Sample, SampleTable};
+use crate::timecode::TimecodeFormat;
+use crate::timing::{EditList, HeaderTiming, SampleRate, TrackDuration, TrakTiming};
 
+pub use crate::aperture::{CleanAperture, TrackAperture};
+pub use crate::aspect::PixelAspectRatio;
+pub use crate::error::Error;
+pub use crate::field::InterlaceMode;
+pub use crate::hdr::{ContentLightLevel, MasteringDisplay};
+pub use crate::icc::{IccProfile, ToneCurve};
+pub use crate::prores::AlphaChannelType;
+pub use crate::timecode::Timecode;
+pub use crate::timing::FrameRate;
+
+mod aperture;
 pub mod args;
+mod aspect;
+mod atom;
+mod avc;
+mod edit;
+mod error;
+mod field;
+mod hdr;
+mod hevc;
+mod icc;
+mod prores;
+mod sample_table;
+#[cfg(test)]
+mod testing;
+mod timecode;
+mod timing;
+
+/// The transfer function index of SMPTE ST 2084 (PQ), the HDR10 one.
+const TRANSFER_FUNCTION_PQ: u16 = 16;
 
-static COLR_ATOM_HEADER: [u8; 4] = [0x63, 0x6f, 0x6c, 0x72]; // "colr"
-static GAMA_ATOM_HEADER: [u8; 4] = [0x67, 0x61, 0x6d, 0x61]; // "gama"
 static PRORES_FRAME_HEADER: [u8; 4] = [0x69, 0x63, 0x70, 0x66]; // "icpf"
 
-#[derive(Default, Debug, PartialEq)]
-enum ColorParameterType {
+/// The size of the beginning of a ProRes frame read by the decoder: the frame size,
+/// the "icpf" tag and the fixed fields of the frame header.
+const PRORES_FRAME_HEADER_SIZE: u32 = 28;
+
+/// Sample description formats of the ProRes family whose samples are "icpf" frames.
+static PRORES_FORMATS: [[u8; 4]; 6] = [
+    *b"apco", // ProRes 422 Proxy
+    *b"apcs", // ProRes 422 LT
+    *b"apcn", // ProRes 422
+    *b"apch", // ProRes 422 HQ
+    *b"ap4h", // ProRes 4444
+    *b"ap4x", // ProRes 4444 XQ
+];
+
+/// The type of a colr atom, as given by its color parameter type field.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ColorParameterType {
+    /// 'nclc': primaries, transfer function and matrix indexes, for video in
+    /// QuickTime files.
+    #[default]
+    Nclc,
+    /// 'nclx': the same indexes plus a full range flag, for video in ISO base media
+    /// (MP4) files.
+    Nclx,
+    /// 'prof': an embedded ICC profile, for print and graphics.
+    Prof,
+    /// 'rICC': an embedded restricted ICC profile (ISO/IEC 23008-12).
+    RIcc,
+    /// Any other type.
+    Unknown([u8; 4]),
+}
+
+impl ColorParameterType {
+    fn from_fourcc(fourcc: [u8; 4]) -> Self {
+        match &fourcc {
+            b"nclc" => ColorParameterType::Nclc,
+            b"nclx" => ColorParameterType::Nclx,
+            b"prof" => ColorParameterType::Prof,
+            b"rICC" => ColorParameterType::RIcc,
+            _ => ColorParameterType::Unknown(fourcc),
+        }
+    }
+
+    fn fourcc(self) -> [u8; 4] {
+        match self {
+            ColorParameterType::Nclc => *b"nclc",
+            ColorParameterType::Nclx => *b"nclx",
+            ColorParameterType::Prof => *b"prof",
+            ColorParameterType::RIcc => *b"rICC",
+            ColorParameterType::Unknown(fourcc) => fourcc,
+        }
+    }
+
+    /// Returns whether the colr atom holds the primaries, transfer function and
+    /// matrix indexes, which is the case of 'nclc' and 'nclx'.
+    fn has_indexes(self) -> bool {
+        matches!(self, ColorParameterType::Nclc | ColorParameterType::Nclx)
+    }
+
+    /// Returns whether the colr atom embeds an ICC profile, which is the case of
+    /// 'prof' and 'rICC'.
+    fn is_icc(self) -> bool {
+        matches!(self, ColorParameterType::Prof | ColorParameterType::RIcc)
+    }
+}
+
+impl fmt::Display for ColorParameterType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", atom::fourcc(&self.fourcc()))
+    }
+}
+
+/// Which colr atom a player honours when a sample description entry has several of
+/// them, e.g. both an 'nclx' and a 'prof' one.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ColrPolicy {
+    /// The first colr atom of the sample entry.
+    #[default]
+    First,
+    /// The last colr atom of the sample entry.
+    Last,
+    /// The first colr atom embedding an ICC profile, or the first one if none does.
+    PreferIcc,
+    /// The first 'nclx' or 'nclc' colr atom, or the first one if there is none.
+    PreferNclx,
+}
+
+/// Which of the colr atoms of a sample description entry a modification applies to.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ColrSelector {
+    /// Every colr atom.
     #[default]
-    Nclc, // for video
-    _Prof, // for print
-    _Unknown,
+    All,
+    /// The colr atoms of the given type.
+    Type(ColorParameterType),
+    /// The colr atom a player would honour according to the given policy.
+    Honoured(ColrPolicy),
 }
 
 #[derive(Default, Debug, PartialEq)]
 struct ColrAtom {
     size: u32,
     offset: u64,
-    _color_parameter_type: ColorParameterType,
+    color_parameter_type: ColorParameterType,
     primary_index: u16,
     transfer_function_index: u16,
     matrix_index: u16,
-    matched: bool,
+    // Whether the video uses the full range of values rather than the limited
+    // ("video") range. Only 'nclx' colr atoms carry this flag.
+    full_range_flag: bool,
 }
 
 #[derive(Default, Debug, PartialEq)]
 struct GamaAtom {
     size: u32,
-    // gama atom candidates
-    offsets: Vec<u64>,
-    // What is the real gama offset? As long as the four bytes before the gama
-    // offset/position candidates are in a specific pattern, i.e. like this: 0x00, 0x00,
-    // 0x00, 0x0c (It indicates the size of the gama atom, 12).
-    the_actual_gama_offset: u64,
+    offset: u64,
     // The actual gama value: for example 2.4, 2.2, etc (It looks like this in
     // hexadecimal form: 0x00, 0x02, 0x66, 0x66).
     gama_value: u32,
     matched: bool,
 }
 
+#[derive(Default, Debug, PartialEq)]
+struct MdcvAtom {
+    size: u32,
+    offset: u64,
+    mastering_display: MasteringDisplay,
+    matched: bool,
+}
+
+/// The 'name' atom of a timecode sample description entry, holding the reel name.
+#[derive(Default, Debug, PartialEq)]
+struct NameAtom {
+    size: u32,
+    offset: u64,
+    reel_name: String,
+    matched: bool,
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct ClliAtom {
+    size: u32,
+    offset: u64,
+    content_light_level: ContentLightLevel,
+    matched: bool,
+}
+
+/// The 'avcC' atom of an H.264 sample description entry, or the 'hvcC' atom of an
+/// HEVC one, whose sequence parameter sets may carry a colour description in their
+/// VUI.
+#[derive(Default, Debug, PartialEq)]
+struct ConfigurationAtom {
+    size: u32,
+    offset: u64,
+    codec: Codec,
+    // The size of the length preceding each NAL unit in the samples.
+    nal_length_size: usize,
+    // Each sequence parameter set of the decoder configuration record, `None` for one
+    // that can't be parsed.
+    sequence_parameter_sets: Vec<Option<SequenceParameterSet>>,
+    matched: bool,
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct ClapAtom {
+    size: u32,
+    offset: u64,
+    clean_aperture: CleanAperture,
+    matched: bool,
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct TaptAtom {
+    size: u32,
+    offset: u64,
+    track_aperture: TrackAperture,
+    matched: bool,
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct FielAtom {
+    size: u32,
+    offset: u64,
+    // The field count followed by the field ordering.
+    fields: [u8; 2],
+    matched: bool,
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct PaspAtom {
+    size: u32,
+    offset: u64,
+    pixel_aspect_ratio: PixelAspectRatio,
+    matched: bool,
+}
+
+/// A sample of a timecode track, holding the frame number of the timecode at the start
+/// of the video it covers.
+#[derive(Default, Debug, Clone, PartialEq)]
+struct TimecodeSample {
+    offset: u64,
+    // The 1-based index of the sample description entry the sample belongs to.
+    sample_description_index: u32,
+    frame_number: u32,
+}
+
+/// A sequence parameter set repeated in a sample of an 'avc3', 'avc4' or 'hev1'
+/// sample description entry, usually at each random access point, which a decoder
+/// honours over the one of the configuration atom.
+#[derive(Default, Debug, Clone, PartialEq)]
+struct InBandSps {
+    // The offset of the NAL unit, after its length, and its size.
+    offset: u64,
+    size: u32,
+    // The 0-based index of the sample holding it, in decoding order.
+    sample_index: u32,
+    // The 1-based index of the sample description entry the sample belongs to.
+    sample_description_index: u32,
+    // `None` if it can't be parsed.
+    sequence_parameter_set: Option<SequenceParameterSet>,
+}
+
 #[derive(Default, Debug, Clone, PartialEq)]
 struct ProResFrame {
     frame_size: u32,
     offset: u64,
+    // The 1-based index of the sample description entry the frame belongs to.
+    sample_description_index: u32,
     _frame_id: f32, // if the value of it is -1.0, it means it's not a icpf frame.
     frame_header_size: u16,
+    bitstream_version: u8,
+    // The four character code of the encoder, e.g. "apl0" for Apple's.
+    encoder_id: [u8; 4],
     color_primaries: u8,
     transfer_characteristic: u8,
     matrix_coefficients: u8,
+    width: u16,
+    height: u16,
+    // 2 for 4:2:2, 3 for 4:4:4.
+    chroma_format: u8,
+    // The display aspect ratio code: 0 unknown, 1 square pixels, 2 4:3, 3 16:9.
+    aspect_ratio_information: u8,
+    // The frame rate code: 0 unknown, then 23.976, 24, 25, 29.97, 30, 50, 59.94, 60,
+    // 100, 119.88 and 120 fps.
+    frame_rate_code: u8,
+    // 0 without alpha, 1 for 8-bit alpha, 2 for 16-bit alpha.
+    alpha_channel_type: u8,
+    // Whether the frame header carries its own quantization matrices rather than
+    // using the default ones.
+    load_luma_quantization_matrix: bool,
+    load_chroma_quantization_matrix: bool,
+    // The interlace mode code: 0 progressive, 1 top field first, 2 bottom field first.
+    interlace_mode: u8,
+    // The number of pictures following the frame header: 1 for a progressive frame,
+    // 2 for an interlaced one (one per field), 0 if they can't be walked.
+    picture_count: u8,
+    // The duration of the sample holding the frame, in the time scale of the media.
+    duration: u32,
 }
 
 impl ProResFrame {
@@ -59,236 +307,3266 @@ impl ProResFrame {
         Self {
             offset: 0,
             frame_size: 0,
+            sample_description_index: 0,
             _frame_id: 0.0,
             frame_header_size: 0,
+            bitstream_version: 0,
+            encoder_id: [0; 4],
             color_primaries: 0,
             transfer_characteristic: 0,
             matrix_coefficients: 0,
+            width: 0,
+            height: 0,
+            chroma_format: 0,
+            aspect_ratio_information: 0,
+            frame_rate_code: 0,
+            alpha_channel_type: 0,
+            load_luma_quantization_matrix: false,
+            load_chroma_quantization_matrix: false,
+            interlace_mode: 0,
+            picture_count: 0,
+            duration: 0,
+        }
+    }
+}
+
+/// Which tracks of the file a modification applies to.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum TrackSelector {
+    /// Every video track of the file, or every timecode track when editing timecode.
+    #[default]
+    AllVideo,
+    /// The track with the given track ID, as stored in its `tkhd` atom.
+    Id(u32),
+    /// The track at the given position in moov, starting from 0 (all tracks are
+    /// counted, not only the video ones).
+    Index(usize),
+}
+
+/// Which tracks, and which sample description entries of these tracks, a
+/// modification applies to.
+///
+/// The methods of [`Video`] taking a `Selection` resolve it before anything is
+/// written, and fail with:
+///
+/// - `Error::TrackIdNotFound` or `Error::TrackIndexNotFound` if no track has the ID
+///   or the index given by `tracks`;
+/// - `Error::NotAVideoTrack` if that track isn't a video one, or `Error::NoVideoTrack`
+///   if the file has no video track at all. The timecode methods
+///   ([`Video::set_reel_name`], [`Video::remove_reel_name`] and
+///   [`Video::set_start_timecode`]) select timecode tracks instead, and fail with
+///   `Error::NotATimecodeTrack` or `Error::NoTimecodeTrack`;
+/// - `Error::VideoSampleEntryNotFound` if a selected track has no sample description
+///   entry, or `Error::SampleDescriptionNotFound` if it has none at
+///   `sample_description_index`, for the methods working on these entries.
+///
+/// Like any other error of these methods, `Error::Io` may also be returned if the file
+/// can't be read or written.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Selection {
+    pub tracks: TrackSelector,
+    /// The 1-based index of the sample description entry in `stsd`, as referenced by
+    /// `stsc`. `None` selects every entry.
+    pub sample_description_index: Option<u32>,
+    /// The colr atoms of these sample description entries.
+    pub colr: ColrSelector,
+}
+
+/// What a frame rate conform does with the audio tracks.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum AudioConform {
+    /// The audio tracks are left as they are: they keep playing at their original
+    /// speed, and drift from the conformed video.
+    #[default]
+    Leave,
+    /// The time scale of the audio media is scaled like the video, so that the audio
+    /// plays as much faster or slower, and its pitch changes accordingly. So is the
+    /// sample rate of its sample descriptions, e.g. 48048 Hz instead of 48000 Hz from
+    /// 23.976 fps to 24 fps, but not the one a compressed stream may hold in its own
+    /// configuration, such as the esds atom of AAC audio.
+    Rescale,
+}
+
+/// A sample description entry of `stsd`, with the atoms nested in it.
+#[derive(Default, Debug, PartialEq)]
+struct SampleDescription {
+    // The 1-based index of the entry in stsd.
+    index: u32,
+    // The sample entry atom, whose type is the data format of the samples, e.g.
+    // "apcn" or "avc1".
+    sample_entry: AtomHeader,
+    // The width and height of a video sample entry, in pixels.
+    width: u16,
+    height: u16,
+    // The pixel depth of a video sample entry: 24 for color, 32 for color with alpha.
+    depth: u16,
+    // The colr atoms of the sample entry, in the order they appear in it. There is
+    // usually one, but some writers add both an 'nclx' and an ICC profile one.
+    colr_atoms: Vec<ColrAtom>,
+    gama_atom: GamaAtom,
+    mdcv_atom: MdcvAtom,
+    clli_atom: ClliAtom,
+    pasp_atom: PaspAtom,
+    fiel_atom: FielAtom,
+    clap_atom: ClapAtom,
+    configuration_atom: ConfigurationAtom,
+    // The reel name of a timecode sample entry.
+    name_atom: NameAtom,
+    // The meaning of the samples of a timecode sample entry.
+    timecode_format: TimecodeFormat,
+}
+
+impl SampleDescription {
+    /// Checks that every sequence parameter set of the avcC or hvcC atom, if any,
+    /// could be parsed, so that its video signal type can be rewritten.
+    fn check_sequence_parameter_sets(&self, track_id: u32) -> Result<(), Error> {
+        if self
+            .configuration_atom
+            .sequence_parameter_sets
+            .iter()
+            .any(Option::is_none)
+        {
+            return Err(Error::UnsupportedSequenceParameterSet { track_id });
+        }
+        Ok(())
+    }
+
+    /// Returns the colr atom a player would honour according to `policy`.
+    fn honoured_colr_atom(&self, policy: ColrPolicy) -> Option<&ColrAtom> {
+        let preferred = match policy {
+            ColrPolicy::First => None,
+            ColrPolicy::Last => self.colr_atoms.last(),
+            ColrPolicy::PreferIcc => self
+                .colr_atoms
+                .iter()
+                .find(|colr_atom| colr_atom.color_parameter_type.is_icc()),
+            ColrPolicy::PreferNclx => self
+                .colr_atoms
+                .iter()
+                .find(|colr_atom| colr_atom.color_parameter_type.has_indexes()),
+        };
+        preferred.or(self.colr_atoms.first())
+    }
+
+    /// Returns the colr atoms designated by `selector`.
+    fn selected_colr_atoms(&self, selector: ColrSelector) -> Vec<&ColrAtom> {
+        match selector {
+            ColrSelector::All => self.colr_atoms.iter().collect(),
+            ColrSelector::Type(color_parameter_type) => self
+                .colr_atoms
+                .iter()
+                .filter(|colr_atom| colr_atom.color_parameter_type == color_parameter_type)
+                .collect(),
+            ColrSelector::Honoured(policy) => self.honoured_colr_atom(policy).into_iter().collect(),
+        }
+    }
+
+    /// Returns the colr atoms designated by `selector` that are of type 'nclc' or
+    /// 'nclx'.
+    fn selected_colr_atoms_with_indexes(&self, selector: ColrSelector) -> Vec<&ColrAtom> {
+        self.selected_colr_atoms(selector)
+            .into_iter()
+            .filter(|colr_atom| colr_atom.color_parameter_type.has_indexes())
+            .collect()
+    }
+
+    /// Constructs a colr atom and adds it to the colr atoms of the sample description.
+    /// Its offset, size and color parameter type are set, and for the 'nclc' and
+    /// 'nclx' types, its primary index, transfer function index and matrix index, plus
+    /// the full range flag of 'nclx'. A colr atom too short to hold them is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - A mutable reference to a `File` object.
+    /// * `header` - The header of the colr atom.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` indicating whether the operation was successful or not.
+    fn construct_colr_atom(&mut self, file: &mut File, header: &AtomHeader) -> io::Result<()> {
+        let Some(type_buf) = atom::read_payload::<4>(file, header)? else {
+            return Ok(());
+        };
+        let mut colr_atom = ColrAtom {
+            offset: header.offset,
+            size: header.size as u32,
+            color_parameter_type: ColorParameterType::from_fourcc(type_buf),
+            ..Default::default()
+        };
+
+        if colr_atom.color_parameter_type.has_indexes() {
+            // The color parameter type, then the three indexes.
+            let Some(payload) = atom::read_payload::<10>(file, header)? else {
+                return Ok(());
+            };
+            colr_atom.primary_index = u16::from_be_bytes([payload[4], payload[5]]);
+            colr_atom.transfer_function_index = u16::from_be_bytes([payload[6], payload[7]]);
+            colr_atom.matrix_index = u16::from_be_bytes([payload[8], payload[9]]);
+        }
+
+        if colr_atom.color_parameter_type == ColorParameterType::Nclx {
+            // The full range flag is the most significant bit of the last byte, the
+            // other 7 bits are reserved.
+            let Some(payload) = atom::read_payload::<11>(file, header)? else {
+                return Ok(());
+            };
+            colr_atom.full_range_flag = payload[10] & 0x80 != 0;
+        }
+
+        self.colr_atoms.push(colr_atom);
+
+        Ok(())
+    }
+
+    /// Constructs a gama atom and sets its offset, size and gama value. A gama atom too
+    /// short to hold the value is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - A mutable reference to a `File` instance.
+    /// * `header` - The header of the gama atom.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an `io::Result` in case of an I/O error occurring when
+    /// seeking file or read bytes from file.
+    fn construct_gama_atom(&mut self, file: &mut File, header: &AtomHeader) -> io::Result<()> {
+        if let Some(value_buf) = atom::read_payload(file, header)? {
+            self.gama_atom = GamaAtom {
+                size: header.size as u32,
+                offset: header.offset,
+                gama_value: u32::from_be_bytes(value_buf),
+                matched: true,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub struct Track {
+    track_id: u32,
+    // The handler type of the media, e.g. "vide" for video or "tmcd" for timecode.
+    handler_type: [u8; 4],
+    sample_descriptions: Vec<SampleDescription>,
+    frames: Vec<ProResFrame>,
+    frame_count: i64,
+    // The track aperture mode dimensions, found in the trak atom itself.
+    tapt_atom: TaptAtom,
+    // The number of time units per second of the media, from its mdhd atom.
+    timescale: u32,
+    // The samples of a timecode track, usually a single one for the whole video.
+    timecode_samples: Vec<TimecodeSample>,
+    // The sequence parameter sets found in the samples of a video track.
+    in_band_sps: Vec<InBandSps>,
+}
+
+impl Track {
+    /// Returns the track ID, as stored in the `tkhd` atom.
+    pub fn track_id(&self) -> u32 {
+        self.track_id
+    }
+
+    /// Returns the handler type of the media, e.g. "vide" or "tmcd".
+    pub fn handler_type(&self) -> [u8; 4] {
+        self.handler_type
+    }
+
+    /// Returns the data format of each sample description entry, e.g. "apcn".
+    pub fn formats(&self) -> Vec<[u8; 4]> {
+        self.sample_descriptions
+            .iter()
+            .map(|description| description.sample_entry.kind)
+            .collect()
+    }
+
+    /// Returns whether this is a video track.
+    pub fn is_video(&self) -> bool {
+        self.handler_type == atom::VIDEO_HANDLER
+    }
+
+    /// Returns the timecode of the first frame of a timecode track, or `None` if it has
+    /// no sample or its sample description isn't understood (e.g. a tape counter).
+    pub fn start_timecode(&self) -> Option<Timecode> {
+        let sample = self.timecode_samples.first()?;
+        let format = self.timecode_format(sample.sample_description_index)?;
+        Some(format.timecode(sample.frame_number))
+    }
+
+    /// Returns the timecode format of the sample description entry at the 1-based
+    /// `sample_description_index`, if it's one this program can count frames in.
+    fn timecode_format(&self, sample_description_index: u32) -> Option<TimecodeFormat> {
+        self.sample_descriptions
+            .iter()
+            .find(|description| description.index == sample_description_index)
+            .map(|description| description.timecode_format)
+            .filter(|format| format.is_supported())
+    }
+
+    /// Checks that every sequence parameter set of a sample description entry can be
+    /// parsed: those of its configuration atom and those repeated in its samples.
+    fn check_sequence_parameter_sets(&self, description: &SampleDescription) -> Result<(), Error> {
+        description.check_sequence_parameter_sets(self.track_id)?;
+        if self.in_band_sps.iter().any(|sps| {
+            sps.sample_description_index == description.index
+                && sps.sequence_parameter_set.is_none()
+        }) {
+            return Err(Error::UnsupportedSequenceParameterSet {
+                track_id: self.track_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Walks the NAL units of a sample, each preceded by its length, and records its
+    /// sequence parameter sets. The walk stops at a length running past the end of the
+    /// sample, leaving the rest of it alone.
+    fn construct_in_band_sps(
+        &mut self,
+        file: &mut File,
+        sample: &Sample,
+        sample_index: u32,
+        codec: Codec,
+        length_size: usize,
+    ) -> io::Result<()> {
+        let end = sample.offset + sample.size as u64;
+        let mut offset = sample.offset;
+        let mut prefix_buf = [0; 6];
+
+        while offset + (length_size + codec.nal_header_size()) as u64 <= end {
+            // The length of the NAL unit, then its header.
+            let prefix = &mut prefix_buf[..length_size + codec.nal_header_size()];
+            file.seek(io::SeekFrom::Start(offset))?;
+            file.read_exact(prefix)?;
+            let size = prefix[..length_size]
+                .iter()
+                .fold(0u64, |size, &byte| size << 8 | byte as u64);
+            let nal_offset = offset + length_size as u64;
+            if nal_offset + size > end {
+                break;
+            }
+
+            if codec.is_sps(&prefix[length_size..]) {
+                let mut nal = vec![0; size as usize];
+                file.seek(io::SeekFrom::Start(nal_offset))?;
+                file.read_exact(&mut nal)?;
+                self.in_band_sps.push(InBandSps {
+                    offset: nal_offset,
+                    size: size as u32,
+                    sample_index,
+                    sample_description_index: sample.sample_description_index,
+                    sequence_parameter_set: SequenceParameterSet::parse(codec, &nal),
+                });
+            }
+            offset = nal_offset + size;
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether a colr atom was found in every video sample description.
+    pub fn has_colr_atom(&self) -> bool {
+        self.sample_descriptions
+            .iter()
+            .all(|description| !description.colr_atoms.is_empty())
+    }
+
+    /// Returns whether a gama atom was found in every video sample description.
+    pub fn has_gama_atom(&self) -> bool {
+        self.sample_descriptions
+            .iter()
+            .all(|description| description.gama_atom.matched)
+    }
+
+    /// Moves the offsets of what was read from the essence as `shift` says.
+    fn relocate_essence(&mut self, shift: Shift) {
+        for frame in self.frames.iter_mut() {
+            frame.offset = shift.apply(frame.offset);
+        }
+        for in_band_sps in self.in_band_sps.iter_mut() {
+            in_band_sps.offset = shift.apply(in_band_sps.offset);
+        }
+        for sample in self.timecode_samples.iter_mut() {
+            sample.offset = shift.apply(sample.offset);
+        }
+    }
+
+    /// Takes what a previous decode of the track read from the essence. The frames
+    /// get the durations and sample descriptions of the samples now at their offsets,
+    /// as the sample tables may have been rewritten.
+    fn reuse_essence(&mut self, previous: Track, samples: &[Sample]) {
+        let mut samples = samples.iter();
+        self.frames = previous
+            .frames
+            .into_iter()
+            .filter_map(|mut frame| {
+                let sample = samples.find(|sample| sample.offset == frame.offset)?;
+                frame.duration = sample.duration;
+                frame.sample_description_index = sample.sample_description_index;
+                Some(frame)
+            })
+            .collect();
+        self.frame_count = self.frames.len() as i64;
+        self.in_band_sps = previous.in_band_sps;
+        self.timecode_samples = previous.timecode_samples;
+    }
+
+    /// Constructs the ProRes frame of a sample if it starts with a frame header.
+    fn probe_prores_frame(&mut self, file: &mut File, sample: &Sample) -> io::Result<()> {
+        let mut tag_buf = [0; 4];
+        file.seek(io::SeekFrom::Start(sample.offset + 4))?;
+        file.read_exact(&mut tag_buf)?;
+        if tag_buf == PRORES_FRAME_HEADER {
+            self.construct_prores_frame(
+                file,
+                sample.offset,
+                sample.sample_description_index,
+                sample.duration,
+            )?;
         }
+        Ok(())
+    }
+
+    /// Constructs a ProRes frame from a file at a given offset.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - A mutable reference to a `File` object.
+    /// * `offset` - The offset in bytes from the start of the file where the frame is
+    ///   located.
+    /// * `sample_description_index` - The 1-based index of the sample description
+    ///   entry of the frame.
+    /// * `duration` - The duration of the sample holding the frame, in the time scale
+    ///   of the media.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an `io::Result` in case of any I/O errors that occur while
+    /// reading from the file.
+    fn construct_prores_frame(
+        &mut self,
+        file: &mut File,
+        offset: u64,
+        sample_description_index: u32,
+        duration: u32,
+    ) -> io::Result<()> {
+        let mut frame = ProResFrame::new();
+        frame.offset = offset;
+        frame.sample_description_index = sample_description_index;
+        frame.duration = duration;
+
+        // The frame size and the "icpf" tag, then the frame header: its size, a
+        // reserved byte, the bitstream version, the encoder ID, the dimensions and the
+        // fields below, followed by the quantization matrices it loads.
+        let mut header_buf = [0; PRORES_FRAME_HEADER_SIZE as usize];
+        file.seek(io::SeekFrom::Start(frame.offset))?;
+        file.read_exact(&mut header_buf)?;
+        frame.frame_size =
+            u32::from_be_bytes([header_buf[0], header_buf[1], header_buf[2], header_buf[3]]);
+        frame.frame_header_size = u16::from_be_bytes([header_buf[8], header_buf[9]]);
+        frame.bitstream_version = header_buf[11];
+        frame.encoder_id = [
+            header_buf[12],
+            header_buf[13],
+            header_buf[14],
+            header_buf[15],
+        ];
+        frame.width = u16::from_be_bytes([header_buf[16], header_buf[17]]);
+        frame.height = u16::from_be_bytes([header_buf[18], header_buf[19]]);
+
+        // chroma_format (2 bits), reserved (2 bits), interlace_mode (2 bits), reserved
+        // (2 bits).
+        frame.chroma_format = header_buf[20] >> 6;
+        frame.interlace_mode = header_buf[20] >> 2 & 0x03;
+        // aspect_ratio_information (4 bits), frame_rate_code (4 bits).
+        frame.aspect_ratio_information = header_buf[21] >> 4;
+        frame.frame_rate_code = header_buf[21] & 0x0F;
+        frame.color_primaries = header_buf[22];
+        frame.transfer_characteristic = header_buf[23];
+        frame.matrix_coefficients = header_buf[24];
+        // reserved (4 bits), alpha_channel_type (4 bits), then a reserved byte and
+        // reserved (6 bits), load_luma_quantization_matrix (1 bit),
+        // load_chroma_quantization_matrix (1 bit).
+        frame.alpha_channel_type = header_buf[25] & 0x0F;
+        frame.load_luma_quantization_matrix = header_buf[27] & 0x02 != 0;
+        frame.load_chroma_quantization_matrix = header_buf[27] & 0x01 != 0;
+
+        // The pictures follow the frame header, each starting with the size of its
+        // header (in the 5 high bits) and its own size. Stuffing bytes may follow them.
+        let frame_end = frame.offset + frame.frame_size as u64;
+        let mut picture_offset = frame.offset + 8 + frame.frame_header_size as u64;
+        while frame.picture_count < 2 && picture_offset + 5 <= frame_end {
+            let mut picture_header_buf = [0; 5];
+            file.seek(io::SeekFrom::Start(picture_offset))?;
+            file.read_exact(&mut picture_header_buf)?;
+            let picture_header_size = (picture_header_buf[0] >> 3) as u64;
+            let picture_size = u32::from_be_bytes([
+                picture_header_buf[1],
+                picture_header_buf[2],
+                picture_header_buf[3],
+                picture_header_buf[4],
+            ]) as u64;
+            if picture_header_size == 0
+                || picture_size < picture_header_size
+                || picture_offset + picture_size > frame_end
+            {
+                break;
+            }
+            frame.picture_count += 1;
+            picture_offset += picture_size;
+        }
+
+        self.frames.push(frame);
+        self.frame_count += 1;
+
+        Ok(())
+    }
+}
+
+/// The new timing of the media of a video track whose samples all last a frame,
+/// computed before anything is written.
+struct MediaTiming {
+    header_timing: HeaderTiming,
+    // The stts atom giving every sample the same duration, in a single entry.
+    stts_atom: Vec<u8>,
+    // How replacing the stts atom changes the length of the file.
+    stts_growth: edit::Growth,
+}
+
+/// The sequence parameter sets of the samples, rewritten in memory.
+struct InBandSpsRewrite {
+    // The NAL units to replace in each top-level atom, each as its offset, its length
+    // and the bytes replacing it, in ascending order.
+    groups: Vec<(AtomHeader, Vec<Replacement>)>,
+    // The track index, the sample index and the change of size of each sample.
+    size_changes: Vec<(usize, usize, i64)>,
+}
+
+impl InBandSpsRewrite {
+    /// Returns the indexes of the tracks whose samples change size.
+    fn resized_tracks(&self) -> Vec<usize> {
+        let mut track_indexes: Vec<usize> =
+            self.size_changes.iter().map(|change| change.0).collect();
+        track_indexes.sort_unstable();
+        track_indexes.dedup();
+        track_indexes
     }
 }
 
-#[derive(Default, Debug, PartialEq)]
-pub struct Video {
-    colr_atom: ColrAtom,
-    gama_atom: GamaAtom,
-    frames: Vec<ProResFrame>,
-    frame_count: i64,
-}
+/// The new timing of a track conformed to another frame rate, computed before
+/// anything is written.
+struct ConformedTrack {
+    track_index: usize,
+    timing: TrakTiming,
+    header_timing: HeaderTiming,
+    track_duration: TrackDuration,
+    edit_list: Option<EditList>,
+    // The new time-to-sample table of a timecode track, of as many entries as before.
+    stts_payload: Option<Vec<u8>>,
+    // The stts atom replacing the one of a video track, and how it changes the length
+    // of the file, see `MediaTiming`.
+    stts_atom: Option<Vec<u8>>,
+    stts_growth: Option<edit::Growth>,
+    // The sample description entries of a timecode track, with their new format.
+    tmcd_entries: Vec<(AtomHeader, TimecodeFormat)>,
+    // The new frame number of each sample of a timecode track.
+    frame_numbers: Vec<u32>,
+    // The sample description entries of an audio track, with their new sample rate.
+    sample_rates: Vec<(AtomHeader, SampleRate)>,
+}
+
+/// A QuickTime or MP4 file, decoded from its moov atom: its tracks, their sample
+/// description entries and the ProRes frames of the video tracks.
+///
+/// The methods modifying the file do it in place, and keep the description in step
+/// with it. An atom inserted into, replaced in or removed from moov changes the size
+/// of every atom containing it: a `free` or `skip` atom after it absorbs the
+/// difference when it is big enough, otherwise the rest of the file is shifted and the
+/// chunk offsets are fixed up.
+#[derive(Default, Debug, PartialEq)]
+pub struct Video {
+    // The major brand of the ftyp atom, e.g. "qt  " for QuickTime or "isom" and
+    // "mp42" for MP4. Left zeroed when the file has no ftyp atom.
+    major_brand: [u8; 4],
+    tracks: Vec<Track>,
+}
+
+impl Video {
+    /// Returns the tracks of the file, in the order of their `trak` atoms in moov.
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Walks a `trak` atom down to its sample descriptions and constructs the
+    /// corresponding track. For a video track, the colr and gama atoms found in its
+    /// sample entries and the ProRes frames found in its samples are constructed too.
+    ///
+    /// Only the atoms that really are children of a video sample entry (e.g.
+    /// `moov/trak/mdia/minf/stbl/stsd/apcn/colr`) are taken into account, so the same
+    /// four bytes appearing in the essence or in another track are never mistaken for
+    /// an atom.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an `io::Result` in case of an I/O error or if the atom
+    /// hierarchy is malformed.
+    fn decode_trak(
+        &mut self,
+        file: &mut File,
+        trak: &AtomHeader,
+        previous: Option<Track>,
+    ) -> io::Result<()> {
+        let mut track = Track::default();
+
+        let trak_children = atom::read_children_of(file, trak)?;
+        if let Some(tkhd) = atom::find(&trak_children, atom::TKHD) {
+            // tkhd: version (1 byte) and flags (3 bytes), creation and modification
+            // times (4 bytes each, 8 bytes each in version 1), then the track ID.
+            let mut version_buf = [0; 1];
+            file.seek(io::SeekFrom::Start(tkhd.body_offset()))?;
+            file.read_exact(&mut version_buf)?;
+            let track_id_offset = if version_buf[0] == 1 { 20 } else { 12 };
+
+            let mut track_id_buf = [0; 4];
+            file.seek(io::SeekFrom::Start(tkhd.body_offset() + track_id_offset))?;
+            file.read_exact(&mut track_id_buf)?;
+            track.track_id = u32::from_be_bytes(track_id_buf);
+        }
+
+        if let Some(tapt) = atom::find(&trak_children, atom::TAPT) {
+            track.construct_tapt_atom(file, &tapt)?;
+        }
+
+        let mdia_children = match atom::find(&trak_children, atom::MDIA) {
+            Some(mdia) => atom::read_children_of(file, &mdia)?,
+            None => Vec::new(),
+        };
+        if let Some(hdlr) = atom::find(&mdia_children, atom::HDLR) {
+            // hdlr: version and flags (4 bytes), component type (4 bytes), then the
+            // component subtype which is the handler type of the media.
+            file.seek(io::SeekFrom::Start(hdlr.body_offset() + 8))?;
+            file.read_exact(&mut track.handler_type)?;
+        }
+        if let Some(mdhd) = atom::find(&mdia_children, atom::MDHD) {
+            track.timescale = HeaderTiming::read(file, &mdhd)?.timescale;
+        }
+
+        let minf_children = match atom::find(&mdia_children, atom::MINF) {
+            Some(minf) => atom::read_children_of(file, &minf)?,
+            None => Vec::new(),
+        };
+        let stbl_children = match atom::find(&minf_children, atom::STBL) {
+            Some(stbl) => atom::read_children_of(file, &stbl)?,
+            None => Vec::new(),
+        };
+
+        if let Some(stsd) = atom::find(&stbl_children, atom::STSD) {
+            for (index, entry) in atom::read_children_of(file, &stsd)?.into_iter().enumerate() {
+                let mut description = SampleDescription {
+                    index: index as u32 + 1,
+                    sample_entry: entry,
+                    ..Default::default()
+                };
+                if track.is_video() {
+                    // Video sample entry: reserved (6 bytes), data reference index
+                    // (2 bytes), version, revision level, vendor, temporal and spatial
+                    // qualities (16 bytes), then the width and the height.
+                    let mut dimensions_buf = [0; 4];
+                    file.seek(io::SeekFrom::Start(entry.body_offset() + 24))?;
+                    file.read_exact(&mut dimensions_buf)?;
+                    description.width = u16::from_be_bytes([dimensions_buf[0], dimensions_buf[1]]);
+                    description.height = u16::from_be_bytes([dimensions_buf[2], dimensions_buf[3]]);
+
+                    // Then the resolutions, data size, frame count and compressor name
+                    // (46 bytes) before the depth.
+                    let mut depth_buf = [0; 2];
+                    file.seek(io::SeekFrom::Start(entry.body_offset() + 74))?;
+                    file.read_exact(&mut depth_buf)?;
+                    description.depth = u16::from_be_bytes(depth_buf);
+
+                    for child in atom::read_sample_entry_children(file, &entry)? {
+                        match child.kind {
+                            atom::COLR => description.construct_colr_atom(file, &child)?,
+                            atom::GAMA => description.construct_gama_atom(file, &child)?,
+                            atom::MDCV => description.construct_mdcv_atom(file, &child)?,
+                            atom::CLLI => description.construct_clli_atom(file, &child)?,
+                            atom::PASP => description.construct_pasp_atom(file, &child)?,
+                            atom::FIEL => description.construct_fiel_atom(file, &child)?,
+                            atom::CLAP => description.construct_clap_atom(file, &child)?,
+                            atom::AVCC => description.construct_configuration_atom(
+                                file,
+                                &child,
+                                Codec::Avc,
+                            )?,
+                            atom::HVCC => description.construct_configuration_atom(
+                                file,
+                                &child,
+                                Codec::Hevc,
+                            )?,
+                            _ => {}
+                        }
+                    }
+                } else if entry.kind == atom::TMCD {
+                    // Timecode sample entry: reserved (6 bytes) and data reference
+                    // index (2 bytes) before the fields of the format.
+                    let mut format_buf = [0; 17];
+                    file.seek(io::SeekFrom::Start(entry.body_offset() + 8))?;
+                    file.read_exact(&mut format_buf)?;
+                    description.timecode_format = TimecodeFormat::from_bytes(&format_buf);
+
+                    if let Some(name) =
+                        atom::find(&atom::read_sample_entry_children(file, &entry)?, atom::NAME)
+                    {
+                        description.construct_name_atom(file, &name)?;
+                    }
+                }
+                track.sample_descriptions.push(description);
+            }
+        }
+
+        // After an edit of moov, what was read from the essence is still valid.
+        if let Some(previous) = previous {
+            let samples = SampleTable::read(file, &stbl_children)?.samples();
+            track.reuse_essence(previous, &samples);
+            self.tracks.push(track);
+            return Ok(());
+        }
+
+        // Every sample of the track is located through the sample tables. Only the
+        // samples of a ProRes sample description that can hold a frame header are
+        // probed, and those whose "icpf" tag is present at offset + 4 are ProRes
+        // frames. A sample cut short by the end of the file is not one.
+        if track.is_video() {
+            let samples = SampleTable::read(file, &stbl_children)?.samples();
+            for sample in samples.iter() {
+                let is_prores = track.sample_descriptions.iter().any(|description| {
+                    description.index == sample.sample_description_index
+                        && PRORES_FORMATS.contains(&description.sample_entry.kind)
+                });
+                if !is_prores || sample.size < PRORES_FRAME_HEADER_SIZE {
+                    continue;
+                }
+                match track.probe_prores_frame(file, sample) {
+                    Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {}
+                    result => result?,
+                }
+            }
+
+            // The samples of the formats that may repeat the parameter sets are made of
+            // NAL units, each preceded by its length.
+            for (sample_index, sample) in samples.iter().enumerate() {
+                let Some(description) = track
+                    .sample_descriptions
+                    .iter()
+                    .find(|description| description.index == sample.sample_description_index)
+                else {
+                    continue;
+                };
+                let configuration_atom = &description.configuration_atom;
+                if !configuration_atom.matched
+                    || !Codec::has_parameter_sets_in_samples(description.sample_entry.kind)
+                {
+                    continue;
+                }
+                let (codec, nal_length_size) =
+                    (configuration_atom.codec, configuration_atom.nal_length_size);
+                track.construct_in_band_sps(
+                    file,
+                    sample,
+                    sample_index as u32,
+                    codec,
+                    nal_length_size,
+                )?;
+            }
+        }
+
+        // A timecode sample is the frame number of the timecode, as a 32-bit integer.
+        if track.handler_type == atom::TIMECODE_HANDLER {
+            let sample_table = SampleTable::read(file, &stbl_children)?;
+            for sample in sample_table.samples() {
+                if sample.size != 4 {
+                    continue;
+                }
+                let mut frame_number_buf = [0; 4];
+                file.seek(io::SeekFrom::Start(sample.offset))?;
+                file.read_exact(&mut frame_number_buf)?;
+                track.timecode_samples.push(TimecodeSample {
+                    offset: sample.offset,
+                    sample_description_index: sample.sample_description_index,
+                    frame_number: u32::from_be_bytes(frame_number_buf),
+                });
+            }
+        }
+
+        self.tracks.push(track);
+
+        Ok(())
+    }
+
+    /// Decodes a video file and constructs the corresponding atoms and frames.
+    ///
+    /// The file is parsed as a tree of atoms (ftyp, wide, mdat, moov, trak, mdia,
+    /// minf, stbl, stsd...) following the size and type of each atom. The essence in
+    /// mdat is never scanned: ProRes frames are located through the sample tables
+    /// and only their headers are read, as are the NAL unit headers of the samples
+    /// that may repeat their sequence parameter sets ('avc3', 'avc4', 'hev1').
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - A string slice that holds the path to the video file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use atom_modifier::Video;
+    ///
+    /// let mut video = Video::default();
+    /// video.decode("tests/footages/1-1-1_2frames_prores422.mov").unwrap();
+    /// ```
+    ///
+    /// # Notes
+    ///
+    /// This method only needs read access to the file.
+    pub fn decode(&mut self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = OpenOptions::new().read(true).open(file_path)?;
+        self.decode_file(&mut file, Vec::new())?;
+
+        Ok(())
+    }
+
+    /// Decodes an already opened video file. See [`Video::decode`].
+    ///
+    /// The essence is read unless `previous` has the tracks of a previous decode of
+    /// the file, in the same order, whose frames, in-band sequence parameter sets and
+    /// timecode samples are then reused.
+    fn decode_file(&mut self, file: &mut File, previous: Vec<Track>) -> io::Result<()> {
+        let mut previous = previous.into_iter();
+        let file_size = file.metadata()?.len();
+
+        let top_level = atom::read_children(file, 0, file_size)?;
+        if let Some(ftyp) = atom::find(&top_level, atom::FTYP) {
+            file.seek(io::SeekFrom::Start(ftyp.body_offset()))?;
+            file.read_exact(&mut self.major_brand)?;
+        }
+        for moov in top_level.iter().filter(|a| a.kind == atom::MOOV) {
+            for trak in atom::read_children_of(file, moov)?
+                .iter()
+                .filter(|a| a.kind == atom::TRAK)
+            {
+                self.decode_trak(file, trak, previous.next())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the file is a QuickTime movie, rather than an ISO base media
+    /// (MP4) file. Files without an ftyp atom are old QuickTime movies.
+    fn is_quicktime(&self) -> bool {
+        self.major_brand == *b"qt  " || self.major_brand == [0; 4]
+    }
+
+    /// Decodes the file again from scratch, after its essence has been modified.
+    fn redecode(&mut self, file: &mut File) -> io::Result<()> {
+        *self = Video::default();
+        self.decode_file(file, Vec::new())
+    }
+
+    /// Decodes moov again after a splice in it, which may have moved the essence as
+    /// `shift` says but didn't change it. The frame headers and the other samples read
+    /// from the essence are moved rather than read again.
+    fn redecode_moov(&mut self, file: &mut File, shift: Shift) -> io::Result<()> {
+        let mut previous = std::mem::take(&mut self.tracks);
+        for track in previous.iter_mut() {
+            track.relocate_essence(shift);
+        }
+        *self = Video::default();
+        self.decode_file(file, previous)
+    }
+
+    /// Returns the indexes in `self.tracks` of the tracks designated by `selector`,
+    /// which must all be video tracks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the selected track doesn't exist or isn't a video track,
+    /// or if the file has no video track at all.
+    fn select_video_tracks(&self, selector: TrackSelector) -> Result<Vec<usize>, Error> {
+        let Some(index) = self.designated_track(selector)? else {
+            let indexes: Vec<usize> = (0..self.tracks.len())
+                .filter(|&i| self.tracks[i].is_video())
+                .collect();
+            if indexes.is_empty() {
+                return Err(Error::NoVideoTrack);
+            }
+            return Ok(indexes);
+        };
+
+        let track = &self.tracks[index];
+        if !track.is_video() {
+            return Err(Error::NotAVideoTrack {
+                track_id: track.track_id,
+                handler_type: track.handler_type,
+            });
+        }
+
+        Ok(vec![index])
+    }
+
+    /// Returns the indexes in `self.tracks` of the timecode tracks designated by
+    /// `selector`: every timecode track by default, as `TrackSelector::AllVideo` is
+    /// the default selector, or the track with the given ID or index, which must be a
+    /// timecode track.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the selected track doesn't exist or isn't a timecode
+    /// track, or if the file has no timecode track at all.
+    fn select_timecode_tracks(&self, selector: TrackSelector) -> Result<Vec<usize>, Error> {
+        let is_timecode = |track: &Track| track.handler_type == atom::TIMECODE_HANDLER;
+        let Some(index) = self.designated_track(selector)? else {
+            let indexes: Vec<usize> = (0..self.tracks.len())
+                .filter(|&i| is_timecode(&self.tracks[i]))
+                .collect();
+            if indexes.is_empty() {
+                return Err(Error::NoTimecodeTrack);
+            }
+            return Ok(indexes);
+        };
+
+        let track = &self.tracks[index];
+        if !is_timecode(track) {
+            return Err(Error::NotATimecodeTrack {
+                track_id: track.track_id,
+                handler_type: track.handler_type,
+            });
+        }
+
+        Ok(vec![index])
+    }
+
+    /// Returns the index in `self.tracks` of the track designated by its ID or index,
+    /// or `None` for `TrackSelector::AllVideo`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if no track has that ID or index.
+    fn designated_track(&self, selector: TrackSelector) -> Result<Option<usize>, Error> {
+        match selector {
+            TrackSelector::AllVideo => Ok(None),
+            TrackSelector::Id(track_id) => self
+                .tracks
+                .iter()
+                .position(|track| track.track_id == track_id)
+                .map(Some)
+                .ok_or(Error::TrackIdNotFound(track_id)),
+            TrackSelector::Index(index) => {
+                if index >= self.tracks.len() {
+                    return Err(Error::TrackIndexNotFound(index));
+                }
+                Ok(Some(index))
+            }
+        }
+    }
+
+    /// Returns the sample description entries designated by `selection`, as pairs of
+    /// indexes in `self.tracks` and in the `sample_descriptions` of that track.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the selected tracks can't be selected (see
+    /// [`Video::select_video_tracks`]), if a selected track has no sample description,
+    /// or if it has no entry at the requested index.
+    fn select_sample_descriptions(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<(usize, usize)>, Error> {
+        let track_indexes = self.select_video_tracks(selection.tracks)?;
+        self.sample_descriptions_of(track_indexes, selection.sample_description_index)
+    }
+
+    /// Returns the timecode sample description entries designated by `selection`, as
+    /// [`Video::select_sample_descriptions`] does for the video ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` if the selected tracks can't be selected (see
+    /// [`Video::select_timecode_tracks`]), if a selected track has no sample
+    /// description, or if it has no entry at the requested index.
+    fn select_timecode_sample_descriptions(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<(usize, usize)>, Error> {
+        let track_indexes = self.select_timecode_tracks(selection.tracks)?;
+        self.sample_descriptions_of(track_indexes, selection.sample_description_index)
+    }
+
+    /// Returns the sample description entry at the 1-based `sample_description_index`
+    /// of each of the tracks at `track_indexes`, or all their entries if `None`, as
+    /// pairs of indexes in `self.tracks` and in the `sample_descriptions` of that
+    /// track.
+    fn sample_descriptions_of(
+        &self,
+        track_indexes: Vec<usize>,
+        sample_description_index: Option<u32>,
+    ) -> Result<Vec<(usize, usize)>, Error> {
+        let mut selected = Vec::new();
+
+        for track_index in track_indexes {
+            let track = &self.tracks[track_index];
+            if track.sample_descriptions.is_empty() {
+                return Err(Error::VideoSampleEntryNotFound {
+                    track_id: track.track_id,
+                });
+            }
+            match sample_description_index {
+                None => {
+                    selected.extend((0..track.sample_descriptions.len()).map(|i| (track_index, i)))
+                }
+                Some(index) => {
+                    let position = track
+                        .sample_descriptions
+                        .iter()
+                        .position(|description| description.index == index)
+                        .ok_or(Error::SampleDescriptionNotFound {
+                            track_id: track.track_id,
+                            index,
+                        })?;
+                    selected.push((track_index, position));
+                }
+            }
+        }
+
+        Ok(selected)
+    }
+
+    /// Inserts an atom at the end of a sample description entry (e.g. in
+    /// `stsd/apcn`), designated by its indexes in `self.tracks` and in the
+    /// `sample_descriptions` of that track.
+    ///
+    /// The sizes of the sample entry and of all its ancestors (stsd, stbl, minf, mdia,
+    /// trak, moov) are grown by the size of the new atom, and the chunk offsets are
+    /// fixed up if the essence had to move. moov is decoded again afterward so that
+    /// the offsets match the modified file, and those of the frames are moved along
+    /// with the essence.
+    fn insert_into_sample_entry(
+        &mut self,
+        file: &mut File,
+        (track_index, description_index): (usize, usize),
+        new_atom: &[u8],
+    ) -> Result<(), Error> {
+        let sample_entry =
+            self.tracks[track_index].sample_descriptions[description_index].sample_entry;
+
+        // Insert after the last child atom rather than at the very end of the entry,
+        // which may be padded with a 32-bit terminator.
+        let at = atom::read_sample_entry_children(file, &sample_entry)?
+            .last()
+            .map_or(atom::sample_entry_children_offset(&sample_entry), |a| {
+                a.end()
+            });
+
+        let ancestors = atom::path_to(file, sample_entry.offset)?;
+        let shift = edit::splice(file, &ancestors, at, 0, new_atom)?;
+
+        self.redecode_moov(file, shift)?;
+
+        Ok(())
+    }
+
+    /// Replaces the `size` bytes long atom located at `offset` in a sample description
+    /// entry with `bytes`, growing or shrinking the sizes of its ancestors and fixing
+    /// up the chunk offsets. An empty `bytes` removes the atom. moov is decoded again
+    /// afterward.
+    fn replace_in_sample_entry(
+        &mut self,
+        file: &mut File,
+        (track_index, description_index): (usize, usize),
+        offset: u64,
+        size: u64,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        let sample_entry =
+            self.tracks[track_index].sample_descriptions[description_index].sample_entry;
+        let ancestors = atom::path_to(file, sample_entry.offset)?;
+        let shift = edit::splice(file, &ancestors, offset, size, bytes)?;
+
+        self.redecode_moov(file, shift)?;
+
+        Ok(())
+    }
+
+    /// Puts `new_atom` into each selected video sample description entry: it replaces
+    /// the atom located by `existing` (as an offset and a size) in that entry, or is
+    /// inserted after the last child atom of the entry if `existing` returns `None`.
+    fn put_into_sample_entries(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        existing: impl Fn(&SampleDescription) -> Option<(u64, u64)>,
+        new_atom: &[u8],
+    ) -> Result<(), Error> {
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let description = &self.tracks[track_index].sample_descriptions[description_index];
+            match existing(description) {
+                Some((offset, size)) => self.replace_in_sample_entry(
+                    file,
+                    (track_index, description_index),
+                    offset,
+                    size,
+                    new_atom,
+                )?,
+                None => {
+                    self.insert_into_sample_entry(file, (track_index, description_index), new_atom)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the atom located by `existing` (as an offset and a size) from each
+    /// selected video sample description entry. Entries for which `existing` returns
+    /// `None` are left untouched.
+    fn remove_from_sample_entries(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        existing: impl Fn(&SampleDescription) -> Option<(u64, u64)>,
+    ) -> Result<(), Error> {
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let description = &self.tracks[track_index].sample_descriptions[description_index];
+            if let Some((offset, size)) = existing(description) {
+                self.replace_in_sample_entry(
+                    file,
+                    (track_index, description_index),
+                    offset,
+                    size,
+                    &[],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Inserts a new colr atom with the given indexes into the selected video sample
+    /// description entries.
+    ///
+    /// The colr atom is of the type selected by `selection.colr` if it is 'nclc' or
+    /// 'nclx'. Otherwise, it is of type 'nclc' in a QuickTime file, and of type 'nclx'
+    /// in an MP4 file. An 'nclx' colr atom gets the given range (limited if `None`).
+    ///
+    /// Sample entries that already have a selected colr atom with indexes are left
+    /// untouched.
+    pub fn insert_colr_atom(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        target_color_primaries: u8,
+        target_transfer_functions: u8,
+        target_matrix: u8,
+        target_full_range: Option<bool>,
+    ) -> Result<(), Error> {
+        let (color_parameter_type, size) = match selection.colr {
+            ColrSelector::Type(ColorParameterType::Nclc) => (b"nclc", 18u32),
+            ColrSelector::Type(ColorParameterType::Nclx) => (b"nclx", 19u32),
+            _ if self.is_quicktime() => (b"nclc", 18u32),
+            _ => (b"nclx", 19u32),
+        };
+        let mut colr_atom = Vec::with_capacity(size as usize);
+        colr_atom.extend_from_slice(&size.to_be_bytes());
+        colr_atom.extend_from_slice(&atom::COLR);
+        colr_atom.extend_from_slice(color_parameter_type);
+        colr_atom.extend_from_slice(&(target_color_primaries as u16).to_be_bytes());
+        colr_atom.extend_from_slice(&(target_transfer_functions as u16).to_be_bytes());
+        colr_atom.extend_from_slice(&(target_matrix as u16).to_be_bytes());
+        if size == 19 {
+            colr_atom.push(Self::full_range_byte(target_full_range.unwrap_or(false)));
+        }
+
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let description = &self.tracks[track_index].sample_descriptions[description_index];
+            if description
+                .selected_colr_atoms_with_indexes(selection.colr)
+                .is_empty()
+            {
+                self.insert_into_sample_entry(file, (track_index, description_index), &colr_atom)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Inserts a new 12-byte gama atom with the given gama value into the selected
+    /// video sample description entries.
+    ///
+    /// Sample entries that already have a gama atom are left untouched.
+    pub fn insert_gama_atom(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        target_gama_value: f32,
+    ) -> Result<(), Error> {
+        let mut gama_atom = Vec::with_capacity(12);
+        gama_atom.extend_from_slice(&12u32.to_be_bytes());
+        gama_atom.extend_from_slice(&atom::GAMA);
+        gama_atom.extend_from_slice(&Self::float_to_bytes(target_gama_value));
+
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let description = &self.tracks[track_index].sample_descriptions[description_index];
+            if !description.gama_atom.matched {
+                self.insert_into_sample_entry(file, (track_index, description_index), &gama_atom)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Physically removes the gama atom from the selected video sample description
+    /// entries, rather than setting its value to 0.
+    ///
+    /// Sample entries that have no gama atom are left untouched.
+    pub fn remove_gama_atom(&mut self, file: &mut File, selection: Selection) -> Result<(), Error> {
+        self.remove_from_sample_entries(file, selection, |description| {
+            let gama_atom = &description.gama_atom;
+            gama_atom
+                .matched
+                .then_some((gama_atom.offset, gama_atom.size as u64))
+        })
+    }
+
+    /// Sets the mastering display color volume (SMPTE ST 2086) of the selected video
+    /// sample description entries: their mdcv atom is overwritten, or a new one is
+    /// inserted.
+    pub fn set_mastering_display(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        mastering_display: &MasteringDisplay,
+    ) -> Result<(), Error> {
+        let mdcv_atom = atom::build(atom::MDCV, &mastering_display.to_bytes());
+
+        self.put_into_sample_entries(
+            file,
+            selection,
+            |description| {
+                let mdcv_atom = &description.mdcv_atom;
+                mdcv_atom
+                    .matched
+                    .then_some((mdcv_atom.offset, mdcv_atom.size as u64))
+            },
+            &mdcv_atom,
+        )
+    }
+
+    /// Removes the mdcv atom from the selected video sample description entries.
+    /// Entries without one are left untouched.
+    pub fn remove_mastering_display(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+    ) -> Result<(), Error> {
+        self.remove_from_sample_entries(file, selection, |description| {
+            let mdcv_atom = &description.mdcv_atom;
+            mdcv_atom
+                .matched
+                .then_some((mdcv_atom.offset, mdcv_atom.size as u64))
+        })
+    }
+
+    /// Sets the content light levels (MaxCLL and MaxFALL) of the selected video sample
+    /// description entries: their clli atom is overwritten, or a new one is inserted.
+    pub fn set_content_light_level(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        content_light_level: &ContentLightLevel,
+    ) -> Result<(), Error> {
+        let clli_atom = atom::build(atom::CLLI, &content_light_level.to_bytes());
+
+        self.put_into_sample_entries(
+            file,
+            selection,
+            |description| {
+                let clli_atom = &description.clli_atom;
+                clli_atom
+                    .matched
+                    .then_some((clli_atom.offset, clli_atom.size as u64))
+            },
+            &clli_atom,
+        )
+    }
+
+    /// Removes the clli atom from the selected video sample description entries.
+    /// Entries without one are left untouched.
+    pub fn remove_content_light_level(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+    ) -> Result<(), Error> {
+        self.remove_from_sample_entries(file, selection, |description| {
+            let clli_atom = &description.clli_atom;
+            clli_atom
+                .matched
+                .then_some((clli_atom.offset, clli_atom.size as u64))
+        })
+    }
+
+    /// Sets the pixel aspect ratio of the selected video sample description entries:
+    /// their pasp atom is overwritten, or a new one is inserted. The
+    /// aspect_ratio_information code of each ProRes frame described by these entries is
+    /// rewritten to match, from the dimensions of the frame.
+    pub fn set_pixel_aspect_ratio(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        pixel_aspect_ratio: &PixelAspectRatio,
+    ) -> Result<(), Error> {
+        let pasp_atom = atom::build(atom::PASP, &pixel_aspect_ratio.to_bytes());
+
+        self.put_into_sample_entries(
+            file,
+            selection,
+            |description| {
+                let pasp_atom = &description.pasp_atom;
+                pasp_atom
+                    .matched
+                    .then_some((pasp_atom.offset, pasp_atom.size as u64))
+            },
+            &pasp_atom,
+        )?;
+
+        // Overwrite the aspect ratio nibble of each ProRes frame described by these
+        // sample descriptions, leaving the frame rate code next to it untouched.
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let track = &mut self.tracks[track_index];
+            let index = track.sample_descriptions[description_index].index;
+            for frame in track
+                .frames
+                .iter_mut()
+                .filter(|frame| frame.sample_description_index == index)
+            {
+                let aspect_ratio_information =
+                    pixel_aspect_ratio.prores_aspect_ratio_information(frame.width, frame.height);
+                let mut buf = [0; 1];
+                file.seek(io::SeekFrom::Start(frame.offset + 21))?;
+                file.read_exact(&mut buf)?;
+                buf[0] = aspect_ratio_information << 4 | buf[0] & 0x0F;
+                file.seek(io::SeekFrom::Start(frame.offset + 21))?;
+                file.write_all(&buf)?;
+                frame.aspect_ratio_information = aspect_ratio_information;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the clean aperture of the selected video sample description entries: their
+    /// clap atom is overwritten, or a new one is inserted.
+    pub fn set_clean_aperture(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        clean_aperture: &CleanAperture,
+    ) -> Result<(), Error> {
+        let clap_atom = atom::build(atom::CLAP, &clean_aperture.to_bytes());
+
+        self.put_into_sample_entries(
+            file,
+            selection,
+            |description| {
+                let clap_atom = &description.clap_atom;
+                clap_atom
+                    .matched
+                    .then_some((clap_atom.offset, clap_atom.size as u64))
+            },
+            &clap_atom,
+        )
+    }
+
+    /// Removes the clap atom from the selected video sample description entries.
+    /// Entries without one are left untouched.
+    pub fn remove_clean_aperture(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+    ) -> Result<(), Error> {
+        self.remove_from_sample_entries(file, selection, |description| {
+            let clap_atom = &description.clap_atom;
+            clap_atom
+                .matched
+                .then_some((clap_atom.offset, clap_atom.size as u64))
+        })
+    }
+
+    /// Replaces the `size` bytes located at `offset` in `parent`, an atom of moov (e.g.
+    /// a trak or stbl atom), with `bytes`, then decodes moov again. `parent` and the
+    /// atoms containing it are resized.
+    fn replace_in(
+        &mut self,
+        file: &mut File,
+        parent: &AtomHeader,
+        offset: u64,
+        size: u64,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        let ancestors = atom::path_to(file, parent.offset)?;
+        let shift = edit::splice(file, &ancestors, offset, size, bytes)?;
+
+        self.redecode_moov(file, shift)?;
+
+        Ok(())
+    }
+
+    /// Sets the reel name of the selected timecode tracks, i.e. the name of the tape or
+    /// camera card their media comes from, which editing applications use to relink
+    /// to the camera originals. The name atom of their timecode sample description
+    /// entries is replaced, or a new one is inserted.
+    ///
+    /// The tracks are selected as described by [`Selection`]: every timecode track by
+    /// default.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidReelName` if `reel_name` isn't made of 1 to 255
+    /// printable ASCII characters, in which case nothing is written.
+    pub fn set_reel_name(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        reel_name: &str,
+    ) -> Result<(), Error> {
+        timecode::check_reel_name(reel_name).map_err(Error::InvalidReelName)?;
+        let name_atom = atom::build(atom::NAME, &timecode::reel_name_to_bytes(reel_name));
+
+        for selected in self.select_timecode_sample_descriptions(selection)? {
+            let current = &self.tracks[selected.0].sample_descriptions[selected.1].name_atom;
+            if current.matched {
+                let (offset, size) = (current.offset, current.size as u64);
+                self.replace_in_sample_entry(file, selected, offset, size, &name_atom)?;
+            } else {
+                self.insert_into_sample_entry(file, selected, &name_atom)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the reel name (name atom) from the selected timecode tracks. Sample
+    /// descriptions without one are left untouched.
+    pub fn remove_reel_name(&mut self, file: &mut File, selection: Selection) -> Result<(), Error> {
+        for selected in self.select_timecode_sample_descriptions(selection)? {
+            let current = &self.tracks[selected.0].sample_descriptions[selected.1].name_atom;
+            if current.matched {
+                let (offset, size) = (current.offset, current.size as u64);
+                self.replace_in_sample_entry(file, selected, offset, size, &[])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the start timecode of the selected timecode tracks, i.e. the timecode of
+    /// their first frame, by rewriting the frame numbers held by their samples in
+    /// place. Later samples, which only exist when the timecode was broken, are
+    /// shifted by as many frames as the first one to keep their distance to it.
+    ///
+    /// The tracks are selected as described by [`Selection`]: every timecode track by
+    /// default. The frames are counted as the sample description of each
+    /// track says: number of frames per second, and whether frame numbers are
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidTimecode` if the timecode can't be counted in a selected
+    /// track, e.g. 00:00:00:24 at 24 frames per second, `Error::TimecodeSampleNotFound`
+    /// if a selected track has no sample, and `Error::UnsupportedTimecodeFormat` if its
+    /// sample description doesn't say how to count its frames. Nothing is written
+    /// unless every selected track can be.
+    pub fn set_start_timecode(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        timecode: &Timecode,
+    ) -> Result<(), Error> {
+        // (track index, sample index, new frame number) of every sample to rewrite.
+        let mut new_frame_numbers = Vec::new();
+
+        for track_index in self.select_timecode_tracks(selection.tracks)? {
+            let track = &self.tracks[track_index];
+            let Some(first) = track.timecode_samples.first() else {
+                return Err(Error::TimecodeSampleNotFound {
+                    track_id: track.track_id,
+                });
+            };
+            let format = |sample: &TimecodeSample| {
+                track
+                    .timecode_format(sample.sample_description_index)
+                    .ok_or(Error::UnsupportedTimecodeFormat {
+                        track_id: track.track_id,
+                    })
+            };
+            let start = format(first)?
+                .frame_number(timecode)
+                .map_err(Error::InvalidTimecode)?;
+            let shift = start as i64 - first.frame_number as i64;
+
+            for (sample_index, sample) in track.timecode_samples.iter().enumerate() {
+                let frames_per_day = format(sample)?.frames_per_day() as i64;
+                let frame_number = (sample.frame_number as i64 + shift).rem_euclid(frames_per_day);
+                new_frame_numbers.push((track_index, sample_index, frame_number as u32));
+            }
+        }
+
+        for (track_index, sample_index, frame_number) in new_frame_numbers {
+            let sample = &mut self.tracks[track_index].timecode_samples[sample_index];
+            file.seek(io::SeekFrom::Start(sample.offset))?;
+            file.write_all(&frame_number.to_be_bytes())?;
+            sample.frame_number = frame_number;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the track aperture mode dimensions of the selected video tracks: their
+    /// tapt atom is replaced, or a new one is inserted right after their tkhd atom,
+    /// where QuickTime writes it.
+    pub fn set_track_aperture(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        track_aperture: &TrackAperture,
+    ) -> Result<(), Error> {
+        let tapt_atom = track_aperture.to_atom();
+
+        for track_index in self.select_video_tracks(selection.tracks)? {
+            let current = &self.tracks[track_index].tapt_atom;
+            // The tracks are decoded in the order of their trak atoms.
+            let trak = atom::traks(file)?[track_index];
+            let (offset, size) = if current.matched {
+                (current.offset, current.size as u64)
+            } else {
+                let trak_children = atom::read_children_of(file, &trak)?;
+                let offset = match atom::find(&trak_children, atom::TKHD) {
+                    Some(tkhd) => tkhd.end(),
+                    None => trak.body_offset(),
+                };
+                (offset, 0)
+            };
+            self.replace_in(file, &trak, offset, size, &tapt_atom)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the tapt atom from the selected video tracks. Tracks without one are
+    /// left untouched.
+    pub fn remove_track_aperture(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+    ) -> Result<(), Error> {
+        for track_index in self.select_video_tracks(selection.tracks)? {
+            let current = &self.tracks[track_index].tapt_atom;
+            if current.matched {
+                let (offset, size) = (current.offset, current.size as u64);
+                let trak = atom::traks(file)?[track_index];
+                self.replace_in(file, &trak, offset, size, &[])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the interlace mode of the selected video sample description entries: their
+    /// fiel atom is overwritten, or a new one is inserted, and the interlace_mode of
+    /// each ProRes frame described by these entries is rewritten to match.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InterlaceModeNotSupported` if a frame isn't made of as many
+    /// pictures as the interlace mode needs (one for a progressive frame, one per field
+    /// for an interlaced one), in which case nothing is written.
+    pub fn set_interlace_mode(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        interlace_mode: InterlaceMode,
+    ) -> Result<(), Error> {
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let track = &self.tracks[track_index];
+            let index = track.sample_descriptions[description_index].index;
+            if let Some(frame) = track.frames.iter().find(|frame| {
+                frame.sample_description_index == index
+                    && frame.picture_count != interlace_mode.picture_count()
+            }) {
+                return Err(Error::InterlaceModeNotSupported {
+                    track_id: track.track_id,
+                    interlace_mode,
+                    picture_count: frame.picture_count,
+                });
+            }
+        }
+
+        let fiel_atom = atom::build(atom::FIEL, &interlace_mode.to_fiel_bytes());
+        self.put_into_sample_entries(
+            file,
+            selection,
+            |description| {
+                let fiel_atom = &description.fiel_atom;
+                fiel_atom
+                    .matched
+                    .then_some((fiel_atom.offset, fiel_atom.size as u64))
+            },
+            &fiel_atom,
+        )?;
+
+        // Overwrite the interlace mode bits of each ProRes frame described by these
+        // sample descriptions, leaving the chroma format next to them untouched.
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let track = &mut self.tracks[track_index];
+            let index = track.sample_descriptions[description_index].index;
+            for frame in track
+                .frames
+                .iter_mut()
+                .filter(|frame| frame.sample_description_index == index)
+            {
+                let mut buf = [0; 1];
+                file.seek(io::SeekFrom::Start(frame.offset + 20))?;
+                file.read_exact(&mut buf)?;
+                buf[0] = buf[0] & !0x0C | interlace_mode.prores_code() << 2;
+                file.seek(io::SeekFrom::Start(frame.offset + 20))?;
+                file.write_all(&buf)?;
+                frame.interlace_mode = interlace_mode.prores_code();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the alpha_channel_type of each ProRes frame described by the selected video
+    /// sample description entries, e.g. to fix ProRes 4444 files whose encoder got it
+    /// wrong.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::AlphaChannelNotSupported` when enabling an alpha channel in
+    /// frames that aren't 4:4:4, or in a sample description whose depth isn't 32, and
+    /// `Error::ProResFramesNotFound` if a selected sample description has no ProRes
+    /// frame. Nothing is written in these cases.
+    pub fn set_alpha_channel_type(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        alpha_channel_type: AlphaChannelType,
+    ) -> Result<(), Error> {
+        let selected = self.select_sample_descriptions(selection)?;
+
+        for &(track_index, description_index) in selected.iter() {
+            let track = &self.tracks[track_index];
+            let description = &track.sample_descriptions[description_index];
+            let mut frames = track
+                .frames
+                .iter()
+                .filter(|frame| frame.sample_description_index == description.index)
+                .peekable();
+            if frames.peek().is_none() {
+                return Err(Error::ProResFramesNotFound {
+                    track_id: track.track_id,
+                    format: description.sample_entry.kind,
+                });
+            }
+            if alpha_channel_type == AlphaChannelType::None {
+                continue;
+            }
+            for frame in frames {
+                if frame.chroma_format != 3 || description.depth != 32 {
+                    return Err(Error::AlphaChannelNotSupported {
+                        track_id: track.track_id,
+                        alpha_channel_type,
+                        depth: description.depth,
+                        chroma_format: frame.chroma_format,
+                    });
+                }
+            }
+        }
+
+        // Overwrite the alpha channel type of each ProRes frame described by these
+        // sample descriptions, leaving the reserved bits next to it untouched.
+        for (track_index, description_index) in selected {
+            let track = &mut self.tracks[track_index];
+            let index = track.sample_descriptions[description_index].index;
+            for frame in track
+                .frames
+                .iter_mut()
+                .filter(|frame| frame.sample_description_index == index)
+            {
+                let mut buf = [0; 1];
+                file.seek(io::SeekFrom::Start(frame.offset + 25))?;
+                file.read_exact(&mut buf)?;
+                buf[0] = buf[0] & 0xF0 | alpha_channel_type.code();
+                file.seek(io::SeekFrom::Start(frame.offset + 25))?;
+                file.write_all(&buf)?;
+                frame.alpha_channel_type = alpha_channel_type.code();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the frame_rate_code of each ProRes frame described by the selected video
+    /// sample description entries, e.g. after a 23.976 fps shoot has been conformed to
+    /// 24 fps.
+    ///
+    /// With `container_timing`, the timing of the selected tracks is rewritten too, as
+    /// [`Video::conform_frame_rate`] does for the video: the time scale and duration
+    /// of their mdhd atom, their stts atom which gets a single entry giving every
+    /// sample the duration of a frame (e.g. a time scale of 24000 and samples of 1000
+    /// at 24 fps), the duration of the tracks (tkhd) and of their edits (elst) in the
+    /// movie, and the duration of the movie (mvhd). Timecode and audio tracks are left
+    /// as they are. As the timing is shared by the whole track, every ProRes frame of a
+    /// selected track must then be described by a selected sample description entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProResFramesNotFound` if a selected sample description has no
+    /// ProRes frame, `Error::PartialContainerTiming` if a selected track has frames of
+    /// sample descriptions left out of `selection`, `Error::MediaTimingNotFound` if a
+    /// track has no tkhd, mdhd or sample table to rewrite and
+    /// `Error::MediaDurationOverflow` if one of its new durations doesn't fit its atom.
+    /// Nothing is written in these cases.
+    pub fn set_frame_rate(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        frame_rate: FrameRate,
+        container_timing: bool,
+    ) -> Result<(), Error> {
+        let selected = self.select_sample_descriptions(selection)?;
+
+        for &(track_index, description_index) in selected.iter() {
+            let track = &self.tracks[track_index];
+            let description = &track.sample_descriptions[description_index];
+            if !track
+                .frames
+                .iter()
+                .any(|frame| frame.sample_description_index == description.index)
+            {
+                return Err(Error::ProResFramesNotFound {
+                    track_id: track.track_id,
+                    format: description.sample_entry.kind,
+                });
+            }
+        }
+
+        let mut conformed = Vec::new();
+        let mut movie_timing = None;
+        if container_timing {
+            let mut track_indexes: Vec<usize> = selected
+                .iter()
+                .map(|&(track_index, _)| track_index)
+                .collect();
+            track_indexes.sort_unstable();
+            track_indexes.dedup();
+            for &track_index in track_indexes.iter() {
+                let track = &self.tracks[track_index];
+                let is_selected = |index: u32| {
+                    selected.iter().any(|&(selected_track, description_index)| {
+                        selected_track == track_index
+                            && track.sample_descriptions[description_index].index == index
+                    })
+                };
+                if !track
+                    .frames
+                    .iter()
+                    .all(|frame| is_selected(frame.sample_description_index))
+                {
+                    return Err(Error::PartialContainerTiming {
+                        track_id: track.track_id,
+                    });
+                }
+            }
+            let traks = atom::traks(file)?;
+            let (timescale, _) = frame_rate.media_timing();
+            for track_index in track_indexes {
+                let speed = self.video_speed(file, &traks, track_index, frame_rate)?;
+                conformed.push(self.conform_track(
+                    file,
+                    &traks,
+                    track_index,
+                    speed,
+                    timescale,
+                    frame_rate,
+                )?);
+            }
+            movie_timing = self.conformed_movie_timing(file, &traks, &conformed)?;
+            Self::check_stts_replacements(file, &conformed)?;
+        }
+
+        for &(track_index, description_index) in selected.iter() {
+            let index = self.tracks[track_index].sample_descriptions[description_index].index;
+            self.write_frame_rate_codes(file, track_index, Some(index), frame_rate)?;
+        }
+        self.write_conformed(file, &conformed, movie_timing)
+    }
+
+    /// Overwrites the frame rate code of the ProRes frames of the track at
+    /// `track_index` described by the sample description entry at the 1-based
+    /// `sample_description_index`, or of all its ProRes frames if `None`, leaving the
+    /// aspect ratio code next to it untouched.
+    fn write_frame_rate_codes(
+        &mut self,
+        file: &mut File,
+        track_index: usize,
+        sample_description_index: Option<u32>,
+        frame_rate: FrameRate,
+    ) -> io::Result<()> {
+        for frame in self.tracks[track_index].frames.iter_mut().filter(|frame| {
+            sample_description_index.is_none_or(|index| frame.sample_description_index == index)
+        }) {
+            let mut buf = [0; 1];
+            file.seek(io::SeekFrom::Start(frame.offset + 21))?;
+            file.read_exact(&mut buf)?;
+            buf[0] = buf[0] & 0xF0 | frame_rate.prores_code();
+            file.seek(io::SeekFrom::Start(frame.offset + 21))?;
+            file.write_all(&buf)?;
+            frame.frame_rate_code = frame_rate.prores_code();
+        }
+
+        Ok(())
+    }
+
+    /// Computes the time scale and duration of the mdhd atom of the track at
+    /// `track_index`, and the stts atom replacing its own, so that every sample lasts
+    /// a frame at `frame_rate`. Nothing is written. Whether the file can take the new
+    /// stts atoms of all the tracks is checked by [`Video::check_stts_replacements`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::MediaTimingNotFound` if the track has no mdhd atom or sample
+    /// table, and `Error::MediaDurationOverflow` if its new duration doesn't fit its
+    /// mdhd atom.
+    fn media_timing(
+        &self,
+        file: &mut File,
+        track_index: usize,
+        frame_rate: FrameRate,
+    ) -> Result<MediaTiming, Error> {
+        let track_id = self.tracks[track_index].track_id;
+        let (timescale, sample_duration) = frame_rate.media_timing();
+
+        let trak = atom::traks(file)?[track_index];
+        let timing =
+            TrakTiming::read(file, &trak)?.ok_or(Error::MediaTimingNotFound { track_id })?;
+
+        let sample_count = SampleTable::read(file, &timing.stbl_children)?.sample_count;
+        let mut header_timing = HeaderTiming::read(file, &timing.mdhd)?;
+        header_timing.timescale = timescale;
+        header_timing.duration = sample_count as u64 * sample_duration as u64;
+        if !header_timing.fits() {
+            return Err(Error::MediaDurationOverflow { track_id });
+        }
+
+        // stts: version and flags, the entry count, then the entry itself.
+        let mut stts_payload = vec![0; 4];
+        for value in [1, sample_count, sample_duration] {
+            stts_payload.extend_from_slice(&value.to_be_bytes());
+        }
+        let stts_atom = atom::build(atom::STTS, &stts_payload);
+        let (offset, size) = timing.stts_range();
+        let ancestors = atom::path_to(file, timing.stbl.offset)?;
+        let stts_growth = edit::Growth::new(ancestors, offset, size, stts_atom.len(), true);
+
+        Ok(MediaTiming {
+            header_timing,
+            stts_atom,
+            stts_growth,
+        })
+    }
+
+    /// Returns how many times faster the video track at `track_index` plays once each
+    /// of its samples lasts a frame at `frame_rate`, e.g. 1.001 from 23.976 fps to 24
+    /// fps. `traks` are the trak atoms of the file.
+    fn video_speed(
+        &self,
+        file: &mut File,
+        traks: &[AtomHeader],
+        track_index: usize,
+        frame_rate: FrameRate,
+    ) -> Result<f64, Error> {
+        let (timescale, sample_duration) = frame_rate.media_timing();
+        let timing =
+            TrakTiming::read(file, &traks[track_index])?.ok_or(Error::MediaTimingNotFound {
+                track_id: self.tracks[track_index].track_id,
+            })?;
+        let old_timescale = HeaderTiming::read(file, &timing.mdhd)?.timescale;
+        let sample_table = SampleTable::read(file, &timing.stbl_children)?;
+        let old_duration = sample_table.duration();
+        let new_duration = sample_table.sample_count as u64 * sample_duration as u64;
+
+        Ok(
+            if old_duration == 0 || old_timescale == 0 || new_duration == 0 {
+                1.0
+            } else {
+                (old_duration as f64 / old_timescale as f64)
+                    / (new_duration as f64 / timescale as f64)
+            },
+        )
+    }
+
+    /// Computes the new timing of the track at `track_index` once it plays `speed`
+    /// times faster, with a media time scale of `new_timescale`: its media, its
+    /// duration and its edits in the movie. The samples of a video track last a frame
+    /// at `frame_rate` (see [`Video::media_timing`]), while those of a timecode track
+    /// keep their distance to each other and their timecode, counted at `frame_rate`.
+    /// Those of an audio track keep their media times, while the sample rate of its
+    /// sample descriptions is scaled like its time scale. `traks` are the trak atoms of
+    /// the file. Nothing is written.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::MediaTimingNotFound` if the track has no tkhd, mdhd or sample
+    /// table, `Error::MediaDurationOverflow` if one of its new durations doesn't fit
+    /// its atom, `Error::UnsupportedTimecodeFormat` if a timecode sample description
+    /// doesn't say how to count frames, `Error::InvalidTimecode` if the timecode of a
+    /// sample can't be counted at `frame_rate`, and `Error::SampleRateOverflow` if the
+    /// new sample rate of an audio sample description doesn't fit it.
+    fn conform_track(
+        &self,
+        file: &mut File,
+        traks: &[AtomHeader],
+        track_index: usize,
+        speed: f64,
+        new_timescale: u32,
+        frame_rate: FrameRate,
+    ) -> Result<ConformedTrack, Error> {
+        let track = &self.tracks[track_index];
+        let track_id = track.track_id;
+        let timing = TrakTiming::read(file, &traks[track_index])?
+            .ok_or(Error::MediaTimingNotFound { track_id })?;
+        let mut header_timing = HeaderTiming::read(file, &timing.mdhd)?;
+        let old_timescale = header_timing.timescale;
+        // The media times of an audio track don't change, only how fast they go.
+        let media_factor = if track.handler_type == atom::SOUND_HANDLER {
+            1.0
+        } else {
+            new_timescale as f64 / (header_timing.timescale as f64 * speed)
+        };
+        let mut stts_atom = None;
+        let mut stts_growth = None;
+        if track.is_video() {
+            let media_timing = self.media_timing(file, track_index, frame_rate)?;
+            header_timing = media_timing.header_timing;
+            stts_atom = Some(media_timing.stts_atom);
+            stts_growth = Some(media_timing.stts_growth);
+        } else {
+            header_timing.timescale = new_timescale;
+            header_timing.duration = timing::rescale(header_timing.duration, media_factor);
+        }
+
+        let mut track_duration = TrackDuration::read(file, &timing.tkhd)?;
+        track_duration.duration = timing::rescale(track_duration.duration, 1.0 / speed);
+        let edit_list = match timing.elst {
+            Some(elst) => {
+                let mut edit_list = EditList::read(file, &elst)?;
+                for edit in edit_list.edits.iter_mut() {
+                    edit.segment_duration = timing::rescale(edit.segment_duration, 1.0 / speed);
+                    if edit.media_time >= 0 {
+                        edit.media_time =
+                            i64::try_from(timing::rescale(edit.media_time as u64, media_factor))
+                                .map_err(|_| Error::MediaDurationOverflow { track_id })?;
+                    }
+                }
+                Some(edit_list)
+            }
+            None => None,
+        };
+        if !header_timing.fits()
+            || !track_duration.fits()
+            || !edit_list.as_ref().is_none_or(EditList::fits)
+        {
+            return Err(Error::MediaDurationOverflow { track_id });
+        }
+
+        let mut stts_payload = None;
+        let mut tmcd_entries = Vec::new();
+        let mut frame_numbers = Vec::new();
+        if track.handler_type == atom::TIMECODE_HANDLER {
+            let sample_table = SampleTable::read(file, &timing.stbl_children)?;
+            let mut payload = Vec::new();
+            for entry in sample_table.time_to_sample.iter() {
+                let sample_delta =
+                    u32::try_from(timing::rescale(entry.sample_delta as u64, media_factor))
+                        .map_err(|_| Error::MediaDurationOverflow { track_id })?;
+                payload.extend_from_slice(&entry.sample_count.to_be_bytes());
+                payload.extend_from_slice(&sample_delta.to_be_bytes());
+            }
+            stts_payload = Some(payload);
+
+            let (_, sample_duration) = frame_rate.media_timing();
+            let conformed_format = |format: TimecodeFormat| {
+                format.with_frame_rate(frame_rate, new_timescale, sample_duration)
+            };
+            for description in track.sample_descriptions.iter() {
+                if description.sample_entry.kind != atom::TMCD {
+                    continue;
+                }
+                if !description.timecode_format.is_supported() {
+                    return Err(Error::UnsupportedTimecodeFormat { track_id });
+                }
+                tmcd_entries.push((
+                    description.sample_entry,
+                    conformed_format(description.timecode_format),
+                ));
+            }
+            // The frame numbers are counted again so that each sample keeps its
+            // timecode, e.g. 01:00:00:00 is frame 86400 at 24 fps but 90000 at 25.
+            for sample in track.timecode_samples.iter() {
+                let format = track
+                    .timecode_format(sample.sample_description_index)
+                    .ok_or(Error::UnsupportedTimecodeFormat { track_id })?;
+                let frame_number = conformed_format(format)
+                    .frame_number(&format.timecode(sample.frame_number))
+                    .map_err(Error::InvalidTimecode)?;
+                frame_numbers.push(frame_number);
+            }
+        }
+
+        let mut sample_rates = Vec::new();
+        if track.handler_type == atom::SOUND_HANDLER {
+            for description in track.sample_descriptions.iter() {
+                let entry = description.sample_entry;
+                let Some(mut sample_rate) = SampleRate::read(file, &entry)? else {
+                    continue;
+                };
+                sample_rate.rate = sample_rate.rate * new_timescale as f64 / old_timescale as f64;
+                if !sample_rate.fits() {
+                    return Err(Error::SampleRateOverflow { track_id });
+                }
+                sample_rates.push((entry, sample_rate));
+            }
+        }
+
+        Ok(ConformedTrack {
+            track_index,
+            timing,
+            header_timing,
+            track_duration,
+            edit_list,
+            stts_payload,
+            stts_atom,
+            stts_growth,
+            tmcd_entries,
+            frame_numbers,
+            sample_rates,
+        })
+    }
+
+    /// Checks that the stts atoms replacing those of the `conformed` video tracks, one
+    /// after the other, leave every size and chunk offset of the file within its
+    /// field. Nothing is written.
+    fn check_stts_replacements(file: &mut File, conformed: &[ConformedTrack]) -> Result<(), Error> {
+        let growths: Vec<edit::Growth> = conformed
+            .iter()
+            .filter_map(|conformed_track| conformed_track.stts_growth.clone())
+            .collect();
+        Ok(edit::check_growths(file, &growths)?)
+    }
+
+    /// Computes the new duration of the movie once the tracks are conformed, i.e. the
+    /// duration of its longest track, along with its mvhd atom. Returns `None` if the
+    /// file has no mvhd atom. `traks` are the trak atoms of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::MediaDurationOverflow` if the new duration doesn't fit mvhd.
+    fn conformed_movie_timing(
+        &self,
+        file: &mut File,
+        traks: &[AtomHeader],
+        conformed: &[ConformedTrack],
+    ) -> Result<Option<(AtomHeader, HeaderTiming)>, Error> {
+        let file_size = file.metadata()?.len();
+        let moov = atom::find(&atom::read_children(file, 0, file_size)?, atom::MOOV);
+        let mvhd = match moov {
+            Some(moov) => atom::find(&atom::read_children_of(file, &moov)?, atom::MVHD),
+            None => None,
+        };
+        let (Some(mvhd), Some(first)) = (mvhd, conformed.first()) else {
+            return Ok(None);
+        };
+
+        let mut header_timing = HeaderTiming::read(file, &mvhd)?;
+        header_timing.duration = 0;
+        for (track_index, trak) in traks.iter().enumerate() {
+            let duration = match conformed.iter().find(|c| c.track_index == track_index) {
+                Some(conformed_track) => conformed_track.track_duration.duration,
+                None => match TrakTiming::read(file, trak)? {
+                    Some(timing) => TrackDuration::read(file, &timing.tkhd)?.duration,
+                    None => 0,
+                },
+            };
+            header_timing.duration = header_timing.duration.max(duration);
+        }
+        if !header_timing.fits() {
+            return Err(Error::MediaDurationOverflow {
+                track_id: self.tracks[first.track_index].track_id,
+            });
+        }
+
+        Ok(Some((mvhd, header_timing)))
+    }
+
+    /// Writes the timing computed by [`Video::conform_track`] and
+    /// [`Video::conformed_movie_timing`]: in place first, then the stts atoms of the
+    /// video tracks, as replacing them may move everything after them. moov is decoded
+    /// again afterward.
+    fn write_conformed(
+        &mut self,
+        file: &mut File,
+        conformed: &[ConformedTrack],
+        movie_timing: Option<(AtomHeader, HeaderTiming)>,
+    ) -> Result<(), Error> {
+        for conformed_track in conformed.iter() {
+            let timing = &conformed_track.timing;
+            conformed_track.header_timing.write(file, &timing.mdhd)?;
+            conformed_track.track_duration.write(file, &timing.tkhd)?;
+            if let (Some(elst), Some(edit_list)) = (timing.elst, &conformed_track.edit_list) {
+                edit_list.write(file, &elst)?;
+            }
+            if let (Some(stts), Some(payload)) = (
+                atom::find(&timing.stbl_children, atom::STTS),
+                &conformed_track.stts_payload,
+            ) {
+                // stts: version and flags, the entry count, then the entries.
+                file.seek(io::SeekFrom::Start(stts.body_offset() + 8))?;
+                file.write_all(payload)?;
+            }
+            for (entry, format) in conformed_track.tmcd_entries.iter() {
+                // tmcd: reserved (6 bytes) and data reference index (2 bytes) before the
+                // fields of the format.
+                file.seek(io::SeekFrom::Start(entry.body_offset() + 8))?;
+                file.write_all(&format.to_bytes())?;
+            }
+            let samples = &mut self.tracks[conformed_track.track_index].timecode_samples;
+            for (sample, &frame_number) in samples.iter_mut().zip(&conformed_track.frame_numbers) {
+                file.seek(io::SeekFrom::Start(sample.offset))?;
+                file.write_all(&frame_number.to_be_bytes())?;
+                sample.frame_number = frame_number;
+            }
+            for (entry, sample_rate) in conformed_track.sample_rates.iter() {
+                sample_rate.write(file, entry)?;
+            }
+        }
+        if let Some((mvhd, header_timing)) = movie_timing {
+            header_timing.write(file, &mvhd)?;
+        }
+
+        for conformed_track in conformed.iter() {
+            if let Some(stts_atom) = &conformed_track.stts_atom {
+                // Replacing a previous stts atom may have moved this one.
+                let track_index = conformed_track.track_index;
+                let trak = atom::traks(file)?[track_index];
+                let timing = TrakTiming::read(file, &trak)?.ok_or(Error::MediaTimingNotFound {
+                    track_id: self.tracks[track_index].track_id,
+                })?;
+                let (offset, size) = timing.stts_range();
+                self.replace_in(file, &timing.stbl, offset, size, stts_atom)?;
+            }
+        }
+
+        Ok(())
+    }
 
-impl Video {
-    /// Constructs a colr atom and sets its offset, size, primary index, transfer
-    /// function index, and matrix index.
+    /// Conforms the selected video tracks to `frame_rate` without re-encoding them:
+    /// each frame lasts a frame at the new rate, so the video plays faster or slower,
+    /// e.g. 1.001 times faster from 23.976 fps to 24 fps.
     ///
-    /// # Arguments
+    /// As with [`Video::encode`], the file is modified in place: the time scale and
+    /// sample durations of the video media (see [`Video::set_frame_rate`]), the frame
+    /// rate code of its ProRes frames, the duration of the tracks (tkhd) and of their
+    /// edits (elst) in the movie, and the duration of the movie (mvhd). Only the stts
+    /// atom of the video tracks may change size, as it is replaced with a single entry.
+    /// Timecode tracks are rescaled the same way and their sample description gets the
+    /// new frame rate, drop frame at 29.97, 59.94 and 119.88 fps and non-drop frame
+    /// otherwise, while the frame numbers held by their samples are counted again at
+    /// that rate so that they keep their timecode. Audio tracks are left alone, unless
+    /// `audio` asks for their time scale and sample rate to be scaled by the speed
+    /// change of the (first) video track.
     ///
-    /// * `file` - A mutable reference to a `File` object.
-    /// * `offset` - The offset of the colr atom as a `usize`.
+    /// Returns the track IDs of the audio tracks left alone, which now drift from the
+    /// video.
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// An `io::Result` indicating whether the operation was successful or not.
-    fn construct_colr_atom(&mut self, file: &mut File, offset: usize) -> io::Result<()> {
-        self.colr_atom.offset = offset as u64;
+    /// Returns `Error::MediaTimingNotFound` if a track to conform has no tkhd, mdhd or
+    /// sample table, `Error::MediaDurationOverflow` if one of its new durations doesn't
+    /// fit its atom, `Error::UnsupportedTimecodeFormat` if a timecode track doesn't say
+    /// how to count its frames (e.g. it holds a tape counter),
+    /// `Error::InvalidTimecode` if a timecode can't be counted at the new frame rate,
+    /// e.g. 00:59:59:29 at 25 fps, and `Error::SampleRateOverflow` if the new sample
+    /// rate of an audio track doesn't fit its sample description. Nothing is written in
+    /// these cases.
+    pub fn conform_frame_rate(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        frame_rate: FrameRate,
+        audio: AudioConform,
+    ) -> Result<Vec<u32>, Error> {
+        let video_tracks = self.select_video_tracks(selection.tracks)?;
+        let (timescale, _) = frame_rate.media_timing();
+        let traks = atom::traks(file)?;
+
+        // How many times faster each track plays, and the time scale of its media
+        // afterward.
+        let mut speeds = Vec::new();
+        let mut left_alone = Vec::new();
+        for &track_index in video_tracks.iter() {
+            let speed = self.video_speed(file, &traks, track_index, frame_rate)?;
+            speeds.push((track_index, speed, timescale));
+        }
+        let video_speed = speeds.first().map_or(1.0, |&(_, speed, _)| speed);
+        for (track_index, track) in self.tracks.iter().enumerate() {
+            match (track.handler_type, audio) {
+                (atom::TIMECODE_HANDLER, _) => speeds.push((track_index, video_speed, timescale)),
+                (atom::SOUND_HANDLER, AudioConform::Rescale) => {
+                    let new_timescale =
+                        u32::try_from(timing::rescale(track.timescale as u64, video_speed))
+                            .map_err(|_| Error::MediaDurationOverflow {
+                                track_id: track.track_id,
+                            })?;
+                    speeds.push((track_index, video_speed, new_timescale));
+                }
+                (atom::SOUND_HANDLER, AudioConform::Leave) => left_alone.push(track.track_id),
+                _ => {}
+            }
+        }
+
+        let mut conformed = Vec::new();
+        for (track_index, speed, new_timescale) in speeds {
+            conformed.push(self.conform_track(
+                file,
+                &traks,
+                track_index,
+                speed,
+                new_timescale,
+                frame_rate,
+            )?);
+        }
+        let movie_timing = self.conformed_movie_timing(file, &traks, &conformed)?;
+        Self::check_stts_replacements(file, &conformed)?;
 
-        let mut size_buf = [0; 4];
-        file.seek(io::SeekFrom::Start(self.colr_atom.offset))?;
-        file.read_exact(&mut size_buf)?;
-        self.colr_atom.size = u32::from_be_bytes(size_buf);
+        for &track_index in video_tracks.iter() {
+            self.write_frame_rate_codes(file, track_index, None, frame_rate)?;
+        }
+        self.write_conformed(file, &conformed, movie_timing)?;
 
-        let mut nclc_buf = [0; 2];
-        file.seek(io::SeekFrom::Start(self.colr_atom.offset + 12))?;
-        file.read_exact(&mut nclc_buf)?;
-        self.colr_atom.primary_index = u16::from_be_bytes(nclc_buf);
+        Ok(left_alone)
+    }
 
-        file.seek(io::SeekFrom::Start(self.colr_atom.offset + 14))?;
-        file.read_exact(&mut nclc_buf)?;
-        self.colr_atom.transfer_function_index = u16::from_be_bytes(nclc_buf);
+    /// Checks the frame headers of the ProRes frames against the sample description
+    /// they belong to, and describes each disagreement with the track ID and the
+    /// 1-based sample description index: a chroma format that isn't the one of the
+    /// format (e.g. 4:2:2 frames in an 'ap4h' entry), dimensions other than the ones of
+    /// the entry, an interlace mode its fiel atom contradicts, an aspect ratio code
+    /// (other than unknown) its pasp atom contradicts, or a frame rate code (other than
+    /// unknown) the duration of its sample in the mdhd time scale contradicts. Such
+    /// files were wrapped wrongly, or come from an encoder that doesn't fill the frame
+    /// header in.
+    pub fn prores_frame_mismatches(&self) -> Vec<(u32, u32, String)> {
+        let mut mismatches = Vec::new();
 
-        file.seek(io::SeekFrom::Start(self.colr_atom.offset + 16))?;
-        file.read_exact(&mut nclc_buf)?;
-        self.colr_atom.matrix_index = u16::from_be_bytes(nclc_buf);
+        for track in self.tracks.iter() {
+            for description in track.sample_descriptions.iter() {
+                let frames: Vec<&ProResFrame> = track
+                    .frames
+                    .iter()
+                    .filter(|frame| frame.sample_description_index == description.index)
+                    .collect();
+                let mut check = |disagrees: &dyn Fn(&ProResFrame) -> bool, what: String| {
+                    let count = frames.iter().filter(|frame| disagrees(frame)).count();
+                    if count > 0 {
+                        mismatches.push((
+                            track.track_id,
+                            description.index,
+                            format!("{} of {} ProRes frames {}", count, frames.len(), what),
+                        ));
+                    }
+                };
 
-        self.colr_atom.matched = true;
+                let format = description.sample_entry.kind;
+                if let Some(chroma_format) = prores::expected_chroma_format(format) {
+                    check(
+                        &|frame| frame.chroma_format != chroma_format,
+                        format!(
+                            "aren't {} as '{}' is",
+                            prores::describe_chroma_format(chroma_format),
+                            atom::fourcc(&format)
+                        ),
+                    );
+                }
+                check(
+                    &|frame| (frame.width, frame.height) != (description.width, description.height),
+                    format!(
+                        "aren't {}x{} as the sample description is",
+                        description.width, description.height
+                    ),
+                );
+                let fiel_atom = &description.fiel_atom;
+                if let (true, Some(interlace_mode)) = (
+                    fiel_atom.matched,
+                    InterlaceMode::from_fiel_bytes(&fiel_atom.fields),
+                ) {
+                    check(
+                        &|frame| frame.interlace_mode != interlace_mode.prores_code(),
+                        format!("aren't {} as the fiel atom says", interlace_mode),
+                    );
+                }
+                if description.pasp_atom.matched {
+                    let pixel_aspect_ratio = description.pasp_atom.pixel_aspect_ratio;
+                    check(
+                        &|frame| {
+                            frame.aspect_ratio_information != 0
+                                && frame.aspect_ratio_information
+                                    != pixel_aspect_ratio
+                                        .prores_aspect_ratio_information(frame.width, frame.height)
+                        },
+                        format!(
+                            "have an aspect ratio code contradicting the {} pasp atom",
+                            pixel_aspect_ratio
+                        ),
+                    );
+                }
+                check(
+                    &|frame| {
+                        FrameRate::from_prores_code(frame.frame_rate_code).is_some_and(
+                            |frame_rate| {
+                                frame.duration != 0
+                                    && !frame_rate.matches(track.timescale, frame.duration)
+                            },
+                        )
+                    },
+                    format!(
+                        "have a frame rate code contradicting their duration in the mdhd time scale of {}",
+                        track.timescale
+                    ),
+                );
+            }
+        }
 
-        Ok(())
+        mismatches
     }
 
-    /// Constructs a gama atom.
-    ///
-    /// Based on whether the four bytes before the gama
-    /// offset/position candidates are in a specific pattern (that is: `0x00, 0x00,
-    /// 0x00, 0x0c` which is the size of gama atom) to determine whether it's a real
-    /// offset of gama atom.
-    ///
-    /// # Arguments
-    ///
-    /// * `file` - A mutable reference to a `File` instance.
-    /// * `offset` - The offset of the gama atom in the file.
+    /// Returns the track ID and the 1-based index of the selected sample description
+    /// entries whose honoured colr atom, according to `colr_policy`, has the PQ
+    /// transfer function but which have no clli atom: HDR10 deliverables need both.
+    pub fn pq_without_content_light_level(
+        &self,
+        selection: Selection,
+        colr_policy: ColrPolicy,
+    ) -> Result<Vec<(u32, u32)>, Error> {
+        let mut missing = Vec::new();
+
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let track = &self.tracks[track_index];
+            let description = &track.sample_descriptions[description_index];
+            let is_pq = description
+                .honoured_colr_atom(colr_policy)
+                .is_some_and(|colr_atom| {
+                    colr_atom.color_parameter_type.has_indexes()
+                        && colr_atom.transfer_function_index == TRANSFER_FUNCTION_PQ
+                });
+            if is_pq && !description.clli_atom.matched {
+                missing.push((track.track_id, description.index));
+            }
+        }
+
+        Ok(missing)
+    }
+
+    /// Returns the ICC profiles embedded in the selected 'prof' and 'rICC' colr atoms
+    /// of the selected sample description entries, with the track ID and the 1-based
+    /// sample description index they come from.
     ///
     /// # Errors
     ///
-    /// This function returns an `io::Result` in case of an I/O error occurring when
-    /// seeking file or read bytes from file..
-    fn construct_gama_atom(&mut self, file: &mut File, offset: usize) -> io::Result<()> {
-        self.gama_atom.offsets.push(offset as u64);
+    /// Returns `Error::IccProfileNotFound` if none of the selected sample descriptions
+    /// embeds an ICC profile, and `Error::InvalidIccProfile` if an embedded profile is
+    /// malformed.
+    pub fn icc_profiles(
+        &self,
+        file: &mut File,
+        selection: Selection,
+    ) -> Result<Vec<(u32, u32, IccProfile)>, Error> {
+        let mut profiles = Vec::new();
+
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let track = &self.tracks[track_index];
+            let description = &track.sample_descriptions[description_index];
+            for colr_atom in description
+                .selected_colr_atoms(selection.colr)
+                .into_iter()
+                .filter(|colr_atom| colr_atom.color_parameter_type.is_icc())
+            {
+                // The profile follows the atom header and the color parameter type.
+                let mut bytes = vec![0; colr_atom.size.saturating_sub(12) as usize];
+                file.seek(io::SeekFrom::Start(colr_atom.offset + 12))?;
+                file.read_exact(&mut bytes)?;
+                profiles.push((
+                    track.track_id,
+                    description.index,
+                    IccProfile::from_bytes(bytes)?,
+                ));
+            }
+        }
 
-        let mut size_buf = [0; 4];
-        file.seek(io::SeekFrom::Start(offset as u64))?;
-        file.read_exact(&mut size_buf)?;
+        if profiles.is_empty() {
+            return Err(Error::IccProfileNotFound);
+        }
+
+        Ok(profiles)
+    }
 
-        if size_buf == [0x00, 0x00, 0x00, 0x0c] {
-            self.gama_atom.the_actual_gama_offset = offset as u64;
-            self.gama_atom.size = u32::from_be_bytes(size_buf);
-            self.gama_atom.matched = true;
+    /// Embeds an ICC profile into the selected video sample description entries: a
+    /// 'prof' colr atom replaces the first selected colr atom embedding an ICC profile,
+    /// or is added after the last child atom of the entry if there is none.
+    pub fn set_icc_profile(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        profile: &IccProfile,
+    ) -> Result<(), Error> {
+        let size = u32::try_from(12 + profile.bytes().len())
+            .map_err(|_| Error::InvalidIccProfile("too big for a colr atom".to_string()))?;
+        let mut colr_atom = Vec::with_capacity(size as usize);
+        colr_atom.extend_from_slice(&size.to_be_bytes());
+        colr_atom.extend_from_slice(&atom::COLR);
+        colr_atom.extend_from_slice(b"prof");
+        colr_atom.extend_from_slice(profile.bytes());
 
-            let mut value_buf = size_buf;
-            file.seek(io::SeekFrom::Start(offset as u64 + 8))?;
-            file.read_exact(&mut value_buf)?;
-            self.gama_atom.gama_value = u32::from_be_bytes(value_buf);
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let existing = self.tracks[track_index].sample_descriptions[description_index]
+                .selected_colr_atoms(selection.colr)
+                .into_iter()
+                .find(|colr_atom| colr_atom.color_parameter_type.is_icc());
+            if let Some(existing) = existing {
+                let (offset, size) = (existing.offset, existing.size as u64);
+                self.replace_in_sample_entry(
+                    file,
+                    (track_index, description_index),
+                    offset,
+                    size,
+                    &colr_atom,
+                )?;
+            } else {
+                self.insert_into_sample_entry(file, (track_index, description_index), &colr_atom)?;
+            }
         }
 
         Ok(())
     }
 
-    /// Constructs a ProRes frame from a file at a given offset.
+    /// Sets the full range flag of the selected 'nclx' colr atoms of each selected
+    /// sample description entry: `true` for full range, `false` for limited ("video")
+    /// range. The video_full_range_flag of the sequence parameter sets of H.264 and
+    /// HEVC sample descriptions is set too, in their avcC or hvcC atom and in the
+    /// parameter sets carried by their samples.
     ///
-    /// # Arguments
+    /// # Errors
     ///
-    /// * `file` - A mutable reference to a `File` object.
-    /// * `offset` - The offset in bytes from the start of the file where the frame is
-    ///   located.
+    /// Returns `Error::NclxColrAtomNotFound` if one of the selected sample descriptions
+    /// has no selected colr atom of type 'nclx', since the other types have no such
+    /// flag, `Error::UnsupportedSequenceParameterSet` if it has a sequence parameter set
+    /// that can't be parsed, `Error::NalUnitLengthOverflow` if a rewritten one no
+    /// longer fits the length field preceding it in its sample, and an I/O error if the
+    /// longer ones would make a size or a chunk offset overflow its 32-bit field.
+    /// Nothing is written in these cases.
+    pub fn set_full_range_flag(
+        &mut self,
+        file: &mut File,
+        selection: Selection,
+        full_range: bool,
+    ) -> Result<(), Error> {
+        let selected = self.select_sample_descriptions(selection)?;
+        let mut nclx_colr_atom_offsets = Vec::new();
+        for &(track_index, description_index) in selected.iter() {
+            let track = &self.tracks[track_index];
+            let description = &track.sample_descriptions[description_index];
+            let offsets: Vec<u64> = description
+                .selected_colr_atoms(selection.colr)
+                .into_iter()
+                .filter(|colr_atom| colr_atom.color_parameter_type == ColorParameterType::Nclx)
+                .map(|colr_atom| colr_atom.offset)
+                .collect();
+            if offsets.is_empty() {
+                return Err(Error::NclxColrAtomNotFound {
+                    track_id: track.track_id,
+                });
+            }
+            track.check_sequence_parameter_sets(description)?;
+            nclx_colr_atom_offsets.extend(offsets);
+        }
+        self.check_sequence_parameter_set_rewrite(file, &selected, None, Some(full_range))?;
+
+        for &offset in nclx_colr_atom_offsets.iter() {
+            file.seek(io::SeekFrom::Start(offset + 18))?;
+            file.write_all(&[Self::full_range_byte(full_range)])?;
+        }
+        for &(track_index, description_index) in selected.iter() {
+            for colr_atom in self.tracks[track_index].sample_descriptions[description_index]
+                .colr_atoms
+                .iter_mut()
+                .filter(|colr_atom| nclx_colr_atom_offsets.contains(&colr_atom.offset))
+            {
+                colr_atom.full_range_flag = full_range;
+            }
+        }
+
+        self.rewrite_sequence_parameter_sets(file, &selected, None, Some(full_range))
+    }
+
+    /// Checks that the sequence parameter sets of the `selected` sample descriptions
+    /// can be rewritten as [`Video::rewrite_sequence_parameter_sets`] does: each of
+    /// them is rewritten in memory, along with the stsz atoms of the samples holding
+    /// them, and the longer atoms and samples must leave every size and chunk offset
+    /// of the file within its field. Nothing is written.
     ///
     /// # Errors
     ///
-    /// This function returns an `io::Result` in case of any I/O errors that occur while
-    /// reading from the file.
-    fn construct_prores_frame(&mut self, file: &mut File, offset: usize) -> io::Result<()> {
-        let mut frame = ProResFrame::new();
-        frame.offset = offset as u64;
+    /// Returns `Error::UnsupportedSequenceParameterSet` if an SPS can't be parsed,
+    /// `Error::NalUnitLengthOverflow` if a rewritten SPS of the samples no longer fits
+    /// the length field preceding it, `Error::MediaTimingNotFound` if a track has no
+    /// stsz atom or if it doesn't list a sample holding one, and an I/O error if a size
+    /// or a chunk offset would no longer fit in its 32-bit field.
+    fn check_sequence_parameter_set_rewrite(
+        &self,
+        file: &mut File,
+        selected: &[(usize, usize)],
+        colour_description: Option<[u8; 3]>,
+        full_range: Option<bool>,
+    ) -> Result<(), Error> {
+        let mut growths = Vec::new();
+        for (track_index, description_index, new_atom) in
+            self.rewritten_configuration_atoms(file, selected, colour_description, full_range)?
+        {
+            let description = &self.tracks[track_index].sample_descriptions[description_index];
+            let configuration_atom = &description.configuration_atom;
+            growths.push(edit::Growth::new(
+                atom::path_to(file, description.sample_entry.offset)?,
+                configuration_atom.offset,
+                configuration_atom.size as u64,
+                new_atom.len(),
+                true,
+            ));
+        }
 
-        let mut frame_size_buf = [0; 4];
-        file.seek(io::SeekFrom::Start(frame.offset))?;
-        file.read_exact(&mut frame_size_buf)?;
-        frame.frame_size = u32::from_be_bytes(frame_size_buf);
+        let in_band = self.rewritten_in_band_sps(file, selected, colour_description, full_range)?;
+        for (top_level, group) in in_band.groups.iter() {
+            growths.extend(group.iter().map(|(at, remove_len, bytes)| {
+                edit::Growth::new([*top_level].to_vec(), *at, *remove_len, bytes.len(), false)
+            }));
+        }
+        for track_index in in_band.resized_tracks() {
+            let (stbl, stsz, stsz_atom) =
+                self.rewritten_stsz_atom(file, track_index, &in_band.size_changes)?;
+            growths.push(edit::Growth::new(
+                atom::path_to(file, stbl.offset)?,
+                stsz.offset,
+                stsz.size,
+                stsz_atom.len(),
+                true,
+            ));
+        }
 
-        let mut frame_header_size_buf = [0; 2];
-        file.seek(io::SeekFrom::Start(frame.offset + 8))?;
-        file.read_exact(&mut frame_header_size_buf)?;
-        frame.frame_header_size = u16::from_be_bytes(frame_header_size_buf);
+        Ok(edit::check_growths(file, &growths)?)
+    }
+
+    /// Rewrites the video signal type of every sequence parameter set of the
+    /// `selected` sample descriptions: the colour description and the full range flag
+    /// are replaced by the given ones, or kept if `None`. Those of the avcC or hvcC
+    /// atom come first, replaced along with their atom when they get longer (see
+    /// [`Video::replace_in_sample_entry`]), then those repeated in the samples (see
+    /// [`Video::rewrite_in_band_sps`]).
+    ///
+    /// The caller checks beforehand that they can be rewritten, see
+    /// [`Video::check_sequence_parameter_set_rewrite`].
+    fn rewrite_sequence_parameter_sets(
+        &mut self,
+        file: &mut File,
+        selected: &[(usize, usize)],
+        colour_description: Option<[u8; 3]>,
+        full_range: Option<bool>,
+    ) -> Result<(), Error> {
+        for (track_index, description_index, new_atom) in
+            self.rewritten_configuration_atoms(file, selected, colour_description, full_range)?
+        {
+            // A previous replacement may have moved this atom.
+            let configuration_atom =
+                &self.tracks[track_index].sample_descriptions[description_index].configuration_atom;
+            let (offset, size) = (configuration_atom.offset, configuration_atom.size as u64);
+            self.replace_in_sample_entry(
+                file,
+                (track_index, description_index),
+                offset,
+                size,
+                &new_atom,
+            )?;
+        }
 
-        let mut color_primaries_buf = [0; 1];
-        file.seek(io::SeekFrom::Start(frame.offset + 22))?;
-        file.read_exact(&mut color_primaries_buf)?;
-        frame.color_primaries = u8::from_be_bytes(color_primaries_buf);
+        self.rewrite_in_band_sps(file, selected, colour_description, full_range)
+    }
 
-        let mut transfer_characteristics_buf = [0; 1];
-        file.seek(io::SeekFrom::Start(frame.offset + 23))?;
-        file.read_exact(&mut transfer_characteristics_buf)?;
-        frame.transfer_characteristic = u8::from_be_bytes(transfer_characteristics_buf);
+    /// Rewrites in memory the sequence parameter sets of the avcC or hvcC atom of the
+    /// `selected` sample descriptions. An SPS without a video signal type gets one,
+    /// which makes it longer. Returns the track index, the sample description index
+    /// and the new configuration atom of each sample description whose atom changes.
+    fn rewritten_configuration_atoms(
+        &self,
+        file: &mut File,
+        selected: &[(usize, usize)],
+        colour_description: Option<[u8; 3]>,
+        full_range: Option<bool>,
+    ) -> Result<Vec<(usize, usize, Vec<u8>)>, Error> {
+        let mut rewritten = Vec::new();
+        for &(track_index, description_index) in selected {
+            let track_id = self.tracks[track_index].track_id;
+            let configuration_atom =
+                &self.tracks[track_index].sample_descriptions[description_index].configuration_atom;
+            if !configuration_atom.matched {
+                continue;
+            }
+            let (offset, size) = (configuration_atom.offset, configuration_atom.size as u64);
+            let codec = configuration_atom.codec;
 
-        let mut matrix_coefficients_buf = [0; 1];
-        file.seek(io::SeekFrom::Start(frame.offset + 24))?;
-        file.read_exact(&mut matrix_coefficients_buf)?;
-        frame.matrix_coefficients = u8::from_be_bytes(matrix_coefficients_buf);
+            let mut payload = vec![0; (size - atom::ATOM_HEADER_SIZE) as usize];
+            file.seek(io::SeekFrom::Start(offset + atom::ATOM_HEADER_SIZE))?;
+            file.read_exact(&mut payload)?;
+            let unsupported = Error::UnsupportedSequenceParameterSet { track_id };
+            let mut configuration =
+                DecoderConfiguration::from_bytes(codec, &payload).ok_or(unsupported)?;
+            for sps in configuration.sequence_parameter_sets_mut() {
+                *sps = avc::rewrite_sps(codec, sps, colour_description, full_range)
+                    .ok_or(Error::UnsupportedSequenceParameterSet { track_id })?;
+            }
 
-        self.frames.push(frame);
-        self.frame_count += 1;
+            let new_payload = configuration.to_bytes();
+            if new_payload != payload {
+                let kind = match codec {
+                    Codec::Avc => atom::AVCC,
+                    Codec::Hevc => atom::HVCC,
+                };
+                rewritten.push((
+                    track_index,
+                    description_index,
+                    atom::build(kind, &new_payload),
+                ));
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Rewrites the sequence parameter sets repeated in the samples of the `selected`
+    /// sample descriptions ('avc3', 'avc4' and 'hev1'), each NAL unit with its length.
+    /// Like the ProRes frame headers, they are overwritten where they are when their
+    /// length doesn't change. A longer SPS moves the rest of the essence: every SPS of
+    /// an mdat atom is replaced in a single pass (see [`edit::splice_many`]), then the
+    /// stsz atom of each track gets the new sizes of its samples.
+    fn rewrite_in_band_sps(
+        &mut self,
+        file: &mut File,
+        selected: &[(usize, usize)],
+        colour_description: Option<[u8; 3]>,
+        full_range: Option<bool>,
+    ) -> Result<(), Error> {
+        let in_band = self.rewritten_in_band_sps(file, selected, colour_description, full_range)?;
+        if in_band.groups.is_empty() {
+            return Ok(());
+        }
+
+        // The last top-level atom is edited first, so that the offsets in the others
+        // stay valid.
+        for (top_level, group) in in_band.groups.iter().rev() {
+            edit::splice_many(file, &[*top_level], group)?;
+        }
+        for track_index in in_band.resized_tracks() {
+            // The essence, or the stsz atom of another track, may have moved this one.
+            let (stbl, stsz, stsz_atom) =
+                self.rewritten_stsz_atom(file, track_index, &in_band.size_changes)?;
+            self.replace_in(file, &stbl, stsz.offset, stsz.size, &stsz_atom)?;
+        }
+
+        self.redecode(file)?;
 
         Ok(())
     }
 
-    /// Decodes a video file and constructs the corresponding atoms and frames.
-    ///
-    /// # Arguments
-    ///
-    /// * `file_path` - A string slice that holds the path to the video file.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use atom_modifier::Video;
-    ///
-    /// let mut video = Video::default();
-    /// video.decode("tests/footages/1-1-1_2frames_prores422.mov").unwrap();
-    /// ```
+    /// Rewrites in memory the sequence parameter sets repeated in the samples of the
+    /// `selected` sample descriptions, see [`Video::rewrite_in_band_sps`].
+    fn rewritten_in_band_sps(
+        &self,
+        file: &mut File,
+        selected: &[(usize, usize)],
+        colour_description: Option<[u8; 3]>,
+        full_range: Option<bool>,
+    ) -> Result<InBandSpsRewrite, Error> {
+        let mut replacements: Vec<Replacement> = Vec::new();
+        let mut size_changes: Vec<(usize, usize, i64)> = Vec::new();
+        for &(track_index, description_index) in selected {
+            let track = &self.tracks[track_index];
+            let description = &track.sample_descriptions[description_index];
+            let codec = description.configuration_atom.codec;
+            let length_size = description.configuration_atom.nal_length_size;
+            for in_band_sps in track
+                .in_band_sps
+                .iter()
+                .filter(|sps| sps.sample_description_index == description.index)
+            {
+                let mut nal = vec![0; in_band_sps.size as usize];
+                file.seek(io::SeekFrom::Start(in_band_sps.offset))?;
+                file.read_exact(&mut nal)?;
+                let rewritten = avc::rewrite_sps(codec, &nal, colour_description, full_range)
+                    .ok_or(Error::UnsupportedSequenceParameterSet {
+                        track_id: track.track_id,
+                    })?;
+                if length_size < 4 && rewritten.len() >> (8 * length_size) != 0 {
+                    return Err(Error::NalUnitLengthOverflow {
+                        track_id: track.track_id,
+                    });
+                }
+
+                let mut bytes = (rewritten.len() as u32).to_be_bytes()[4 - length_size..].to_vec();
+                bytes.extend_from_slice(&rewritten);
+                let delta = rewritten.len() as i64 - nal.len() as i64;
+                if delta != 0 {
+                    size_changes.push((track_index, in_band_sps.sample_index as usize, delta));
+                }
+                let at = in_band_sps.offset - length_size as u64;
+                replacements.push((at, length_size as u64 + nal.len() as u64, bytes));
+            }
+        }
+
+        // The essence is only in top-level atoms, mdat usually.
+        replacements.sort_by_key(|replacement| replacement.0);
+        let mut groups = Vec::new();
+        if !replacements.is_empty() {
+            let file_size = file.metadata()?.len();
+            for top_level in atom::read_children(file, 0, file_size)? {
+                let group: Vec<Replacement> = replacements
+                    .iter()
+                    .filter(|(at, _, _)| (top_level.offset..top_level.end()).contains(at))
+                    .cloned()
+                    .collect();
+                if !group.is_empty() {
+                    groups.push((top_level, group));
+                }
+            }
+        }
+
+        Ok(InBandSpsRewrite {
+            groups,
+            size_changes,
+        })
+    }
+
+    /// Builds the stsz atom of the track at `track_index` once the sizes of its
+    /// samples change as `size_changes` says, returning it along with the stbl atom of
+    /// the track and the stsz atom it replaces.
     ///
-    /// # Notes
+    /// # Errors
     ///
-    /// This method only needs read access to the file.
-    pub fn decode(&mut self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let file = OpenOptions::new().read(true).open(file_path)?;
+    /// Returns `Error::MediaTimingNotFound` if the track has no stsz atom or if it
+    /// doesn't list a sample that changes size, and `Error::NalUnitLengthOverflow` if
+    /// the new size of a sample doesn't fit its 32 bits.
+    fn rewritten_stsz_atom(
+        &self,
+        file: &mut File,
+        track_index: usize,
+        size_changes: &[(usize, usize, i64)],
+    ) -> Result<(AtomHeader, AtomHeader, Vec<u8>), Error> {
+        let track_id = self.tracks[track_index].track_id;
+        let trak = atom::traks(file)?[track_index];
+        let timing =
+            TrakTiming::read(file, &trak)?.ok_or(Error::MediaTimingNotFound { track_id })?;
+        let stsz = atom::find(&timing.stbl_children, atom::STSZ)
+            .ok_or(Error::MediaTimingNotFound { track_id })?;
 
-        let search_patterns = [COLR_ATOM_HEADER, GAMA_ATOM_HEADER, PRORES_FRAME_HEADER];
-        let ac = AhoCorasick::new(search_patterns)?;
+        let sample_table = SampleTable::read(file, &timing.stbl_children)?;
+        let mut sample_sizes: Vec<u32> = (0..sample_table.sample_count as usize)
+            .map_while(|index| sample_table.size_of(index))
+            .collect();
+        for &(_, sample_index, delta) in
+            size_changes.iter().filter(|change| change.0 == track_index)
+        {
+            let sample_size = sample_sizes
+                .get_mut(sample_index)
+                .ok_or(Error::MediaTimingNotFound { track_id })?;
+            *sample_size = u32::try_from(*sample_size as i64 + delta)
+                .map_err(|_| Error::NalUnitLengthOverflow { track_id })?;
+        }
 
-        // error of result is std::io::Error
-        for result in ac.stream_find_iter(&file) {
-            let mat = result?;
+        // stsz: version and flags, a sample size of 0 as the samples have their own,
+        // the sample count, then the size of each sample.
+        let mut stsz_payload = vec![0; 8];
+        stsz_payload.extend_from_slice(&(sample_sizes.len() as u32).to_be_bytes());
+        for sample_size in sample_sizes {
+            stsz_payload.extend_from_slice(&sample_size.to_be_bytes());
+        }
 
-            let mut file_to_seek = OpenOptions::new().read(true).open(file_path)?;
+        Ok((timing.stbl, stsz, atom::build(atom::STSZ, &stsz_payload)))
+    }
 
-            match mat.pattern().as_u32() {
-                0 => self.construct_colr_atom(&mut file_to_seek, mat.start() - 4)?,
-                1 => self.construct_gama_atom(&mut file_to_seek, mat.start() - 4)?,
-                2 => self.construct_prores_frame(&mut file_to_seek, mat.start() - 4)?,
-                _ => unreachable!(),
-            };
+    /// Returns the last byte of an 'nclx' colr atom: the full range flag in its most
+    /// significant bit, followed by 7 reserved bits.
+    fn full_range_byte(full_range: bool) -> u8 {
+        if full_range {
+            0x80
+        } else {
+            0
         }
-
-        Ok(())
     }
 
+    /// Overwrites the color primaries, transfer characteristics and matrix
+    /// coefficients of the selected 'nclc' and 'nclx' colr atoms of each selected
+    /// sample description entry, of every ProRes frame header that refers to it
+    /// (through `stsc`) and of the sequence parameter sets of its avcC or hvcC atom and
+    /// of its samples, and the gama
+    /// value of its gama atom if present.
+    ///
+    /// Selected colr atoms embedding an ICC profile are left untouched, see
+    /// [`Video::colr_atoms_left_untouched`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ColrAtomNotFound` if one of the selected sample descriptions has
+    /// no selected colr atom, `Error::ColrAtomWithoutIndexes` if none of them is of type
+    /// 'nclc' or 'nclx', and `Error::ProResFramesNotFound` if it is ProRes but no ProRes
+    /// frame refers to it: patching the default offsets would corrupt the file. The same
+    /// goes for `Error::UnsupportedSequenceParameterSet`, if a sequence parameter set
+    /// can't be parsed, `Error::NalUnitLengthOverflow`, if a rewritten one no longer
+    /// fits the length field preceding it in its sample, and an I/O error if the longer
+    /// ones would make a size or a chunk offset overflow its 32-bit field. Nothing is
+    /// written in these cases.
     pub fn encode(
-        &self,
+        &mut self,
         file: &mut File,
-        video: &Video,
+        selection: Selection,
         target_color_primaries: u8,
         target_transfer_functions: u8,
         target_matrix: u8,
         target_gama_value: f32,
-    ) -> io::Result<()> {
-        // Overwrite mov colr atom
-        let buf = [
-            0,
+    ) -> Result<(), Error> {
+        let selected = self.select_sample_descriptions(selection)?;
+        let descriptions = || {
+            selected.iter().map(|&(track_index, description_index)| {
+                let track = &self.tracks[track_index];
+                (track, &track.sample_descriptions[description_index])
+            })
+        };
+
+        for (track, description) in descriptions() {
+            if description.selected_colr_atoms(selection.colr).is_empty() {
+                return Err(Error::ColrAtomNotFound {
+                    track_id: track.track_id,
+                });
+            }
+            if description
+                .selected_colr_atoms_with_indexes(selection.colr)
+                .is_empty()
+            {
+                return Err(Error::ColrAtomWithoutIndexes {
+                    track_id: track.track_id,
+                });
+            }
+            let format = description.sample_entry.kind;
+            if PRORES_FORMATS.contains(&format)
+                && !track
+                    .frames
+                    .iter()
+                    .any(|frame| frame.sample_description_index == description.index)
+            {
+                return Err(Error::ProResFramesNotFound {
+                    track_id: track.track_id,
+                    format,
+                });
+            }
+            track.check_sequence_parameter_sets(description)?;
+        }
+        let colour_description = [
             target_color_primaries,
-            0,
             target_transfer_functions,
-            0,
             target_matrix,
         ];
-        file.seek(io::SeekFrom::Start(video.colr_atom.offset + 12))?;
-        file.write_all(&buf)?;
-
-        // Overwrite each ProRes frame
-        for frame in video.frames.iter() {
-            let buf = [
-                target_color_primaries,
-                target_transfer_functions,
-                target_matrix,
-            ];
-            file.seek(io::SeekFrom::Start(frame.offset + 22))?;
-            file.write_all(&buf)?;
+        self.check_sequence_parameter_set_rewrite(file, &selected, Some(colour_description), None)?;
+
+        for (track, description) in descriptions() {
+            // Overwrite mov colr atoms
+            for colr_atom in description.selected_colr_atoms_with_indexes(selection.colr) {
+                let buf = [
+                    0,
+                    target_color_primaries,
+                    0,
+                    target_transfer_functions,
+                    0,
+                    target_matrix,
+                ];
+                file.seek(io::SeekFrom::Start(colr_atom.offset + 12))?;
+                file.write_all(&buf)?;
+            }
+
+            // Overwrite each ProRes frame described by this sample description
+            for frame in track
+                .frames
+                .iter()
+                .filter(|frame| frame.sample_description_index == description.index)
+            {
+                let buf = [
+                    target_color_primaries,
+                    target_transfer_functions,
+                    target_matrix,
+                ];
+                file.seek(io::SeekFrom::Start(frame.offset + 22))?;
+                file.write_all(&buf)?;
+            }
+
+            // Overwrite gama atom
+            //
+            // If gama atom matched, it means that the original file has gama atom and
+            // also has gama value. At this time, -g of args can work, and the original
+            // gama value is overwritten with the value given by -g. If gama atom
+            // doesn't match, it has to be inserted beforehand with `insert_gama_atom`.
+            if description.gama_atom.matched && target_gama_value != -1.0 {
+                let new_gama_value = Self::float_to_bytes(target_gama_value);
+                file.seek(io::SeekFrom::Start(description.gama_atom.offset + 8))?;
+                file.write_all(&new_gama_value)?;
+            }
         }
 
-        // Overwrite gama atom
-        //
-        // If gama atom matched, it means that the original file has gama atom and also
-        // has gama value. At this time, -g of args can work, and the original gama
-        // value is overwritten with the value given by -g. If gama atom doesn't match,
-        // just ignore the -g arg.
-        if self.gama_atom.matched
-            && self.gama_atom.the_actual_gama_offset != 0
-            && target_gama_value != -1.0
-        {
-            let new_gama_value = Self::float_to_bytes(target_gama_value);
-            file.seek(io::SeekFrom::Start(
-                video.gama_atom.the_actual_gama_offset + 8,
-            ))?;
-            file.write_all(&new_gama_value)?;
+        // Keep the description of the file in step with what was written, as the
+        // structural edits don't read the frames again.
+        for &(track_index, description_index) in selected.iter() {
+            let track = &mut self.tracks[track_index];
+            let description = &mut track.sample_descriptions[description_index];
+            let written: Vec<u64> = description
+                .selected_colr_atoms_with_indexes(selection.colr)
+                .iter()
+                .map(|colr_atom| colr_atom.offset)
+                .collect();
+            for colr_atom in description
+                .colr_atoms
+                .iter_mut()
+                .filter(|colr_atom| written.contains(&colr_atom.offset))
+            {
+                colr_atom.primary_index = target_color_primaries as u16;
+                colr_atom.transfer_function_index = target_transfer_functions as u16;
+                colr_atom.matrix_index = target_matrix as u16;
+            }
+            if description.gama_atom.matched && target_gama_value != -1.0 {
+                description.gama_atom.gama_value =
+                    u32::from_be_bytes(Self::float_to_bytes(target_gama_value));
+            }
+
+            let index = description.index;
+            for frame in track
+                .frames
+                .iter_mut()
+                .filter(|frame| frame.sample_description_index == index)
+            {
+                frame.color_primaries = target_color_primaries;
+                frame.transfer_characteristic = target_transfer_functions;
+                frame.matrix_coefficients = target_matrix;
+            }
         }
 
-        Ok(())
+        // Last, as a longer SPS moves the atoms after it.
+        self.rewrite_sequence_parameter_sets(file, &selected, Some(colour_description), None)
+    }
+
+    /// Returns the colr atoms of the selected sample description entries that
+    /// [`Video::encode`] leaves untouched, with the track ID and the 1-based sample
+    /// description index they come from: those `selection.colr` leaves out and those
+    /// embedding an ICC profile. They may contradict the new indexes.
+    pub fn colr_atoms_left_untouched(
+        &self,
+        selection: Selection,
+    ) -> Result<Vec<(u32, u32, ColorParameterType)>, Error> {
+        let mut untouched = Vec::new();
+
+        for (track_index, description_index) in self.select_sample_descriptions(selection)? {
+            let track = &self.tracks[track_index];
+            let description = &track.sample_descriptions[description_index];
+            let selected = description.selected_colr_atoms(selection.colr);
+            for colr_atom in description.colr_atoms.iter() {
+                let touched = colr_atom.color_parameter_type.has_indexes()
+                    && selected.iter().any(|s| std::ptr::eq(*s, colr_atom));
+                if !touched {
+                    untouched.push((
+                        track.track_id,
+                        description.index,
+                        colr_atom.color_parameter_type,
+                    ));
+                }
+            }
+        }
+
+        Ok(untouched)
+    }
+
+    /// Describes the tracks of the file and their tapt atom, their sample descriptions,
+    /// the colr, gama, mdcv, clli, clap, fiel and pasp atoms of these and the frame
+    /// headers of their ProRes frames, marking the colr atom a player would honour
+    /// according to `colr_policy`.
+    pub fn info(&self, colr_policy: ColrPolicy) -> String {
+        let mut info = String::new();
+
+        for track in self.tracks.iter() {
+            let _ = write!(
+                info,
+                "Track {} ('{}')",
+                track.track_id,
+                atom::fourcc(&track.handler_type)
+            );
+            if track.is_video() {
+                let _ = write!(info, ", {} ProRes frames", track.frame_count);
+            }
+            if let Some(timecode) = track.start_timecode() {
+                let _ = write!(info, ", starting at {}", timecode);
+            }
+            let _ = writeln!(info);
+            if track.tapt_atom.matched {
+                let _ = writeln!(info, "  tapt: {}", track.tapt_atom.track_aperture);
+            }
+
+            for description in track.sample_descriptions.iter() {
+                let _ = writeln!(
+                    info,
+                    "  Sample description {}: '{}'",
+                    description.index,
+                    atom::fourcc(&description.sample_entry.kind)
+                );
+                if description.sample_entry.kind == atom::TMCD {
+                    let _ = writeln!(info, "    timecode: {}", description.timecode_format);
+                }
+                if description.name_atom.matched {
+                    let _ = writeln!(info, "    reel name: {}", description.name_atom.reel_name);
+                }
+                let honoured = description.honoured_colr_atom(colr_policy);
+                for colr_atom in description.colr_atoms.iter() {
+                    let _ = write!(info, "    colr '{}': ", colr_atom.color_parameter_type);
+                    let color_parameter_type = colr_atom.color_parameter_type;
+                    if color_parameter_type.has_indexes() {
+                        let _ = write!(
+                            info,
+                            "primaries {}, transfer function {}, matrix {}",
+                            colr_atom.primary_index,
+                            colr_atom.transfer_function_index,
+                            colr_atom.matrix_index
+                        );
+                        if color_parameter_type == ColorParameterType::Nclx {
+                            let range = if colr_atom.full_range_flag {
+                                "full"
+                            } else {
+                                "limited"
+                            };
+                            let _ = write!(info, ", {} range", range);
+                        }
+                    } else if color_parameter_type.is_icc() {
+                        let _ = write!(info, "ICC profile of {} bytes", colr_atom.size - 12);
+                    } else {
+                        let _ = write!(info, "{} bytes", colr_atom.size);
+                    }
+                    if honoured.is_some_and(|h| std::ptr::eq(h, colr_atom)) {
+                        let _ = write!(info, " (honoured)");
+                    }
+                    let _ = writeln!(info);
+                }
+                let video_signal_type = |sps: &Option<SequenceParameterSet>| match sps
+                    .map(|sps| sps.video_signal_type)
+                {
+                    Some(Some(video_signal_type)) => video_signal_type.to_string(),
+                    Some(None) => "no video signal type".to_string(),
+                    None => "not understood".to_string(),
+                };
+                for (index, sps) in description
+                    .configuration_atom
+                    .sequence_parameter_sets
+                    .iter()
+                    .enumerate()
+                {
+                    let _ = writeln!(info, "    SPS {}: {}", index + 1, video_signal_type(sps));
+                }
+                // The SPSs repeated in the samples are usually all the same: each
+                // different one is counted.
+                let mut in_band: Vec<(String, usize)> = Vec::new();
+                for sps in track
+                    .in_band_sps
+                    .iter()
+                    .filter(|sps| sps.sample_description_index == description.index)
+                {
+                    let summary = video_signal_type(&sps.sequence_parameter_set);
+                    match in_band
+                        .iter_mut()
+                        .find(|(existing, _)| *existing == summary)
+                    {
+                        Some((_, count)) => *count += 1,
+                        None => in_band.push((summary, 1)),
+                    }
+                }
+                for (summary, count) in in_band {
+                    let plural = if count > 1 { "s" } else { "" };
+                    let _ = writeln!(info, "    SPS in {} sample{}: {}", count, plural, summary);
+                }
+                if description.gama_atom.matched {
+                    let _ = writeln!(
+                        info,
+                        "    gama: {}",
+                        Self::bytes_to_float(description.gama_atom.gama_value.to_be_bytes())
+                    );
+                }
+                if description.mdcv_atom.matched {
+                    let _ = writeln!(
+                        info,
+                        "    mdcv: {}",
+                        description.mdcv_atom.mastering_display
+                    );
+                }
+                if description.clli_atom.matched {
+                    let _ = writeln!(
+                        info,
+                        "    clli: {}",
+                        description.clli_atom.content_light_level
+                    );
+                }
+                if description.clap_atom.matched {
+                    let _ = writeln!(info, "    clap: {}", description.clap_atom.clean_aperture);
+                }
+                if description.fiel_atom.matched {
+                    let fields = description.fiel_atom.fields;
+                    let _ = write!(
+                        info,
+                        "    fiel: {} fields, ordering {}",
+                        fields[0], fields[1]
+                    );
+                    if let Some(interlace_mode) = InterlaceMode::from_fiel_bytes(&fields) {
+                        let _ = write!(info, " ({})", interlace_mode);
+                    }
+                    let _ = writeln!(info);
+                }
+                if description.pasp_atom.matched {
+                    let _ = writeln!(
+                        info,
+                        "    pasp: {}",
+                        description.pasp_atom.pixel_aspect_ratio
+                    );
+                }
+                let frames: Vec<&ProResFrame> = track
+                    .frames
+                    .iter()
+                    .filter(|frame| frame.sample_description_index == description.index)
+                    .collect();
+                Self::write_prores_frame_field(
+                    &mut info,
+                    "ProRes bitstream version",
+                    &frames,
+                    |frame| frame.bitstream_version,
+                    |version| version.to_string(),
+                );
+                Self::write_prores_frame_field(
+                    &mut info,
+                    "ProRes encoder",
+                    &frames,
+                    |frame| frame.encoder_id,
+                    |encoder_id| format!("'{}'", atom::fourcc(&encoder_id)),
+                );
+                Self::write_prores_frame_field(
+                    &mut info,
+                    "ProRes dimensions",
+                    &frames,
+                    |frame| (frame.width, frame.height),
+                    |(width, height)| format!("{}x{}", width, height),
+                );
+                Self::write_prores_frame_field(
+                    &mut info,
+                    "ProRes chroma format",
+                    &frames,
+                    |frame| frame.chroma_format,
+                    |code| prores::describe_chroma_format(code).to_string(),
+                );
+                Self::write_prores_frame_field(
+                    &mut info,
+                    "ProRes interlace mode",
+                    &frames,
+                    |frame| frame.interlace_mode,
+                    |code| match InterlaceMode::from_prores_code(code) {
+                        Some(interlace_mode) => interlace_mode.to_string(),
+                        None => "reserved".to_string(),
+                    },
+                );
+                Self::write_prores_frame_field(
+                    &mut info,
+                    "ProRes aspect ratio",
+                    &frames,
+                    |frame| frame.aspect_ratio_information,
+                    |code| aspect::describe_prores_aspect_ratio_information(code).to_string(),
+                );
+                Self::write_prores_frame_field(
+                    &mut info,
+                    "ProRes frame rate",
+                    &frames,
+                    |frame| frame.frame_rate_code,
+                    prores::describe_frame_rate_code,
+                );
+                Self::write_prores_frame_field(
+                    &mut info,
+                    "ProRes alpha channel",
+                    &frames,
+                    |frame| frame.alpha_channel_type,
+                    |code| prores::describe_alpha_channel_type(code).to_string(),
+                );
+                Self::write_prores_frame_field(
+                    &mut info,
+                    "ProRes quantization matrices",
+                    &frames,
+                    |frame| {
+                        (
+                            frame.load_luma_quantization_matrix,
+                            frame.load_chroma_quantization_matrix,
+                        )
+                    },
+                    |(luma, chroma)| {
+                        let origin = |loaded| if loaded { "custom" } else { "default" };
+                        format!("luma {}, chroma {}", origin(luma), origin(chroma))
+                    },
+                );
+            }
+        }
+
+        info
+    }
+
+    /// Writes a line describing a field of the frame headers of `frames`, as found in
+    /// the first one, and how many frames disagree with it. Nothing is written if
+    /// there are no frames.
+    fn write_prores_frame_field<T: PartialEq>(
+        info: &mut String,
+        label: &str,
+        frames: &[&ProResFrame],
+        field: impl Fn(&ProResFrame) -> T,
+        describe: impl Fn(T) -> String,
+    ) {
+        let Some(first_frame) = frames.first() else {
+            return;
+        };
+        let value = field(first_frame);
+        let disagreeing = frames.iter().filter(|frame| field(frame) != value).count();
+        let _ = write!(info, "    {}: {}", label, describe(value));
+        if disagreeing > 0 {
+            let _ = write!(info, " ({} frames disagree)", disagreeing);
+        }
+        let _ = writeln!(info);
     }
 
     /// Converts a floating point number to a byte array.
@@ -329,7 +3607,7 @@ impl Video {
     /// # Arguments
     ///
     /// * `bytes` - An array of 4 bytes which represent a big-endian i32 and
-    /// subsequently a fixed-point number with 16 fractional bits.
+    ///   subsequently a fixed-point number with 16 fractional bits.
     ///
     /// # Returns
     ///
@@ -364,6 +3642,7 @@ impl RoundTo for f32 {
 
 #[cfg(test)]
 mod tests {
+    use crate::atom::SizeForm;
     use crate::ColorParameterType::Nclc;
 
     use super::*;
@@ -377,44 +3656,145 @@ mod tests {
             .expect("Some issue occur when decoding '1-1-1_2frames_prores422.mov'.");
 
         let expected_result_111 = Video {
-            colr_atom: ColrAtom {
-                size: 18,
-                _color_parameter_type: Nclc,
-                offset: 1234280,
-                primary_index: 1,
-                transfer_function_index: 1,
-                matrix_index: 1,
-                matched: true,
-            },
-            gama_atom: GamaAtom {
-                size: 0,
-                gama_value: 0,
-                offsets: [].to_vec(),
-                the_actual_gama_offset: 0,
-                matched: false,
-            },
-            frames: [
-                ProResFrame {
-                    offset: 40,
-                    frame_size: 616448,
-                    _frame_id: 0.0,
-                    frame_header_size: 148,
-                    color_primaries: 1,
-                    transfer_characteristic: 1,
-                    matrix_coefficients: 1,
+            major_brand: *b"qt  ",
+            tracks: vec![
+                Track {
+                    track_id: 1,
+                    handler_type: *b"vide",
+                    sample_descriptions: vec![SampleDescription {
+                        index: 1,
+                        sample_entry: AtomHeader {
+                            offset: 1234184,
+                            size: 130,
+                            kind: *b"apcn",
+                            size_form: SizeForm::Compact,
+                        },
+                        width: 1920,
+                        height: 1080,
+                        depth: 24,
+                        colr_atoms: vec![ColrAtom {
+                            size: 18,
+                            color_parameter_type: Nclc,
+                            offset: 1234280,
+                            primary_index: 1,
+                            transfer_function_index: 1,
+                            matrix_index: 1,
+                            full_range_flag: false,
+                        }],
+                        gama_atom: GamaAtom {
+                            size: 0,
+                            offset: 0,
+                            gama_value: 0,
+                            matched: false,
+                        },
+                        mdcv_atom: Default::default(),
+                        clli_atom: Default::default(),
+                        pasp_atom: PaspAtom {
+                            size: 16,
+                            offset: 1234298,
+                            pixel_aspect_ratio: PixelAspectRatio::default(),
+                            matched: true,
+                        },
+                        fiel_atom: FielAtom {
+                            size: 10,
+                            offset: 1234270,
+                            fields: [1, 0],
+                            matched: true,
+                        },
+                        clap_atom: Default::default(),
+                        configuration_atom: Default::default(),
+                        name_atom: Default::default(),
+                        timecode_format: Default::default(),
+                    }],
+                    frames: [
+                        ProResFrame {
+                            offset: 40,
+                            frame_size: 616448,
+                            sample_description_index: 1,
+                            _frame_id: 0.0,
+                            frame_header_size: 148,
+                            color_primaries: 1,
+                            transfer_characteristic: 1,
+                            matrix_coefficients: 1,
+                            width: 1920,
+                            height: 1080,
+                            aspect_ratio_information: 0,
+                            interlace_mode: 0,
+                            picture_count: 1,
+                            bitstream_version: 0,
+                            encoder_id: *b"apl0",
+                            chroma_format: 2,
+                            frame_rate_code: 0,
+                            alpha_channel_type: 0,
+                            load_luma_quantization_matrix: true,
+                            load_chroma_quantization_matrix: true,
+                            duration: 512,
+                        },
+                        ProResFrame {
+                            offset: 616488,
+                            frame_size: 617195,
+                            sample_description_index: 1,
+                            _frame_id: 0.0,
+                            frame_header_size: 148,
+                            color_primaries: 1,
+                            transfer_characteristic: 1,
+                            matrix_coefficients: 1,
+                            width: 1920,
+                            height: 1080,
+                            aspect_ratio_information: 0,
+                            interlace_mode: 0,
+                            picture_count: 1,
+                            bitstream_version: 0,
+                            encoder_id: *b"apl0",
+                            chroma_format: 2,
+                            frame_rate_code: 0,
+                            alpha_channel_type: 0,
+                            load_luma_quantization_matrix: true,
+                            load_chroma_quantization_matrix: true,
+                            duration: 512,
+                        },
+                    ]
+                    .to_vec(),
+                    frame_count: 2,
+                    tapt_atom: Default::default(),
+                    timescale: 12288,
+                    timecode_samples: Vec::new(),
+                    in_band_sps: Vec::new(),
                 },
-                ProResFrame {
-                    offset: 616488,
-                    frame_size: 617195,
-                    _frame_id: 0.0,
-                    frame_header_size: 148,
-                    color_primaries: 1,
-                    transfer_characteristic: 1,
-                    matrix_coefficients: 1,
+                Track {
+                    track_id: 2,
+                    handler_type: *b"tmcd",
+                    sample_descriptions: vec![SampleDescription {
+                        index: 1,
+                        sample_entry: AtomHeader {
+                            offset: 1234892,
+                            size: 57,
+                            kind: *b"tmcd",
+                            size_form: SizeForm::Compact,
+                        },
+                        name_atom: NameAtom {
+                            size: 23,
+                            offset: 1234926,
+                            reel_name: "A_0005_12SO".to_string(),
+                            matched: true,
+                        },
+                        timecode_format: TimecodeFormat {
+                            flags: 0,
+                            timescale: 12288,
+                            frame_duration: 512,
+                            number_of_frames: 24,
+                        },
+                        ..Default::default()
+                    }],
+                    timescale: 12288,
+                    timecode_samples: vec![TimecodeSample {
+                        offset: 36,
+                        sample_description_index: 1,
+                        frame_number: 86400,
+                    }],
+                    ..Default::default()
                 },
-            ]
-            .to_vec(),
-            frame_count: 2,
+            ],
         };
 
         let mut video_121 = Video::default();
@@ -424,47 +3804,745 @@ mod tests {
             .expect("Some issue occur when decoding '1-2-1_2frames_prores422.mov'.");
 
         let expected_result_121 = Video {
-            colr_atom: ColrAtom {
-                size: 18,
-                _color_parameter_type: Nclc,
-                offset: 1234292,
-                primary_index: 1,
-                transfer_function_index: 2,
-                matrix_index: 1,
-                matched: true,
+            major_brand: *b"qt  ",
+            tracks: vec![
+                Track {
+                    track_id: 1,
+                    handler_type: *b"vide",
+                    sample_descriptions: vec![SampleDescription {
+                        index: 1,
+                        sample_entry: AtomHeader {
+                            offset: 1234184,
+                            size: 142,
+                            kind: *b"apcn",
+                            size_form: SizeForm::Compact,
+                        },
+                        width: 1920,
+                        height: 1080,
+                        depth: 24,
+                        colr_atoms: vec![ColrAtom {
+                            size: 18,
+                            color_parameter_type: Nclc,
+                            offset: 1234292,
+                            primary_index: 1,
+                            transfer_function_index: 2,
+                            matrix_index: 1,
+                            full_range_flag: false,
+                        }],
+                        gama_atom: GamaAtom {
+                            size: 12,
+                            offset: 1234280,
+                            gama_value: 157286,
+                            matched: true,
+                        },
+                        mdcv_atom: Default::default(),
+                        clli_atom: Default::default(),
+                        pasp_atom: PaspAtom {
+                            size: 16,
+                            offset: 1234310,
+                            pixel_aspect_ratio: PixelAspectRatio::default(),
+                            matched: true,
+                        },
+                        fiel_atom: FielAtom {
+                            size: 10,
+                            offset: 1234270,
+                            fields: [1, 0],
+                            matched: true,
+                        },
+                        clap_atom: Default::default(),
+                        configuration_atom: Default::default(),
+                        name_atom: Default::default(),
+                        timecode_format: Default::default(),
+                    }],
+                    frames: [
+                        ProResFrame {
+                            offset: 40,
+                            frame_size: 616448,
+                            sample_description_index: 1,
+                            _frame_id: 0.0,
+                            frame_header_size: 148,
+                            color_primaries: 1,
+                            transfer_characteristic: 2,
+                            matrix_coefficients: 1,
+                            width: 1920,
+                            height: 1080,
+                            aspect_ratio_information: 0,
+                            interlace_mode: 0,
+                            picture_count: 1,
+                            bitstream_version: 0,
+                            encoder_id: *b"apl0",
+                            chroma_format: 2,
+                            frame_rate_code: 0,
+                            alpha_channel_type: 0,
+                            load_luma_quantization_matrix: true,
+                            load_chroma_quantization_matrix: true,
+                            duration: 512,
+                        },
+                        ProResFrame {
+                            offset: 616488,
+                            frame_size: 617195,
+                            sample_description_index: 1,
+                            _frame_id: 0.0,
+                            frame_header_size: 148,
+                            color_primaries: 1,
+                            transfer_characteristic: 2,
+                            matrix_coefficients: 1,
+                            width: 1920,
+                            height: 1080,
+                            aspect_ratio_information: 0,
+                            interlace_mode: 0,
+                            picture_count: 1,
+                            bitstream_version: 0,
+                            encoder_id: *b"apl0",
+                            chroma_format: 2,
+                            frame_rate_code: 0,
+                            alpha_channel_type: 0,
+                            load_luma_quantization_matrix: true,
+                            load_chroma_quantization_matrix: true,
+                            duration: 512,
+                        },
+                    ]
+                    .to_vec(),
+                    frame_count: 2,
+                    tapt_atom: Default::default(),
+                    timescale: 12288,
+                    timecode_samples: Vec::new(),
+                    in_band_sps: Vec::new(),
+                },
+                Track {
+                    track_id: 2,
+                    handler_type: *b"tmcd",
+                    sample_descriptions: vec![SampleDescription {
+                        index: 1,
+                        sample_entry: AtomHeader {
+                            offset: 1234904,
+                            size: 57,
+                            kind: *b"tmcd",
+                            size_form: SizeForm::Compact,
+                        },
+                        name_atom: NameAtom {
+                            size: 23,
+                            offset: 1234938,
+                            reel_name: "A_0005_12SO".to_string(),
+                            matched: true,
+                        },
+                        timecode_format: TimecodeFormat {
+                            flags: 0,
+                            timescale: 12288,
+                            frame_duration: 512,
+                            number_of_frames: 24,
+                        },
+                        ..Default::default()
+                    }],
+                    timescale: 12288,
+                    timecode_samples: vec![TimecodeSample {
+                        offset: 36,
+                        sample_description_index: 1,
+                        frame_number: 86400,
+                    }],
+                    ..Default::default()
+                },
+            ],
+        };
+
+        assert_eq!(video_111, expected_result_111);
+        assert_eq!(video_121, expected_result_121);
+    }
+
+    #[test]
+    fn test_decode_ignores_essence() {
+        // The second sample looks like the atoms of a sample entry, and holds an ICC
+        // profile whose description names them too.
+        let essence = [
+            testing::nclc_colr_atom(9, 16, 9),
+            testing::atom(b"gama", &[0, 2, 0x66, 0x66]),
+            testing::icc_profile("colr gama mdcv", 2.4),
+        ]
+        .concat();
+        for layout in [
+            Default::default(),
+            testing::Layout {
+                moov_first: true,
+                mdat_to_end: true,
+                ..Default::default()
             },
-            gama_atom: GamaAtom {
-                size: 12,
-                gama_value: 157286,
-                offsets: [1234280].to_vec(),
-                the_actual_gama_offset: 1234280,
-                matched: true,
+        ] {
+            let track = testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(
+                    b"apcn",
+                    &[testing::nclc_colr_atom(1, 1, 1)],
+                )]
+                .to_vec(),
+                samples: [testing::prores_frame(400, [1, 1, 1]), essence.clone()].to_vec(),
+                ..Default::default()
+            };
+            let file_path = testing::write_mov("essence.mov", &[track], layout);
+            let video = testing::decode(&file_path);
+
+            let description = &video.tracks[0].sample_descriptions[0];
+            assert_eq!(description.colr_atoms.len(), 1);
+            let colr_atom = &description.colr_atoms[0];
+            assert_eq!(
+                (
+                    colr_atom.primary_index,
+                    colr_atom.transfer_function_index,
+                    colr_atom.matrix_index
+                ),
+                (1, 1, 1)
+            );
+            assert!(!description.gama_atom.matched);
+            assert!(!description.mdcv_atom.matched);
+            assert_eq!(video.tracks[0].frames.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_prores_frame_probe() {
+        // Only the samples of ProRes sample descriptions that can hold a frame header
+        // are ProRes frames, and the last one is cut short by the end of the file.
+        let tracks = [
+            testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(b"apcn", &[])].to_vec(),
+                samples: [
+                    testing::prores_frame(400, [1, 1, 1]),
+                    testing::prores_frame(400, [1, 1, 1])[..20].to_vec(),
+                    testing::prores_frame(400, [1, 1, 1]),
+                ]
+                .to_vec(),
+                ..Default::default()
             },
-            frames: [
-                ProResFrame {
-                    offset: 40,
-                    frame_size: 616448,
-                    _frame_id: 0.0,
-                    frame_header_size: 148,
-                    color_primaries: 1,
-                    transfer_characteristic: 2,
-                    matrix_coefficients: 1,
-                },
-                ProResFrame {
-                    offset: 616488,
-                    frame_size: 617195,
-                    _frame_id: 0.0,
-                    frame_header_size: 148,
-                    color_primaries: 1,
-                    transfer_characteristic: 2,
-                    matrix_coefficients: 1,
+            testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(b"avc1", &[])].to_vec(),
+                samples: [testing::prores_frame(400, [1, 1, 1])].to_vec(),
+                ..Default::default()
+            },
+        ];
+        let layout = testing::Layout {
+            moov_first: true,
+            mdat_to_end: true,
+            ..Default::default()
+        };
+        let file_path = testing::write_mov("probe.mov", &tracks, layout);
+        let file = OpenOptions::new().write(true).open(&file_path).unwrap();
+        let file_size = file.metadata().unwrap().len();
+        // The second track comes last in mdat: cut it, then the third ProRes frame
+        // after its tag.
+        file.set_len(file_size - 400 - 390).unwrap();
+
+        let video = testing::decode(&file_path);
+        assert_eq!(video.tracks[0].frames.len(), 1);
+        assert!(video.tracks[1].frames.is_empty());
+    }
+
+    #[test]
+    fn test_encode_without_colr_atom() {
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(b"apcn", &[])].to_vec(),
+            samples: [testing::prores_frame(400, [1, 1, 1])].to_vec(),
+            ..Default::default()
+        };
+        // A video that has not found any colr atom must not touch the file.
+        let (file_path, mut file, mut video) =
+            testing::open_mov("encode_without_colr.mov", &[track], Default::default());
+        let original = std::fs::read(&file_path).unwrap();
+        let result = video.encode(&mut file, Selection::default(), 1, 2, 1, -1.0);
+
+        assert!(matches!(
+            result,
+            Err(Error::ColrAtomNotFound { track_id: 1 })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), original);
+    }
+
+    #[test]
+    fn test_insert_colr_atom() {
+        for (moov_first, co64) in [(false, false), (true, false), (true, true)] {
+            let track = testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(
+                    b"apcn",
+                    &[testing::atom(b"fiel", &[1, 0])],
+                )]
+                .to_vec(),
+                samples: [
+                    testing::prores_frame(400, [1, 1, 1]),
+                    testing::prores_frame(500, [1, 1, 1]),
+                ]
+                .to_vec(),
+                ..Default::default()
+            };
+            let layout = testing::Layout {
+                moov_first,
+                co64,
+                ..Default::default()
+            };
+            let (file_path, mut file, mut video) =
+                testing::open_mov("insert_colr.mov", &[track], layout);
+            assert!(!video.tracks[0].has_colr_atom());
+            assert_eq!(video.tracks[0].frames.len(), 2);
+
+            video
+                .insert_colr_atom(&mut file, Selection::default(), 1, 2, 1, None)
+                .unwrap();
+
+            // The frames must still be found, at their new offsets if the essence
+            // moved.
+            let decoded = testing::redecode(&file_path, &video);
+            assert_eq!(
+                decoded.tracks[0].sample_descriptions[0].colr_atoms[0].size,
+                18
+            );
+            assert_eq!(
+                decoded.tracks[0].sample_descriptions[0].colr_atoms[0].primary_index,
+                1
+            );
+            assert_eq!(
+                decoded.tracks[0].sample_descriptions[0].colr_atoms[0].transfer_function_index,
+                2
+            );
+            assert_eq!(
+                decoded.tracks[0].sample_descriptions[0].colr_atoms[0].matrix_index,
+                1
+            );
+            assert_eq!(
+                decoded.tracks[0].sample_descriptions[0].sample_entry.size,
+                86 + 10 + 18
+            );
+            assert_eq!(decoded.tracks[0].frames.len(), 2);
+            assert_eq!(decoded.tracks[0].frames[1].frame_size, 500);
+            assert_eq!(
+                decoded.tracks[0].frames[1].offset,
+                decoded.tracks[0].frames[0].offset + 400
+            );
+        }
+    }
+
+    #[test]
+    fn test_insert_and_remove_gama_atom() {
+        let (file_path, mut file, mut video) =
+            testing::open_footage("1-1-1_2frames_prores422.mov", "gama.mov");
+        assert!(!video.tracks[0].has_gama_atom());
+
+        video
+            .insert_gama_atom(&mut file, Selection::default(), 2.4)
+            .unwrap();
+        assert!(video.tracks[0].has_gama_atom());
+        assert_eq!(video.tracks[0].sample_descriptions[0].gama_atom.size, 12);
+        assert_eq!(
+            Video::bytes_to_float(
+                video.tracks[0].sample_descriptions[0]
+                    .gama_atom
+                    .gama_value
+                    .to_be_bytes()
+            ),
+            2.4
+        );
+        assert_eq!(
+            video.tracks[0].sample_descriptions[0].sample_entry.size,
+            130 + 12
+        );
+        assert_eq!(video.tracks[0].frames.len(), 2);
+
+        // Removing it again must give back the original file, byte for byte.
+        video
+            .remove_gama_atom(&mut file, Selection::default())
+            .unwrap();
+        assert!(!video.tracks[0].has_gama_atom());
+        assert_eq!(
+            std::fs::read(&file_path).unwrap(),
+            std::fs::read("tests/footages/1-1-1_2frames_prores422.mov").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_multiple_video_tracks() {
+        let video_track = |transfer| testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(
+                b"apcn",
+                &[testing::nclc_colr_atom(1, transfer, 1)],
+            )]
+            .to_vec(),
+            samples: [testing::prores_frame(400, [1, transfer as u8, 1])].to_vec(),
+            ..Default::default()
+        };
+        let timecode_track = testing::Track {
+            handler_type: *b"tmcd",
+            sample_entries: [testing::atom(b"tmcd", &[0; 26])].to_vec(),
+            samples: [vec![0; 4]].to_vec(),
+            ..Default::default()
+        };
+        let tracks = [video_track(1), timecode_track, video_track(2)];
+        let (file_path, mut file, mut video) =
+            testing::open_mov("multiple_tracks.mov", &tracks, Default::default());
+        let track_ids: Vec<u32> = video.tracks().iter().map(|t| t.track_id()).collect();
+        assert_eq!(track_ids, [1, 2, 3]);
+        assert_eq!(
+            video.tracks[2].sample_descriptions[0].colr_atoms[0].transfer_function_index,
+            2
+        );
+        assert!(!video.tracks[1].is_video());
+
+        // Only the selected track is modified.
+        video
+            .encode(
+                &mut file,
+                Selection {
+                    tracks: TrackSelector::Id(3),
+                    ..Default::default()
                 },
+                9,
+                16,
+                9,
+                -1.0,
+            )
+            .unwrap();
+        let decoded = testing::redecode(&file_path, &video);
+        assert_eq!(
+            decoded.tracks[0].sample_descriptions[0].colr_atoms[0].transfer_function_index,
+            1
+        );
+        assert_eq!(decoded.tracks[0].frames[0].transfer_characteristic, 1);
+        assert_eq!(
+            decoded.tracks[2].sample_descriptions[0].colr_atoms[0].transfer_function_index,
+            16
+        );
+        assert_eq!(decoded.tracks[2].frames[0].transfer_characteristic, 16);
+
+        // By default, all the video tracks are.
+        video
+            .encode(&mut file, Selection::default(), 1, 2, 1, -1.0)
+            .unwrap();
+        let decoded = testing::redecode(&file_path, &video);
+        assert_eq!(
+            decoded.tracks[0].sample_descriptions[0].colr_atoms[0].transfer_function_index,
+            2
+        );
+        assert_eq!(
+            decoded.tracks[2].sample_descriptions[0].colr_atoms[0].transfer_function_index,
+            2
+        );
+
+        let result = video.encode(
+            &mut file,
+            Selection {
+                tracks: TrackSelector::Index(1),
+                ..Default::default()
+            },
+            1,
+            1,
+            1,
+            -1.0,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::NotAVideoTrack { track_id: 2, .. })
+        ));
+        let result = video.encode(
+            &mut file,
+            Selection {
+                tracks: TrackSelector::Id(4),
+                ..Default::default()
+            },
+            1,
+            1,
+            1,
+            -1.0,
+        );
+        assert!(matches!(result, Err(Error::TrackIdNotFound(4))));
+    }
+
+    #[test]
+    fn test_multiple_sample_descriptions() {
+        // The second and third frames use the second sample description, which has no
+        // gama atom yet.
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [
+                testing::visual_sample_entry(b"apcn", &[testing::nclc_colr_atom(1, 1, 1)]),
+                testing::visual_sample_entry(b"apch", &[testing::nclc_colr_atom(1, 2, 1)]),
             ]
             .to_vec(),
-            frame_count: 2,
+            samples: [
+                testing::prores_frame(400, [1, 1, 1]),
+                testing::prores_frame(500, [1, 2, 1]),
+                testing::prores_frame(600, [1, 2, 1]),
+            ]
+            .to_vec(),
+            sample_description_indexes: [1, 2, 2].to_vec(),
+            ..Default::default()
         };
+        let (file_path, mut file, mut video) = testing::open_mov(
+            "multiple_sample_descriptions.mov",
+            &[track],
+            Default::default(),
+        );
+        assert_eq!(video.tracks[0].formats(), [*b"apcn", *b"apch"]);
+        let indexes: Vec<u32> = video.tracks[0]
+            .frames
+            .iter()
+            .map(|frame| frame.sample_description_index)
+            .collect();
+        assert_eq!(indexes, [1, 2, 2]);
 
-        assert_eq!(video_111, expected_result_111);
-        assert_eq!(video_121, expected_result_121);
+        let second = Selection {
+            sample_description_index: Some(2),
+            ..Default::default()
+        };
+        video.insert_gama_atom(&mut file, second, 2.4).unwrap();
+        assert!(!video.tracks[0].sample_descriptions[0].gama_atom.matched);
+        assert!(video.tracks[0].sample_descriptions[1].gama_atom.matched);
+
+        // Only the second sample description and its frames are modified.
+        video.encode(&mut file, second, 9, 16, 9, 2.2).unwrap();
+        let decoded = testing::redecode(&file_path, &video);
+        let descriptions = &decoded.tracks[0].sample_descriptions;
+        assert_eq!(descriptions[0].colr_atoms[0].transfer_function_index, 1);
+        assert_eq!(descriptions[1].colr_atoms[0].transfer_function_index, 16);
+        assert_eq!(descriptions[1].gama_atom.gama_value, 144179);
+        let transfers: Vec<u8> = decoded.tracks[0]
+            .frames
+            .iter()
+            .map(|frame| frame.transfer_characteristic)
+            .collect();
+        assert_eq!(transfers, [1, 16, 16]);
+
+        // By default, every sample description is.
+        video
+            .encode(&mut file, Selection::default(), 1, 2, 1, -1.0)
+            .unwrap();
+        let decoded = testing::redecode(&file_path, &video);
+        assert!(decoded.tracks[0]
+            .frames
+            .iter()
+            .all(|frame| frame.transfer_characteristic == 2));
+
+        let result = video.encode(
+            &mut file,
+            Selection {
+                sample_description_index: Some(3),
+                ..Default::default()
+            },
+            1,
+            1,
+            1,
+            -1.0,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::SampleDescriptionNotFound {
+                track_id: 1,
+                index: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_colr_and_gama_atoms() {
+        // A 12-byte 'nclx' colr atom and a gama atom without a value, followed by a
+        // complete colr atom: the short atoms are ignored, and left untouched.
+        let short_atoms = [
+            testing::atom(b"colr", b"nclx"),
+            testing::atom(b"gama", &[0, 2]),
+        ]
+        .concat();
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(
+                b"apcn",
+                &[short_atoms.clone(), testing::nclc_colr_atom(9, 16, 9)],
+            )]
+            .to_vec(),
+            samples: [testing::prores_frame(400, [1, 1, 1])].to_vec(),
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) =
+            testing::open_mov("truncated_colr.mov", &[track], Default::default());
+
+        let description = &video.tracks[0].sample_descriptions[0];
+        assert_eq!(description.colr_atoms.len(), 1);
+        assert_eq!(description.colr_atoms[0].primary_index, 9);
+        assert!(!description.gama_atom.matched);
+
+        video
+            .encode(&mut file, Selection::default(), 1, 1, 1, -1.0)
+            .unwrap();
+        let video = testing::redecode(&file_path, &video);
+        let colr_atom = &video.tracks[0].sample_descriptions[0].colr_atoms[0];
+        assert_eq!(
+            (
+                colr_atom.primary_index,
+                colr_atom.transfer_function_index,
+                colr_atom.matrix_index
+            ),
+            (1, 1, 1)
+        );
+        let bytes = std::fs::read(&file_path).unwrap();
+        assert!(bytes
+            .windows(short_atoms.len())
+            .any(|window| window == short_atoms));
+    }
+
+    #[test]
+    fn test_nclx_colr_atom() {
+        // An MP4 file whose first track has an 'nclx' colr atom, and whose second
+        // track has no colr atom at all.
+        let tracks = [
+            testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(
+                    b"avc1",
+                    &[testing::nclx_colr_atom(1, 1, 1, false)],
+                )]
+                .to_vec(),
+                samples: [vec![0; 400]].to_vec(),
+                ..Default::default()
+            },
+            testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(b"avc1", &[])].to_vec(),
+                samples: [vec![0; 400]].to_vec(),
+                ..Default::default()
+            },
+        ];
+        let layout = testing::Layout {
+            mp4: true,
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) = testing::open_mov("nclx.mp4", &tracks, layout);
+        let colr_atom = &video.tracks[0].sample_descriptions[0].colr_atoms[0];
+        assert_eq!(colr_atom.color_parameter_type, ColorParameterType::Nclx);
+        assert_eq!(colr_atom.size, 19);
+        assert!(!colr_atom.full_range_flag);
+
+        // An MP4 file gets an 'nclx' colr atom, with the requested range.
+        video
+            .insert_colr_atom(&mut file, Selection::default(), 1, 1, 1, Some(true))
+            .unwrap();
+        let colr_atom = &video.tracks[1].sample_descriptions[0].colr_atoms[0];
+        assert_eq!(colr_atom.color_parameter_type, ColorParameterType::Nclx);
+        assert_eq!(colr_atom.size, 19);
+        assert!(colr_atom.full_range_flag);
+
+        video
+            .set_full_range_flag(&mut file, Selection::default(), true)
+            .unwrap();
+        video
+            .encode(&mut file, Selection::default(), 9, 16, 9, -1.0)
+            .unwrap();
+
+        let decoded = testing::redecode(&file_path, &video);
+        for track in decoded.tracks.iter() {
+            let colr_atom = &track.sample_descriptions[0].colr_atoms[0];
+            assert_eq!(
+                (
+                    colr_atom.primary_index,
+                    colr_atom.transfer_function_index,
+                    colr_atom.matrix_index
+                ),
+                (9, 16, 9)
+            );
+            assert!(colr_atom.full_range_flag);
+        }
+
+        // 'nclc' has no full range flag.
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(
+                b"avc1",
+                &[testing::nclc_colr_atom(1, 1, 1)],
+            )]
+            .to_vec(),
+            samples: [vec![0; 400]].to_vec(),
+            ..Default::default()
+        };
+        let (_file_path, mut file, mut video) =
+            testing::open_mov("nclc.mov", &[track], Default::default());
+        let result = video.set_full_range_flag(&mut file, Selection::default(), false);
+        assert!(matches!(
+            result,
+            Err(Error::NclxColrAtomNotFound { track_id: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_multiple_colr_atoms() {
+        // An 'nclx' colr atom followed by an ICC profile, as written by some HEIF
+        // derived writers.
+        let profile = testing::icc_profile("Display P3", 2.4);
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(
+                b"hvc1",
+                &[
+                    testing::nclx_colr_atom(12, 13, 6, true),
+                    testing::prof_colr_atom(&profile),
+                ],
+            )]
+            .to_vec(),
+            samples: [vec![0; 400]].to_vec(),
+            ..Default::default()
+        };
+        let layout = testing::Layout {
+            mp4: true,
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) =
+            testing::open_mov("multiple_colr_atoms.mp4", &[track], layout);
+        let description = &video.tracks[0].sample_descriptions[0];
+        let types: Vec<ColorParameterType> = description
+            .colr_atoms
+            .iter()
+            .map(|colr_atom| colr_atom.color_parameter_type)
+            .collect();
+        assert_eq!(types, [ColorParameterType::Nclx, ColorParameterType::Prof]);
+        let honoured = |policy| {
+            description
+                .honoured_colr_atom(policy)
+                .unwrap()
+                .color_parameter_type
+        };
+        assert_eq!(honoured(ColrPolicy::First), ColorParameterType::Nclx);
+        assert_eq!(honoured(ColrPolicy::Last), ColorParameterType::Prof);
+        assert_eq!(honoured(ColrPolicy::PreferIcc), ColorParameterType::Prof);
+        assert_eq!(honoured(ColrPolicy::PreferNclx), ColorParameterType::Nclx);
+        let expected = format!(
+            "colr 'prof': ICC profile of {} bytes (honoured)",
+            profile.len()
+        );
+        assert!(video.info(ColrPolicy::PreferIcc).contains(&expected));
+
+        // The ICC profile has no indexes to write.
+        let prof_only = Selection {
+            colr: ColrSelector::Type(ColorParameterType::Prof),
+            ..Default::default()
+        };
+        let result = video.encode(&mut file, prof_only, 1, 1, 1, -1.0);
+        assert!(matches!(
+            result,
+            Err(Error::ColrAtomWithoutIndexes { track_id: 1 })
+        ));
+        assert_eq!(video.icc_profiles(&mut file, prof_only).unwrap().len(), 1);
+
+        // All the colr atoms are selected by default, but only 'nclx' can be written
+        // and the ICC profile is reported as left untouched.
+        video
+            .encode(&mut file, Selection::default(), 1, 1, 1, -1.0)
+            .unwrap();
+        assert_eq!(
+            video
+                .colr_atoms_left_untouched(Selection::default())
+                .unwrap(),
+            [(1, 1, ColorParameterType::Prof)]
+        );
+        let decoded = testing::redecode(&file_path, &video);
+        let colr_atoms = &decoded.tracks[0].sample_descriptions[0].colr_atoms;
+        assert_eq!(colr_atoms[0].primary_index, 1);
+        assert!(colr_atoms[0].full_range_flag);
+        assert_eq!(colr_atoms[1].size as usize, 12 + profile.len());
     }
 }
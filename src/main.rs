@@ -7,7 +7,7 @@ use std::{path::Path, time::Instant};
 use clap::Parser;
 
 use atom_modifier::args::Args;
-use atom_modifier::Video;
+use atom_modifier::{AudioConform, IccProfile, Video};
 
 /// Creates a backup file for the given input file path. The backup file name will be in
 /// the format "{filename}_Original.{ext}".
@@ -59,6 +59,31 @@ fn backup_input_file(input_file_path: &Path) -> io::Result<()> {
     Ok(())
 }
 
+/// Warns about the selected sample descriptions whose transfer function is PQ but
+/// which have no content light levels, as HDR10 deliverables need both.
+fn warn_about_missing_content_light_levels(video: &Video, args: &Args) {
+    for (track_id, sample_description_index) in video
+        .pq_without_content_light_level(args.selection(), args.colr_policy)
+        .unwrap_or_default()
+    {
+        eprintln!(
+            "Warning: track {}, sample description {} has the PQ transfer function but no clli atom, use --content-light-level to add one",
+            track_id, sample_description_index
+        );
+    }
+}
+
+/// Warns about the ProRes frames whose header disagrees with their sample description
+/// or with the timing of their track.
+fn warn_about_prores_frame_mismatches(video: &Video) {
+    for (track_id, sample_description_index, mismatch) in video.prores_frame_mismatches() {
+        eprintln!(
+            "Warning: track {}, sample description {}: {}",
+            track_id, sample_description_index, mismatch
+        );
+    }
+}
+
 fn main() {
     let args = Args::parse();
 
@@ -79,6 +104,70 @@ fn main() {
         now.elapsed()
     );
 
+    let selection = args.selection();
+
+    if args.info {
+        print!("{}", video.info(args.colr_policy));
+        warn_about_missing_content_light_levels(&video, &args);
+        warn_about_prores_frame_mismatches(&video);
+    }
+
+    // Reading the embedded ICC profiles
+    if args.icc_info || args.extract_icc.is_some() {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .open(&args.input_file_path)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error trying to open file '{}' in reading mode: {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+        let profiles = video
+            .icc_profiles(&mut file, selection)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error reading the ICC profiles of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+        if args.icc_info {
+            for (track_id, sample_description_index, profile) in profiles.iter() {
+                println!(
+                    "Track {}, sample description {}: {}",
+                    track_id, sample_description_index, profile
+                );
+            }
+        }
+        if let Some(icc_file_path) = &args.extract_icc {
+            // The first profile is written, use --track-id and --sample-description
+            // to choose another one.
+            std::fs::write(icc_file_path, profiles[0].2.bytes()).unwrap_or_else(|e| {
+                eprintln!(
+                    "Error writing the ICC profile to '{}': {}",
+                    icc_file_path, e
+                );
+                std::process::exit(1);
+            });
+        }
+    }
+
+    if !args.modifies_file() {
+        return;
+    }
+
+    let icc_profile = args.icc_profile.as_ref().map(|icc_file_path| {
+        std::fs::read(icc_file_path)
+            .map_err(atom_modifier::Error::from)
+            .and_then(IccProfile::from_bytes)
+            .unwrap_or_else(|e| {
+                eprintln!("Error reading the ICC profile '{}': {}", icc_file_path, e);
+                std::process::exit(1);
+            })
+    });
+
     if !args.modify_in_place {
         // Make a backup of the original file name as "<filename>_Original.<ext>".
         backup_input_file(Path::new(&args.input_file_path))
@@ -99,24 +188,282 @@ fn main() {
 
     // Encoding
     let now = Instant::now();
-    video
-        .encode(
-            &mut file,
-            &video,
-            args.primary_index,
-            args.transfer_function_index,
-            args.matrix_index,
-            args.gama_value,
-        )
-        .unwrap_or_else(|e| {
-            eprintln!("Error encoding the file '{}': {}", args.input_file_path, e);
-            std::process::exit(1);
-        });
+    if let (true, Some((p, t, m))) = (args.insert_colr, args.color_indexes()) {
+        video
+            .insert_colr_atom(&mut file, selection, p, t, m, args.full_range())
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error inserting a colr atom into '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(full_range) = args.full_range() {
+        video
+            .set_full_range_flag(&mut file, selection, full_range)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the full range flag of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if args.gama_value != -1.0 {
+        video
+            .insert_gama_atom(&mut file, selection, args.gama_value)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error inserting a gama atom into '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if args.remove_gama {
+        video
+            .remove_gama_atom(&mut file, selection)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error removing the gama atom from '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some((p, t, m)) = args.color_indexes() {
+        video
+            .encode(&mut file, selection, p, t, m, args.gama_value)
+            .unwrap_or_else(|e| {
+                eprintln!("Error encoding the file '{}': {}", args.input_file_path, e);
+                std::process::exit(1);
+            });
+        // Another colr atom may still say something else, e.g. an ICC profile next to
+        // the 'nclx' one.
+        for (track_id, sample_description_index, color_parameter_type) in video
+            .colr_atoms_left_untouched(selection)
+            .unwrap_or_default()
+        {
+            eprintln!(
+                "Warning: the '{}' colr atom of track {}, sample description {} was left untouched and may conflict with the new values",
+                color_parameter_type, track_id, sample_description_index
+            );
+        }
+    }
+    if let Some(mastering_display) = &args.mastering_display {
+        video
+            .set_mastering_display(&mut file, selection, mastering_display)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the mastering display metadata of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if args.remove_mastering_display {
+        video
+            .remove_mastering_display(&mut file, selection)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error removing the mastering display metadata from '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(content_light_level) = &args.content_light_level {
+        video
+            .set_content_light_level(&mut file, selection, content_light_level)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the content light levels of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if args.remove_content_light_level {
+        video
+            .remove_content_light_level(&mut file, selection)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error removing the content light levels from '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(interlace_mode) = args.interlace_mode {
+        video
+            .set_interlace_mode(&mut file, selection, interlace_mode)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the interlace mode of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(alpha_channel_type) = args.alpha_channel_type {
+        video
+            .set_alpha_channel_type(&mut file, selection, alpha_channel_type)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the alpha channel type of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(frame_rate) = args.frame_rate {
+        video
+            .set_frame_rate(&mut file, selection, frame_rate, args.container_timing)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the frame rate of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(frame_rate) = args.conform {
+        let audio = if args.conform_audio {
+            AudioConform::Rescale
+        } else {
+            AudioConform::Leave
+        };
+        let left_alone = video
+            .conform_frame_rate(&mut file, selection, frame_rate, audio)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error conforming the frame rate of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+        for track_id in left_alone {
+            eprintln!(
+                "Warning: audio track {} was left at its original speed and now drifts from the video, use --conform-audio to rescale it",
+                track_id
+            );
+        }
+    }
+    if let Some(pixel_aspect_ratio) = &args.pixel_aspect_ratio {
+        video
+            .set_pixel_aspect_ratio(&mut file, selection, pixel_aspect_ratio)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the pixel aspect ratio of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(clean_aperture) = &args.clean_aperture {
+        video
+            .set_clean_aperture(&mut file, selection, clean_aperture)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the clean aperture of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if args.remove_clean_aperture {
+        video
+            .remove_clean_aperture(&mut file, selection)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error removing the clean aperture from '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(track_aperture) = &args.track_aperture {
+        video
+            .set_track_aperture(&mut file, selection, track_aperture)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the track aperture of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if args.remove_track_aperture {
+        video
+            .remove_track_aperture(&mut file, selection)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error removing the track aperture from '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(reel_name) = &args.reel_name {
+        video
+            .set_reel_name(&mut file, selection, reel_name)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the reel name of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if args.remove_reel_name {
+        video
+            .remove_reel_name(&mut file, selection)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error removing the reel name from '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(start_timecode) = &args.start_timecode {
+        video
+            .set_start_timecode(&mut file, selection, start_timecode)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error setting the start timecode of '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
+    if let Some(icc_profile) = &icc_profile {
+        video
+            .set_icc_profile(&mut file, selection, icc_profile)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Error embedding the ICC profile into '{}': {}",
+                    args.input_file_path, e
+                );
+                std::process::exit(1);
+            });
+    }
     println!(
         "- Time elapsed after encoding the file: {:?}",
         now.elapsed()
     );
 
+    // The new transfer function only shows up once the file is decoded again.
+    let mut encoded_video = Video::default();
+    if encoded_video.decode(args.input_file_path.as_str()).is_ok() {
+        warn_about_missing_content_light_levels(&encoded_video, &args);
+        // A frame rate code set without the container timing may contradict it.
+        if args.frame_rate.is_some() {
+            warn_about_prores_frame_mismatches(&encoded_video);
+        }
+    }
+
     // Logging
     let mut file = OpenOptions::new()
         .read(true)
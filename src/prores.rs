@@ -0,0 +1,252 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The frame rates of the frame_rate_code of a ProRes frame header, from code 1, as
+/// fractions (numerator, denominator). Code 0 means unknown, codes 12 to 15 are
+/// reserved.
+pub(crate) const FRAME_RATES: [(u32, u32); 11] = [
+    (24000, 1001),
+    (24, 1),
+    (25, 1),
+    (30000, 1001),
+    (30, 1),
+    (50, 1),
+    (60000, 1001),
+    (60, 1),
+    (100, 1),
+    (120000, 1001),
+    (120, 1),
+];
+
+/// Returns the frame rate of a frame_rate_code, if it is a known one.
+pub(crate) fn frame_rate(code: u8) -> Option<(u32, u32)> {
+    FRAME_RATES.get((code as usize).checked_sub(1)?).copied()
+}
+
+/// Describes the frame_rate_code of a ProRes frame header, e.g. "29.97 fps".
+pub(crate) fn describe_frame_rate_code(code: u8) -> String {
+    match (code, frame_rate(code)) {
+        (_, Some((numerator, denominator))) => {
+            let fps = numerator as f64 / denominator as f64;
+            format!("{} fps", (fps * 1000.0).round() / 1000.0)
+        }
+        (0, None) => "unknown".to_string(),
+        (_, None) => "reserved".to_string(),
+    }
+}
+
+/// Describes the chroma_format of a ProRes frame header.
+pub(crate) fn describe_chroma_format(code: u8) -> &'static str {
+    match code {
+        2 => "4:2:2",
+        3 => "4:4:4",
+        _ => "reserved",
+    }
+}
+
+/// Describes the alpha_channel_type of a ProRes frame header.
+pub(crate) fn describe_alpha_channel_type(code: u8) -> &'static str {
+    match code {
+        0 => "none",
+        1 => "8-bit",
+        2 => "16-bit",
+        _ => "reserved",
+    }
+}
+
+/// The alpha_channel_type of a ProRes frame header: whether the frames carry an
+/// alpha channel, and how many bits its samples have.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum AlphaChannelType {
+    #[default]
+    None,
+    Bits8,
+    Bits16,
+}
+
+impl AlphaChannelType {
+    /// The 4-bit alpha_channel_type of a ProRes frame header.
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            AlphaChannelType::None => 0,
+            AlphaChannelType::Bits8 => 1,
+            AlphaChannelType::Bits16 => 2,
+        }
+    }
+}
+
+impl FromStr for AlphaChannelType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(AlphaChannelType::None),
+            "8" => Ok(AlphaChannelType::Bits8),
+            "16" => Ok(AlphaChannelType::Bits16),
+            _ => Err("valid values are none, 8 and 16".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for AlphaChannelType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", describe_alpha_channel_type(self.code()))
+    }
+}
+
+/// Returns the chroma_format the frames of a ProRes sample description format must
+/// have: 4:4:4 for the ProRes 4444 family, 4:2:2 for the ProRes 422 one.
+pub(crate) fn expected_chroma_format(format: [u8; 4]) -> Option<u8> {
+    match &format {
+        b"apco" | b"apcs" | b"apcn" | b"apch" => Some(2),
+        b"ap4h" | b"ap4x" => Some(3),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{ColrPolicy, Error, Selection, Video};
+
+    #[test]
+    fn test_frame_rate_code() {
+        assert_eq!(frame_rate(4), Some((30000, 1001)));
+        assert_eq!(frame_rate(0), None);
+        assert_eq!(frame_rate(12), None);
+        assert_eq!(describe_frame_rate_code(1), "23.976 fps");
+        assert_eq!(describe_frame_rate_code(3), "25 fps");
+        assert_eq!(describe_frame_rate_code(0), "unknown");
+        assert_eq!(describe_frame_rate_code(15), "reserved");
+    }
+
+    #[test]
+    fn test_alpha_channel_type() {
+        assert_eq!("16".parse(), Ok(AlphaChannelType::Bits16));
+        assert_eq!(AlphaChannelType::Bits16.code(), 2);
+        assert_eq!(AlphaChannelType::Bits8.to_string(), "8-bit");
+        assert!("32".parse::<AlphaChannelType>().is_err());
+    }
+
+    #[test]
+    fn test_prores_frame_header() {
+        let mut video = Video::default();
+        video
+            .decode("tests/footages/1-1-1_2frames_prores422.mov")
+            .unwrap();
+        let info = video.info(ColrPolicy::default());
+        for line in [
+            "    ProRes bitstream version: 0\n",
+            "    ProRes encoder: 'apl0'\n",
+            "    ProRes dimensions: 1920x1080\n",
+            "    ProRes chroma format: 4:2:2\n",
+            "    ProRes interlace mode: progressive\n",
+            "    ProRes frame rate: unknown\n",
+            "    ProRes alpha channel: none\n",
+            "    ProRes quantization matrices: luma custom, chroma custom\n",
+        ] {
+            assert!(info.contains(line), "{} not in {}", line, info);
+        }
+        assert!(video.prores_frame_mismatches().is_empty());
+
+        // 4:2:2 frames wrapped as ProRes 4444, the second one with other dimensions
+        // and interlaced although the fiel atom says progressive.
+        let mut other_frame = testing::interlaced_prores_frame(400, [1, 1, 1]);
+        other_frame[16..20].copy_from_slice(&[0x05, 0x00, 0x02, 0xD0]);
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(
+                b"ap4h",
+                &[testing::atom(b"fiel", &[1, 0])],
+            )]
+            .to_vec(),
+            samples: [testing::prores_frame(400, [1, 1, 1]), other_frame].to_vec(),
+            ..Default::default()
+        };
+        let file_path = testing::write_mov("mismatches.mov", &[track], Default::default());
+        let mut video = Video::default();
+        video.decode(file_path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            video.prores_frame_mismatches(),
+            [
+                (
+                    1,
+                    1,
+                    "2 of 2 ProRes frames aren't 4:4:4 as 'ap4h' is".to_string()
+                ),
+                (
+                    1,
+                    1,
+                    "1 of 2 ProRes frames aren't 1920x1080 as the sample description is"
+                        .to_string()
+                ),
+                (
+                    1,
+                    1,
+                    "1 of 2 ProRes frames aren't progressive as the fiel atom says".to_string()
+                ),
+            ]
+        );
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("    ProRes dimensions: 1920x1080 (1 frames disagree)\n"));
+    }
+
+    #[test]
+    fn test_set_alpha_channel_type() {
+        // ProRes 4444 frames, in a sample description without alpha (depth 24) and
+        // in one with alpha (depth 32).
+        let mut frame = testing::prores_frame(400, [1, 1, 1]);
+        frame[20] = 0xC0; // 4:4:4, progressive
+        frame[25] = 0x02; // 16-bit alpha
+        let mut entry_with_alpha = testing::visual_sample_entry(b"ap4h", &[]);
+        entry_with_alpha[8 + 74..8 + 76].copy_from_slice(&32u16.to_be_bytes());
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(b"ap4h", &[]), entry_with_alpha].to_vec(),
+            samples: [frame.clone(), frame.clone(), frame].to_vec(),
+            sample_description_indexes: [1, 2, 2].to_vec(),
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) =
+            testing::open_mov("alpha.mov", &[track], Default::default());
+        let original = std::fs::read(&file_path).unwrap();
+        assert_eq!(video.tracks[0].sample_descriptions[1].depth, 32);
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("    ProRes alpha channel: 16-bit\n"));
+
+        // The first sample description has a depth of 24, nothing is written.
+        assert!(matches!(
+            video.set_alpha_channel_type(&mut file, Selection::default(), AlphaChannelType::Bits8),
+            Err(Error::AlphaChannelNotSupported { depth: 24, .. })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), original);
+
+        let selection = Selection {
+            sample_description_index: Some(2),
+            ..Default::default()
+        };
+        video
+            .set_alpha_channel_type(&mut file, selection, AlphaChannelType::Bits8)
+            .unwrap();
+        video
+            .set_alpha_channel_type(
+                &mut file,
+                Selection {
+                    sample_description_index: Some(1),
+                    ..Default::default()
+                },
+                AlphaChannelType::None,
+            )
+            .unwrap();
+        testing::redecode(&file_path, &video);
+        let alpha_channel_types: Vec<u8> = video.tracks[0]
+            .frames
+            .iter()
+            .map(|frame| frame.alpha_channel_type)
+            .collect();
+        assert_eq!(alpha_channel_types, [0, 1, 1]);
+    }
+}
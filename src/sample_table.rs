@@ -0,0 +1,285 @@
+use std::fs::File;
+use std::io;
+
+use crate::atom::{self, AtomHeader};
+
+/// One entry of the sample-to-chunk (`stsc`) table.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SampleToChunk {
+    pub(crate) first_chunk: u32,
+    pub(crate) samples_per_chunk: u32,
+    pub(crate) sample_description_index: u32,
+}
+
+/// One entry of the time-to-sample (`stts`) table: a run of samples of the same
+/// duration, in the time scale of the media.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TimeToSample {
+    pub(crate) sample_count: u32,
+    pub(crate) sample_delta: u32,
+}
+
+/// A sample of a track, located through its sample table.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Sample {
+    pub(crate) offset: u64,
+    pub(crate) size: u32,
+    /// The 1-based index of the sample description entry (in `stsd`) of the sample.
+    pub(crate) sample_description_index: u32,
+    /// The duration of the sample in the time scale of the media, 0 if the
+    /// time-to-sample table doesn't cover it.
+    pub(crate) duration: u32,
+}
+
+/// The parts of a sample table (`stbl`) needed to locate every sample of a track in
+/// the file: sample sizes (`stsz`), sample-to-chunk (`stsc`) and chunk offsets
+/// (`stco` or `co64`), along with their durations (`stts`).
+#[derive(Default, Debug, Clone, PartialEq)]
+pub(crate) struct SampleTable {
+    /// The size shared by every sample, or 0 if each sample has its own in
+    /// `sample_sizes`.
+    pub(crate) sample_size: u32,
+    /// The number of samples of the track, as given by `stsz`.
+    pub(crate) sample_count: u32,
+    pub(crate) sample_sizes: Vec<u32>,
+    pub(crate) sample_to_chunk: Vec<SampleToChunk>,
+    pub(crate) chunk_offsets: Vec<u64>,
+    pub(crate) time_to_sample: Vec<TimeToSample>,
+}
+
+impl SampleTable {
+    /// Reads the sample table from the children of a `stbl` atom.
+    ///
+    /// Missing tables are treated as empty, which yields no samples.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file can't be read or if a table claims more
+    /// entries than its atom can hold. A constant sample size isn't repeated for each
+    /// sample, so its count is not bounded by the size of `stsz`.
+    pub(crate) fn read(file: &mut File, stbl_children: &[AtomHeader]) -> io::Result<Self> {
+        let mut table = SampleTable::default();
+
+        for child in stbl_children.iter() {
+            match child.kind {
+                atom::STSZ => {
+                    let body = atom::read_body(file, child)?;
+                    table.sample_size = be_u32(&body, 4)?;
+                    table.sample_count = be_u32(&body, 8)?;
+                    if table.sample_size == 0 {
+                        let count = table.sample_count as usize;
+                        if count > body.len().saturating_sub(12) / 4 {
+                            return Err(truncated_table());
+                        }
+                        table.sample_sizes = (0..count)
+                            .map(|i| be_u32(&body, 12 + i * 4))
+                            .collect::<io::Result<_>>()?;
+                    }
+                }
+                atom::STSC => {
+                    let body = atom::read_body(file, child)?;
+                    let count = be_u32(&body, 4)? as usize;
+                    table.sample_to_chunk = (0..count)
+                        .map(|i| {
+                            Ok(SampleToChunk {
+                                first_chunk: be_u32(&body, 8 + i * 12)?,
+                                samples_per_chunk: be_u32(&body, 12 + i * 12)?,
+                                sample_description_index: be_u32(&body, 16 + i * 12)?,
+                            })
+                        })
+                        .collect::<io::Result<_>>()?;
+                }
+                atom::STTS => {
+                    let body = atom::read_body(file, child)?;
+                    let count = be_u32(&body, 4)? as usize;
+                    table.time_to_sample = (0..count)
+                        .map(|i| {
+                            Ok(TimeToSample {
+                                sample_count: be_u32(&body, 8 + i * 8)?,
+                                sample_delta: be_u32(&body, 12 + i * 8)?,
+                            })
+                        })
+                        .collect::<io::Result<_>>()?;
+                }
+                atom::STCO => {
+                    let body = atom::read_body(file, child)?;
+                    let count = be_u32(&body, 4)? as usize;
+                    table.chunk_offsets = (0..count)
+                        .map(|i| be_u32(&body, 8 + i * 4).map(u64::from))
+                        .collect::<io::Result<_>>()?;
+                }
+                atom::CO64 => {
+                    let body = atom::read_body(file, child)?;
+                    let count = be_u32(&body, 4)? as usize;
+                    table.chunk_offsets = (0..count)
+                        .map(|i| be_u64(&body, 8 + i * 8))
+                        .collect::<io::Result<_>>()?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Returns the size of the sample at the given 0-based index, if there is one.
+    pub(crate) fn size_of(&self, index: usize) -> Option<u32> {
+        if index >= self.sample_count as usize {
+            None
+        } else if self.sample_size != 0 {
+            Some(self.sample_size)
+        } else {
+            self.sample_sizes.get(index).copied()
+        }
+    }
+
+    /// Returns the total duration of the samples, in the time scale of the media.
+    pub(crate) fn duration(&self) -> u64 {
+        self.time_to_sample
+            .iter()
+            .map(|entry| entry.sample_count as u64 * entry.sample_delta as u64)
+            .sum()
+    }
+
+    /// Computes where every sample is in the file, in decoding order.
+    ///
+    /// Each chunk starts at its chunk offset and holds the number of samples given by
+    /// the last `stsc` entry whose first chunk is not after it; the samples of a chunk
+    /// are stored contiguously and share the sample description of that entry. The
+    /// durations are taken from the runs of `stts`, in the same order.
+    pub(crate) fn samples(&self) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        let mut durations = self
+            .time_to_sample
+            .iter()
+            .flat_map(|entry| (0..entry.sample_count).map(|_| entry.sample_delta));
+
+        for (chunk_index, chunk_offset) in self.chunk_offsets.iter().enumerate() {
+            let chunk_number = chunk_index as u32 + 1;
+            let Some(entry) = self
+                .sample_to_chunk
+                .iter()
+                .rev()
+                .find(|entry| entry.first_chunk <= chunk_number)
+            else {
+                continue;
+            };
+
+            let mut offset = *chunk_offset;
+            for _ in 0..entry.samples_per_chunk {
+                let Some(size) = self.size_of(samples.len()) else {
+                    return samples;
+                };
+                samples.push(Sample {
+                    offset,
+                    size,
+                    sample_description_index: entry.sample_description_index,
+                    duration: durations.next().unwrap_or(0),
+                });
+                offset += size as u64;
+            }
+        }
+
+        samples
+    }
+}
+
+fn be_u32(buf: &[u8], at: usize) -> io::Result<u32> {
+    buf.get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(truncated_table)
+}
+
+fn be_u64(buf: &[u8], at: usize) -> io::Result<u64> {
+    buf.get(at..at + 8)
+        .map(|b| u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+        .ok_or_else(truncated_table)
+}
+
+fn truncated_table() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "sample table has more entries than its atom can hold",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_samples() {
+        // Two chunks of two samples, then chunks of a single sample using the second
+        // sample description.
+        let table = SampleTable {
+            sample_count: 5,
+            sample_sizes: [10, 20, 30, 40, 50].to_vec(),
+            sample_to_chunk: [
+                SampleToChunk {
+                    first_chunk: 1,
+                    samples_per_chunk: 2,
+                    sample_description_index: 1,
+                },
+                SampleToChunk {
+                    first_chunk: 3,
+                    samples_per_chunk: 1,
+                    sample_description_index: 2,
+                },
+            ]
+            .to_vec(),
+            chunk_offsets: [100, 200, 300].to_vec(),
+            time_to_sample: [
+                TimeToSample {
+                    sample_count: 3,
+                    sample_delta: 1001,
+                },
+                TimeToSample {
+                    sample_count: 1,
+                    sample_delta: 2002,
+                },
+            ]
+            .to_vec(),
+            ..Default::default()
+        };
+
+        let samples = table.samples();
+        let offsets: Vec<u64> = samples.iter().map(|s| s.offset).collect();
+        let indexes: Vec<u32> = samples.iter().map(|s| s.sample_description_index).collect();
+        assert_eq!(offsets, [100, 110, 200, 230, 300]);
+        assert_eq!(indexes, [1, 1, 1, 1, 2]);
+        // The last sample isn't covered by stts.
+        let durations: Vec<u32> = samples.iter().map(|s| s.duration).collect();
+        assert_eq!(durations, [1001, 1001, 1001, 2002, 0]);
+    }
+
+    #[test]
+    fn test_read_sample_sizes() {
+        // A constant size for a huge number of samples, and a table claiming more
+        // sizes than it holds.
+        let constant = testing::atom(b"stsz", &[0, 0, 0, 0, 0, 0, 1, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let truncated = testing::atom(
+            b"stsz",
+            &[
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x03, 0xE8, 0, 0, 1, 0, 0, 0, 2, 0,
+            ],
+        );
+        let bytes = [constant, truncated].concat();
+        let path = testing::write_file("stsz.mov", &bytes);
+        let mut file = File::open(&path).unwrap();
+        let atoms = atom::read_children(&mut file, 0, bytes.len() as u64).unwrap();
+
+        let table = SampleTable::read(&mut file, &atoms[..1]).unwrap();
+        assert_eq!((table.sample_size, table.sample_count), (256, u32::MAX));
+        assert!(table.sample_sizes.is_empty());
+        assert_eq!(table.size_of(u32::MAX as usize - 1), Some(256));
+        assert_eq!(table.size_of(u32::MAX as usize), None);
+
+        assert_eq!(
+            SampleTable::read(&mut file, &atoms[1..])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
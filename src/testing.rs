@@ -0,0 +1,681 @@
+//! Helpers to build small synthetic QuickTime files for the tests.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use crate::{avc, Video};
+
+/// A file of the temporary directory, removed when dropped so that a failing test
+/// doesn't leave it behind.
+pub(crate) struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        TempFile(std::env::temp_dir().join(format!("atom_modifier_{}", name)))
+    }
+}
+
+impl Deref for TempFile {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Builds an atom from its type and payload.
+pub(crate) fn atom(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut atom = Vec::with_capacity(8 + payload.len());
+    atom.extend_from_slice(&(8 + payload.len() as u32).to_be_bytes());
+    atom.extend_from_slice(kind);
+    atom.extend_from_slice(payload);
+    atom
+}
+
+/// Builds a video sample description entry of the given format with the given child
+/// atoms (`fiel`, `colr`, `gama`...).
+pub(crate) fn visual_sample_entry(format: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+    let mut payload = vec![0; 78];
+    payload[7] = 1; // data reference index
+    payload[24..28].copy_from_slice(&[0x07, 0x80, 0x04, 0x38]); // 1920x1080
+    payload[74..76].copy_from_slice(&24u16.to_be_bytes()); // depth
+    payload[76..78].copy_from_slice(&(-1i16).to_be_bytes()); // color table id
+    for child in children {
+        payload.extend_from_slice(child);
+    }
+    atom(format, &payload)
+}
+
+/// Builds an 18-byte 'colr' atom of type 'nclc'.
+pub(crate) fn nclc_colr_atom(primaries: u16, transfer: u16, matrix: u16) -> Vec<u8> {
+    let mut payload = b"nclc".to_vec();
+    for index in [primaries, transfer, matrix] {
+        payload.extend_from_slice(&index.to_be_bytes());
+    }
+    atom(b"colr", &payload)
+}
+
+/// Builds a 19-byte 'colr' atom of type 'nclx'.
+pub(crate) fn nclx_colr_atom(
+    primaries: u16,
+    transfer: u16,
+    matrix: u16,
+    full_range: bool,
+) -> Vec<u8> {
+    let mut payload = b"nclx".to_vec();
+    for index in [primaries, transfer, matrix] {
+        payload.extend_from_slice(&index.to_be_bytes());
+    }
+    payload.push(if full_range { 0x80 } else { 0 });
+    atom(b"colr", &payload)
+}
+
+/// Builds a minimal version 2 RGB display ICC profile with the given description, a
+/// D65 white point and the same pure gamma curve for the three channels.
+pub(crate) fn icc_profile(description: &str, gamma: f64) -> Vec<u8> {
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&(description.len() as u32 + 1).to_be_bytes());
+    desc.extend_from_slice(description.as_bytes());
+    desc.push(0);
+    desc.resize(desc.len().next_multiple_of(4), 0);
+
+    let mut wtpt = b"XYZ \0\0\0\0".to_vec();
+    for value in [0.9505f64, 1.0, 1.0891] {
+        wtpt.extend_from_slice(&((value * 65536.0).round() as i32).to_be_bytes());
+    }
+
+    let mut curv = b"curv\0\0\0\0".to_vec();
+    curv.extend_from_slice(&1u32.to_be_bytes());
+    curv.extend_from_slice(&((gamma * 256.0) as u16).to_be_bytes());
+    curv.extend_from_slice(&[0; 2]);
+
+    // The three TRC tags share the same data, as allowed by the specification.
+    let tags: [(&[u8; 4], usize); 5] = [
+        (b"desc", 0),
+        (b"wtpt", 1),
+        (b"rTRC", 2),
+        (b"gTRC", 2),
+        (b"bTRC", 2),
+    ];
+    let data = [desc, wtpt, curv];
+    let mut offsets = Vec::new();
+    let mut offset = 128 + 4 + tags.len() * 12;
+    for element in data.iter() {
+        offsets.push(offset);
+        offset += element.len();
+    }
+
+    let mut profile = vec![0; 128];
+    profile[0..4].copy_from_slice(&(offset as u32).to_be_bytes());
+    profile[8] = 2;
+    profile[9] = 0x10;
+    profile[12..16].copy_from_slice(b"mntr");
+    profile[16..20].copy_from_slice(b"RGB ");
+    profile[20..24].copy_from_slice(b"XYZ ");
+    profile[36..40].copy_from_slice(b"acsp");
+    profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    for (signature, element) in tags {
+        profile.extend_from_slice(signature);
+        profile.extend_from_slice(&(offsets[element] as u32).to_be_bytes());
+        profile.extend_from_slice(&(data[element].len() as u32).to_be_bytes());
+    }
+    profile.extend_from_slice(&data.concat());
+    profile
+}
+
+/// Builds a 'colr' atom of type 'prof' embedding the given ICC profile.
+pub(crate) fn prof_colr_atom(profile: &[u8]) -> Vec<u8> {
+    atom(b"colr", &[&b"prof"[..], profile].concat())
+}
+
+/// Builds a progressive ProRes 422 frame of `size` bytes: a frame header carrying the
+/// given color bytes, followed by a dummy picture.
+pub(crate) fn prores_frame(size: u32, color: [u8; 3]) -> Vec<u8> {
+    prores_frame_of_pictures(size, color, 0, 1)
+}
+
+/// Builds an interlaced, top field first, ProRes 422 frame of `size` bytes: a frame
+/// header carrying the given color bytes, followed by a dummy picture per field.
+pub(crate) fn interlaced_prores_frame(size: u32, color: [u8; 3]) -> Vec<u8> {
+    prores_frame_of_pictures(size, color, 1, 2)
+}
+
+fn prores_frame_of_pictures(
+    size: u32,
+    color: [u8; 3],
+    interlace_mode: u8,
+    picture_count: u32,
+) -> Vec<u8> {
+    let mut frame = vec![0; size as usize];
+    frame[0..4].copy_from_slice(&size.to_be_bytes());
+    frame[4..8].copy_from_slice(b"icpf");
+    frame[8..10].copy_from_slice(&148u16.to_be_bytes());
+    frame[12..16].copy_from_slice(b"apl0");
+    frame[16..20].copy_from_slice(&[0x07, 0x80, 0x04, 0x38]);
+    frame[20] = 0x80 | interlace_mode << 2; // 4:2:2
+    frame[22..25].copy_from_slice(&color);
+
+    // Each picture starts with the size of its header (8 bytes) and its own size.
+    let picture_size = (size - 156) / picture_count;
+    for picture in 0..picture_count {
+        let offset = (156 + picture * picture_size) as usize;
+        frame[offset] = 8 << 3;
+        frame[offset + 1..offset + 5].copy_from_slice(&picture_size.to_be_bytes());
+    }
+    frame
+}
+
+/// Writes the fields of an H.264 or HEVC parameter set, bit by bit.
+#[derive(Default)]
+struct BitWriter(Vec<bool>);
+
+impl BitWriter {
+    fn u(&mut self, value: u32, count: usize) -> &mut Self {
+        self.0
+            .extend((0..count).rev().map(|shift| value >> shift & 1 == 1));
+        self
+    }
+
+    fn ue(&mut self, value: u32) -> &mut Self {
+        let code = value + 1;
+        let length = 32 - code.leading_zeros() as usize;
+        self.u(0, length - 1).u(code, length)
+    }
+
+    fn se(&mut self, value: i32) -> &mut Self {
+        let code = if value > 0 { 2 * value - 1 } else { -2 * value };
+        self.ue(code as u32)
+    }
+
+    /// Ends the RBSP with its trailing bits and packs it into bytes.
+    fn into_rbsp(mut self) -> Vec<u8> {
+        self.0.push(true);
+        while !self.0.len().is_multiple_of(8) {
+            self.0.push(false);
+        }
+        self.0
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |value, &bit| value << 1 | bit as u8))
+            .collect()
+    }
+}
+
+/// Builds a 1920x1080 High profile H.264 sequence parameter set NAL unit with a
+/// scaling matrix and picture order count type 1, and the given video signal type. A
+/// VUI is written if there is a video signal type or `vui_extras`, in which case it
+/// also has a sample aspect ratio and timing info at 25 fps.
+pub(crate) fn h264_sps(
+    video_signal_type: Option<avc::VideoSignalType>,
+    vui_extras: bool,
+) -> Vec<u8> {
+    let mut sps = BitWriter::default();
+    sps.u(100, 8).u(0, 8).u(40, 8).ue(0); // profile, constraints, level, id
+    sps.ue(1).ue(0).ue(0).u(0, 1); // 4:2:0, 8 bits
+    sps.u(1, 1).u(1, 1).se(2).se(-10).u(0, 7); // one scaling list, then 7 absent
+    sps.ue(0).ue(1).u(0, 1).se(-2).se(1).ue(2).se(1).se(-1); // frame num, POC type 1
+    sps.ue(4).u(0, 1).ue(119).ue(67).u(1, 1).u(1, 1); // references, 120x68 macroblocks
+    sps.u(1, 1).ue(0).ue(0).ue(0).ue(4); // cropped to 1080 lines
+
+    sps.u((video_signal_type.is_some() || vui_extras) as u32, 1);
+    if video_signal_type.is_some() || vui_extras {
+        if vui_extras {
+            sps.u(1, 1).u(255, 8).u(1, 16).u(1, 16); // square pixels
+        } else {
+            sps.u(0, 1);
+        }
+        sps.u(0, 1); // overscan info
+        match video_signal_type {
+            Some(video_signal_type) => {
+                sps.u(1, 1).u(video_signal_type.video_format as u32, 3);
+                sps.u(video_signal_type.full_range as u32, 1);
+                sps.u(video_signal_type.colour_description.is_some() as u32, 1);
+                for code_point in video_signal_type.colour_description.into_iter().flatten() {
+                    sps.u(code_point as u32, 8);
+                }
+            }
+            None => {
+                sps.u(0, 1);
+            }
+        }
+        sps.u(0, 1); // chroma location
+        if vui_extras {
+            sps.u(1, 1).u(1, 32).u(50, 32).u(1, 1); // 25 fps
+        } else {
+            sps.u(0, 1);
+        }
+        sps.u(0, 4); // HRD, picture structure, bitstream restriction
+    }
+
+    let mut nal = vec![0x67];
+    nal.extend(avc::escape(&sps.into_rbsp()));
+    nal
+}
+
+/// Builds the payload of an 'avcC' atom holding the given sequence parameter set and
+/// a picture parameter set.
+pub(crate) fn avcc_payload(sps: &[u8]) -> Vec<u8> {
+    let pps = [0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0];
+    let mut payload = vec![1, sps[1], sps[2], sps[3], 0xFF, 0xE1];
+    payload.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+    payload.extend_from_slice(sps);
+    payload.push(1);
+    payload.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+    payload.extend_from_slice(&pps);
+    // 4:2:0, 8-bit luma and chroma, no SPS extension.
+    payload.extend_from_slice(&[0xFD, 0xF8, 0xF8, 0]);
+    payload
+}
+
+/// Builds a 1920x1080 Main 10 profile HEVC sequence parameter set NAL unit with two
+/// temporal sub-layers, scaling lists, short-term reference picture sets (one
+/// predicted from the other) and a long-term reference picture, and the given video
+/// signal type. A VUI is written as [`h264_sps`] does.
+pub(crate) fn hevc_sps(
+    video_signal_type: Option<avc::VideoSignalType>,
+    vui_extras: bool,
+) -> Vec<u8> {
+    let mut sps = BitWriter::default();
+    sps.u(0, 4).u(1, 3).u(1, 1); // VPS id, 2 sub-layers, temporal id nesting
+    sps.u(0, 3).u(2, 5).u(0x2000_0000, 32); // general profile: Main 10
+    sps.u(0b1001, 4).u(0, 32).u(0, 12).u(120, 8); // progressive, frame only, level 4
+    sps.u(1, 1).u(1, 1).u(0, 14); // sub-layer profile and level present
+    sps.u(0, 3)
+        .u(2, 5)
+        .u(0x2000_0000, 32)
+        .u(0b1001, 4)
+        .u(0, 32)
+        .u(0, 12)
+        .u(90, 8);
+    sps.ue(0)
+        .ue(1)
+        .ue(1920)
+        .ue(1088)
+        .u(1, 1)
+        .ue(0)
+        .ue(0)
+        .ue(0)
+        .ue(4); // 4:2:0, 1080
+    sps.ue(2).ue(2).ue(4); // 10 bits, 8-bit POC LSB
+    sps.u(1, 1).ue(4).ue(2).ue(0).ue(4).ue(2).ue(0); // ordering info per sub-layer
+    sps.ue(0).ue(3).ue(0).ue(3).ue(1).ue(1); // block sizes, hierarchy depths
+    sps.u(1, 1).u(1, 1); // scaling lists, coded in the SPS
+    for size_id in 0..4 {
+        for matrix_id in (0..6).step_by(if size_id == 3 { 3 } else { 1 }) {
+            if matrix_id == 0 {
+                sps.u(1, 1);
+                if size_id > 1 {
+                    sps.se(8);
+                }
+                for coefficient in 0..64.min(1 << (4 + 2 * size_id)) {
+                    sps.se(if coefficient % 2 == 0 { 1 } else { -1 });
+                }
+            } else {
+                sps.u(0, 1).ue(1); // copied from the previous matrix
+            }
+        }
+    }
+    sps.u(1, 1).u(1, 1).u(0, 1); // AMP, SAO, no PCM
+    sps.ue(2).ue(2).ue(0).ue(0).u(1, 1).ue(1).u(1, 1); // two previous pictures
+    sps.u(1, 1)
+        .u(0, 1)
+        .ue(0)
+        .u(1, 1)
+        .u(0, 1)
+        .u(1, 1)
+        .u(0, 1)
+        .u(0, 1); // predicted
+    sps.u(1, 1).ue(1).u(16, 8).u(1, 1); // a long-term reference picture
+    sps.u(1, 1).u(1, 1); // temporal MVP, strong intra smoothing
+
+    sps.u((video_signal_type.is_some() || vui_extras) as u32, 1);
+    if video_signal_type.is_some() || vui_extras {
+        if vui_extras {
+            sps.u(1, 1).u(255, 8).u(1, 16).u(1, 16); // square pixels
+        } else {
+            sps.u(0, 1);
+        }
+        sps.u(0, 1); // overscan info
+        match video_signal_type {
+            Some(video_signal_type) => {
+                sps.u(1, 1).u(video_signal_type.video_format as u32, 3);
+                sps.u(video_signal_type.full_range as u32, 1);
+                sps.u(video_signal_type.colour_description.is_some() as u32, 1);
+                for code_point in video_signal_type.colour_description.into_iter().flatten() {
+                    sps.u(code_point as u32, 8);
+                }
+            }
+            None => {
+                sps.u(0, 1);
+            }
+        }
+        // Chroma location, neutral chroma, field sequence, frame field info and
+        // default display window.
+        sps.u(0, 5);
+        if vui_extras {
+            sps.u(1, 1).u(1, 32).u(25, 32).u(0, 1).u(0, 1); // 25 fps, no HRD
+        } else {
+            sps.u(0, 1);
+        }
+        sps.u(0, 1); // bitstream restriction
+    }
+    sps.u(0, 1); // no SPS extension
+
+    let mut nal = vec![0x42, 0x01];
+    nal.extend(avc::escape(&sps.into_rbsp()));
+    nal
+}
+
+/// Builds the payload of an 'hvcC' atom holding a video parameter set, the given
+/// sequence parameter set and a picture parameter set, for NAL units preceded by
+/// 4-byte lengths.
+pub(crate) fn hvcc_payload(sps: &[u8]) -> Vec<u8> {
+    let vps = [0x40, 0x01, 0x0C, 0x01, 0xFF, 0xFF];
+    let pps = [0x44, 0x01, 0xC1, 0x72, 0xB4, 0x62, 0x40];
+    let mut payload = vec![1, 0x02, 0x20, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 120];
+    payload.extend_from_slice(&[0xF0, 0, 0xFC, 0xFD, 0xFA, 0xFA, 0, 0, 0x0F]);
+    payload.push(3);
+    for (nal_unit_type, nal) in [(32, &vps[..]), (33, sps), (34, &pps[..])] {
+        payload.push(0x80 | nal_unit_type);
+        payload.extend_from_slice(&1u16.to_be_bytes());
+        payload.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        payload.extend_from_slice(nal);
+    }
+    payload
+}
+
+/// Builds a sample of an H.264 or HEVC track from its NAL units, each preceded by
+/// its length on 4 bytes.
+pub(crate) fn length_prefixed(nal_units: &[Vec<u8>]) -> Vec<u8> {
+    let mut sample = Vec::new();
+    for nal in nal_units {
+        sample.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        sample.extend_from_slice(nal);
+    }
+    sample
+}
+
+/// A track of a synthetic file, whose samples are stored one per chunk.
+#[derive(Default)]
+pub(crate) struct Track {
+    pub(crate) handler_type: [u8; 4],
+    pub(crate) sample_entries: Vec<Vec<u8>>,
+    pub(crate) samples: Vec<Vec<u8>>,
+    /// The 1-based sample description index of each sample. When empty, every sample
+    /// uses the first sample description.
+    pub(crate) sample_description_indexes: Vec<u32>,
+    /// The duration of each sample in the media time scale of 24000. When empty, every
+    /// sample lasts 1001, i.e. a frame at 23.976 fps.
+    pub(crate) durations: Vec<u32>,
+}
+
+/// Where a synthetic file has a `free` atom of the given size.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub(crate) enum Free {
+    #[default]
+    None,
+    InsideMoov(u32),
+    AfterMoov(u32),
+}
+
+/// How a synthetic file is laid out.
+#[derive(Default, Clone, Copy)]
+pub(crate) struct Layout {
+    /// Whether moov comes before mdat ("fast start") or after it.
+    pub(crate) moov_first: bool,
+    pub(crate) free: Free,
+    /// Whether the chunk offsets are stored in `co64` tables rather than `stco`.
+    pub(crate) co64: bool,
+    /// Number of zero bytes at the beginning of the mdat payload, before the samples.
+    /// They are left as a hole in the file, so that tests can build files over 4 GB
+    /// without using the disk space.
+    pub(crate) mdat_gap: u64,
+    /// Whether mdat has a size of 0, i.e. extends to the end of the file. Only makes
+    /// sense with `moov_first`.
+    pub(crate) mdat_to_end: bool,
+    /// Whether the file is an MP4 file (ftyp brand 'isom') rather than a QuickTime
+    /// movie.
+    pub(crate) mp4: bool,
+}
+
+fn full_atom(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![0; 4]; // version and flags
+    body.extend_from_slice(payload);
+    atom(kind, &body)
+}
+
+fn free_atom(size: u32) -> Vec<u8> {
+    atom(b"free", &vec![0; size as usize - 8])
+}
+
+fn trak(track_id: u32, track: &Track, chunk_offsets: &[u64], co64: bool) -> Vec<u8> {
+    let mut tkhd = vec![0; 80];
+    tkhd[8..12].copy_from_slice(&track_id.to_be_bytes());
+
+    let mut hdlr = b"mhlr".to_vec();
+    hdlr.extend_from_slice(&track.handler_type);
+    hdlr.extend_from_slice(&[0; 13]);
+
+    let durations: Vec<u32> = (0..track.samples.len())
+        .map(|index| track.durations.get(index).copied().unwrap_or(1001))
+        .collect();
+    let mut mdhd = vec![0; 20];
+    mdhd[8..12].copy_from_slice(&24000u32.to_be_bytes());
+    mdhd[12..16].copy_from_slice(&durations.iter().sum::<u32>().to_be_bytes());
+
+    // One entry per run of samples of the same duration.
+    let mut stts_entries: Vec<[u32; 2]> = Vec::new();
+    for &duration in durations.iter() {
+        match stts_entries.last_mut() {
+            Some(entry) if entry[1] == duration => entry[0] += 1,
+            _ => stts_entries.push([1, duration]),
+        }
+    }
+    let mut stts = (stts_entries.len() as u32).to_be_bytes().to_vec();
+    for value in stts_entries.concat() {
+        stts.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut stsd = (track.sample_entries.len() as u32).to_be_bytes().to_vec();
+    for entry in track.sample_entries.iter() {
+        stsd.extend_from_slice(entry);
+    }
+
+    let mut stsz = 0u32.to_be_bytes().to_vec();
+    stsz.extend_from_slice(&(track.samples.len() as u32).to_be_bytes());
+    for sample in track.samples.iter() {
+        stsz.extend_from_slice(&(sample.len() as u32).to_be_bytes());
+    }
+
+    // One entry each time the sample description changes, from chunk to chunk.
+    let mut stsc_entries: Vec<[u32; 3]> = Vec::new();
+    for chunk in 0..track.samples.len().max(1) {
+        let index = track
+            .sample_description_indexes
+            .get(chunk)
+            .copied()
+            .unwrap_or(1);
+        if stsc_entries.last().is_none_or(|entry| entry[2] != index) {
+            stsc_entries.push([chunk as u32 + 1, 1, index]);
+        }
+    }
+    let mut stsc = (stsc_entries.len() as u32).to_be_bytes().to_vec();
+    for value in stsc_entries.concat() {
+        stsc.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut chunk_offset_table = (chunk_offsets.len() as u32).to_be_bytes().to_vec();
+    for offset in chunk_offsets {
+        if co64 {
+            chunk_offset_table.extend_from_slice(&offset.to_be_bytes());
+        } else {
+            chunk_offset_table.extend_from_slice(&(*offset as u32).to_be_bytes());
+        }
+    }
+
+    let stbl = atom(
+        b"stbl",
+        &[
+            full_atom(b"stsd", &stsd),
+            full_atom(b"stts", &stts),
+            full_atom(b"stsz", &stsz),
+            full_atom(b"stsc", &stsc),
+            full_atom(if co64 { b"co64" } else { b"stco" }, &chunk_offset_table),
+        ]
+        .concat(),
+    );
+    let mdia = [
+        full_atom(b"mdhd", &mdhd),
+        full_atom(b"hdlr", &hdlr),
+        atom(b"minf", &stbl),
+    ]
+    .concat();
+    atom(
+        b"trak",
+        &[full_atom(b"tkhd", &tkhd), atom(b"mdia", &mdia)].concat(),
+    )
+}
+
+fn moov(tracks: &[Track], samples_offset: u64, layout: &Layout) -> Vec<u8> {
+    let mut offset = samples_offset;
+    let mut traks = Vec::new();
+    for (index, track) in tracks.iter().enumerate() {
+        let chunk_offsets: Vec<u64> = track
+            .samples
+            .iter()
+            .map(|sample| {
+                let chunk_offset = offset;
+                offset += sample.len() as u64;
+                chunk_offset
+            })
+            .collect();
+        traks.extend_from_slice(&trak(index as u32 + 1, track, &chunk_offsets, layout.co64));
+    }
+    if let Free::InsideMoov(size) = layout.free {
+        traks.extend_from_slice(&free_atom(size));
+    }
+    atom(b"moov", &traks)
+}
+
+/// Writes a synthetic QuickTime file made of ftyp, mdat and moov, laid out as
+/// described by `layout`, to the temporary directory.
+///
+/// mdat gets a largesize header when it is bigger than 4 GB.
+pub(crate) fn write_mov(name: &str, tracks: &[Track], layout: Layout) -> TempFile {
+    let ftyp = if layout.mp4 {
+        atom(b"ftyp", b"isom\x00\x00\x02\x00isommp41")
+    } else {
+        atom(b"ftyp", b"qt  \x00\x00\x02\x00qt  ")
+    };
+    let samples: Vec<u8> = tracks.iter().flat_map(|t| t.samples.concat()).collect();
+
+    let mdat_size = 8 + layout.mdat_gap + samples.len() as u64;
+    let mdat_header = if layout.mdat_to_end {
+        [&0u32.to_be_bytes()[..], b"mdat"].concat()
+    } else if mdat_size > u32::MAX as u64 {
+        [
+            &1u32.to_be_bytes()[..],
+            b"mdat",
+            &(mdat_size + 8).to_be_bytes(),
+        ]
+        .concat()
+    } else {
+        [&(mdat_size as u32).to_be_bytes()[..], b"mdat"].concat()
+    };
+    let after_moov = match layout.free {
+        Free::AfterMoov(size) => free_atom(size),
+        _ => Vec::new(),
+    };
+
+    let path = TempFile::new(name);
+    let mut file = File::create(&path).unwrap();
+    file.write_all(&ftyp).unwrap();
+
+    let gap = mdat_header.len() as u64 + layout.mdat_gap;
+    if layout.moov_first {
+        let moov_size = (moov(tracks, 0, &layout).len() + after_moov.len()) as u64;
+        let samples_offset = ftyp.len() as u64 + moov_size + gap;
+        file.write_all(&moov(tracks, samples_offset, &layout))
+            .unwrap();
+        file.write_all(&after_moov).unwrap();
+        file.write_all(&mdat_header).unwrap();
+        file.seek(SeekFrom::Current(layout.mdat_gap as i64))
+            .unwrap();
+        file.write_all(&samples).unwrap();
+    } else {
+        let samples_offset = ftyp.len() as u64 + gap;
+        file.write_all(&mdat_header).unwrap();
+        file.seek(SeekFrom::Current(layout.mdat_gap as i64))
+            .unwrap();
+        file.write_all(&samples).unwrap();
+        file.write_all(&moov(tracks, samples_offset, &layout))
+            .unwrap();
+        file.write_all(&after_moov).unwrap();
+    }
+
+    path
+}
+
+/// Writes the given bytes to a file of the temporary directory.
+pub(crate) fn write_file(name: &str, bytes: &[u8]) -> TempFile {
+    let path = TempFile::new(name);
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+/// Copies one of the test footages to the temporary directory.
+pub(crate) fn copy_footage(footage: &str, name: &str) -> TempFile {
+    let path = TempFile::new(name);
+    std::fs::copy(Path::new("tests/footages").join(footage), &path).unwrap();
+    path
+}
+
+/// Opens a temporary file for reading and writing and decodes it.
+pub(crate) fn open(path: TempFile) -> (TempFile, File, Video) {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .unwrap();
+    let mut video = Video::default();
+    video.decode(path.to_str().unwrap()).unwrap();
+    (path, file, video)
+}
+
+/// Writes a synthetic QuickTime file as [`write_mov`] does, then opens and decodes it.
+pub(crate) fn open_mov(name: &str, tracks: &[Track], layout: Layout) -> (TempFile, File, Video) {
+    open(write_mov(name, tracks, layout))
+}
+
+/// Copies one of the test footages as [`copy_footage`] does, then opens and decodes it.
+pub(crate) fn open_footage(footage: &str, name: &str) -> (TempFile, File, Video) {
+    open(copy_footage(footage, name))
+}
+
+/// Decodes a file from scratch.
+pub(crate) fn decode(path: &Path) -> Video {
+    let mut video = Video::default();
+    video.decode(path.to_str().unwrap()).unwrap();
+    video
+}
+
+/// Decodes the file again after an edit, checks that it matches the description the
+/// edit left in `video`, and returns it.
+pub(crate) fn redecode(path: &Path, video: &Video) -> Video {
+    let decoded = decode(path);
+    assert_eq!(&decoded, video);
+    decoded
+}
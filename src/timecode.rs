@@ -0,0 +1,467 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::str::FromStr;
+
+use crate::atom::{self, AtomHeader};
+use crate::{FrameRate, NameAtom, SampleDescription};
+
+/// Flag of a timecode sample entry: frame numbers are dropped to stay in sync with a
+/// 1000/1001 rate, e.g. two every minute but every tenth one at 29.97 fps.
+const DROP_FRAME: u32 = 0x1;
+/// Flag of a timecode sample entry: its samples hold a tape counter instead of a frame
+/// number.
+const COUNTER: u32 = 0x8;
+
+/// Size of the fields of a 'name' atom before the reel name itself: its length and
+/// its language code (2 bytes each).
+const NAME_FIELDS_SIZE: usize = 4;
+
+/// Decodes the payload of the 'name' atom of a timecode sample description entry: the
+/// reel name (or source name) of the tape or camera card the media comes from. A
+/// length greater than the payload gives `None`.
+pub(crate) fn reel_name_from_bytes(payload: &[u8]) -> Option<String> {
+    let length = u16::from_be_bytes([*payload.first()?, *payload.get(1)?]) as usize;
+    let name = payload.get(NAME_FIELDS_SIZE..NAME_FIELDS_SIZE + length)?;
+    Some(String::from_utf8_lossy(name).into_owned())
+}
+
+/// Encodes the payload of a 'name' atom holding `reel_name`, with the language code 0
+/// (English) as QuickTime writes it.
+pub(crate) fn reel_name_to_bytes(reel_name: &str) -> Vec<u8> {
+    let mut payload = (reel_name.len() as u16).to_be_bytes().to_vec();
+    payload.extend_from_slice(&[0; 2]);
+    payload.extend_from_slice(reel_name.as_bytes());
+    payload
+}
+
+/// Checks that a reel name can be stored in a 'name' atom and understood by the
+/// editing applications relinking to it: printable ASCII characters (the language
+/// code of the atom makes it Mac Roman), at most 255 of them.
+pub(crate) fn check_reel_name(reel_name: &str) -> Result<(), String> {
+    if reel_name.is_empty() || reel_name.len() > 255 {
+        return Err("a reel name has 1 to 255 characters".to_string());
+    }
+    if !reel_name.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+        return Err("a reel name is made of printable ASCII characters".to_string());
+    }
+    Ok(())
+}
+
+/// The fields of a timecode sample entry ('tmcd') giving the meaning of the frame
+/// numbers held by the samples of the track.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TimecodeFormat {
+    pub(crate) flags: u32,
+    pub(crate) timescale: u32,
+    pub(crate) frame_duration: u32,
+    /// The number of frames per second the timecode counts, e.g. 30 at 29.97 fps.
+    pub(crate) number_of_frames: u8,
+}
+
+impl TimecodeFormat {
+    /// Decodes the fields of a timecode sample entry following its reserved bytes and
+    /// data reference index: reserved (4 bytes), flags, time scale, frame duration
+    /// (4 bytes each) and number of frames (1 byte).
+    pub(crate) fn from_bytes(bytes: &[u8; 17]) -> Self {
+        let u32_at = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+        TimecodeFormat {
+            flags: u32_at(4),
+            timescale: u32_at(8),
+            frame_duration: u32_at(12),
+            number_of_frames: bytes[16],
+        }
+    }
+
+    /// Encodes the fields decoded by [`TimecodeFormat::from_bytes`], with zeroed
+    /// reserved bytes.
+    pub(crate) fn to_bytes(self) -> [u8; 17] {
+        let mut bytes = [0; 17];
+        bytes[4..8].copy_from_slice(&self.flags.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.timescale.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.frame_duration.to_be_bytes());
+        bytes[16] = self.number_of_frames;
+        bytes
+    }
+
+    /// Returns this format counting frames at `frame_rate`, whose media has the given
+    /// time scale and frame duration. Frame numbers are dropped if timecodes drop them
+    /// at that rate, and not otherwise.
+    pub(crate) fn with_frame_rate(
+        self,
+        frame_rate: FrameRate,
+        timescale: u32,
+        frame_duration: u32,
+    ) -> Self {
+        let flags = if frame_rate.drop_frame() {
+            self.flags | DROP_FRAME
+        } else {
+            self.flags & !DROP_FRAME
+        };
+        TimecodeFormat {
+            flags,
+            timescale,
+            frame_duration,
+            number_of_frames: frame_rate.frames_per_second(),
+        }
+    }
+
+    pub(crate) fn drop_frame(&self) -> bool {
+        self.flags & DROP_FRAME != 0
+    }
+
+    /// Returns whether the samples hold frame numbers this format can turn into
+    /// timecodes, i.e. it isn't a counter and counts a number of frames per second.
+    pub(crate) fn is_supported(&self) -> bool {
+        self.flags & COUNTER == 0 && self.number_of_frames > 0
+    }
+
+    /// Returns the number of frame numbers dropped at the start of every minute but
+    /// every tenth one: 2 at 30 frames per second, 4 at 60.
+    fn dropped_frames(&self) -> u32 {
+        if self.drop_frame() {
+            self.number_of_frames as u32 / 15
+        } else {
+            0
+        }
+    }
+
+    /// Returns the number of frames in 24 hours, after which timecodes wrap around.
+    pub(crate) fn frames_per_day(&self) -> u32 {
+        self.number_of_frames as u32 * 86_400 - self.dropped_frames() * (1440 - 144)
+    }
+
+    /// Returns the timecode of a frame number, wrapped around at 24 hours.
+    pub(crate) fn timecode(&self, frame_number: u32) -> Timecode {
+        let fps = self.number_of_frames as u32;
+        let dropped = self.dropped_frames();
+        let mut frame_number = frame_number % self.frames_per_day();
+
+        if dropped > 0 {
+            // Count the frame numbers dropped before this frame, the first minute of
+            // every ten dropping none.
+            let frames_per_minute = fps * 60 - dropped;
+            let frames_per_ten_minutes = frames_per_minute * 10 + dropped;
+            let tens = frame_number / frames_per_ten_minutes;
+            let rest = frame_number % frames_per_ten_minutes;
+            frame_number += dropped * 9 * tens;
+            if rest > dropped {
+                frame_number += dropped * ((rest - dropped) / frames_per_minute);
+            }
+        }
+
+        Timecode {
+            hours: (frame_number / (fps * 3600)) as u8,
+            minutes: (frame_number / (fps * 60) % 60) as u8,
+            seconds: (frame_number / fps % 60) as u8,
+            frames: (frame_number % fps) as u8,
+            drop_frame: self.drop_frame(),
+        }
+    }
+
+    /// Returns the frame number of a timecode.
+    ///
+    /// # Errors
+    ///
+    /// Returns the reason why the timecode can't be counted in this format: too many
+    /// frames, or a frame number dropped by a drop frame timecode.
+    pub(crate) fn frame_number(&self, timecode: &Timecode) -> Result<u32, String> {
+        let fps = self.number_of_frames as u32;
+        let dropped = self.dropped_frames();
+        if timecode.frames as u32 >= fps {
+            return Err(format!(
+                "{} has more than the {} frames per second of the timecode track",
+                timecode, fps
+            ));
+        }
+        if dropped > 0
+            && timecode.seconds == 0
+            && !timecode.minutes.is_multiple_of(10)
+            && (timecode.frames as u32) < dropped
+        {
+            return Err(format!(
+                "{} is skipped by the drop frame timecode track",
+                timecode
+            ));
+        }
+
+        let minutes = timecode.hours as u32 * 60 + timecode.minutes as u32;
+        Ok(
+            fps * (minutes * 60 + timecode.seconds as u32) + timecode.frames as u32
+                - dropped * (minutes - minutes / 10),
+        )
+    }
+}
+
+impl fmt::Display for TimecodeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} frames per second, frame duration {}/{}, {}",
+            self.number_of_frames,
+            self.frame_duration,
+            self.timescale,
+            if self.flags & COUNTER != 0 {
+                "counter"
+            } else if self.drop_frame() {
+                "drop frame"
+            } else {
+                "non-drop frame"
+            }
+        )
+    }
+}
+
+/// A SMPTE timecode, written HH:MM:SS:FF, or HH:MM:SS;FF when it drops frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timecode {
+    hours: u8,
+    minutes: u8,
+    seconds: u8,
+    frames: u8,
+    drop_frame: bool,
+}
+
+impl FromStr for Timecode {
+    type Err = String;
+
+    /// Parses HH:MM:SS:FF. A semicolon, or a period, before the frames is accepted for
+    /// drop frame timecodes, but whether frames are dropped is up to the timecode
+    /// track.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' isn't a timecode, expected HH:MM:SS:FF", s);
+        let (time, frames, drop_frame) = match s.rfind([';', '.']) {
+            Some(at) => (&s[..at], &s[at + 1..], true),
+            None => match s.rsplit_once(':') {
+                Some((time, frames)) => (time, frames, false),
+                None => return Err(invalid()),
+            },
+        };
+        let fields: Vec<&str> = time.split(':').chain([frames]).collect();
+        if fields.len() != 4
+            || fields
+                .iter()
+                .any(|field| field.is_empty() || field.len() > 2)
+        {
+            return Err(invalid());
+        }
+        let mut values = [0; 4];
+        for (value, field) in values.iter_mut().zip(fields) {
+            *value = field.parse::<u8>().map_err(|_| invalid())?;
+        }
+
+        let [hours, minutes, seconds, frames] = values;
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return Err(format!(
+                "{} is out of range, hours go up to 23, minutes and seconds up to 59",
+                s
+            ));
+        }
+        Ok(Timecode {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            drop_frame,
+        })
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours,
+            self.minutes,
+            self.seconds,
+            if self.drop_frame { ';' } else { ':' },
+            self.frames
+        )
+    }
+}
+
+impl SampleDescription {
+    /// Constructs the name atom of a timecode sample entry, holding its reel name, and
+    /// sets its offset and size. A name atom whose length exceeds its size is ignored.
+    pub(crate) fn construct_name_atom(
+        &mut self,
+        file: &mut File,
+        header: &AtomHeader,
+    ) -> io::Result<()> {
+        let payload = atom::read_body(file, header)?;
+
+        if let Some(reel_name) = reel_name_from_bytes(&payload) {
+            self.name_atom = NameAtom {
+                size: header.size as u32,
+                offset: header.offset,
+                reel_name,
+                matched: true,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{ColrPolicy, Error, Selection, TrackSelector};
+
+    #[test]
+    fn test_reel_name() {
+        let payload = reel_name_to_bytes("A_0005_12SO");
+        assert_eq!(payload[..4], [0, 11, 0, 0]);
+        assert_eq!(
+            reel_name_from_bytes(&payload).as_deref(),
+            Some("A_0005_12SO")
+        );
+        assert_eq!(reel_name_from_bytes(&payload[..8]), None);
+
+        assert!(check_reel_name("A001C003").is_ok());
+        assert!(check_reel_name("Reel 12").is_ok());
+        assert!(check_reel_name("").is_err());
+        assert!(check_reel_name("Bobine é").is_err());
+    }
+
+    #[test]
+    fn test_timecode() {
+        let format = TimecodeFormat {
+            flags: 0,
+            timescale: 12288,
+            frame_duration: 512,
+            number_of_frames: 24,
+        };
+        let timecode: Timecode = "01:00:00:00".parse().unwrap();
+        assert_eq!(format.frame_number(&timecode), Ok(86400));
+        assert_eq!(format.timecode(86400), timecode);
+        assert_eq!(
+            format.timecode(86400 + 24 * 86400).to_string(),
+            "01:00:00:00"
+        );
+        assert!(format
+            .frame_number(&"00:00:00:24".parse().unwrap())
+            .is_err());
+
+        let drop_frame = TimecodeFormat {
+            flags: DROP_FRAME,
+            timescale: 30000,
+            frame_duration: 1001,
+            number_of_frames: 30,
+        };
+        for (frame_number, timecode) in [
+            (0, "00:00:00;00"),
+            (1799, "00:00:59;29"),
+            (1800, "00:01:00;02"),
+            (17982, "00:10:00;00"),
+            (107892, "01:00:00;00"),
+            (2589407, "23:59:59;29"),
+        ] {
+            assert_eq!(drop_frame.timecode(frame_number).to_string(), timecode);
+            let parsed: Timecode = timecode.parse().unwrap();
+            assert_eq!(drop_frame.frame_number(&parsed), Ok(frame_number));
+        }
+        assert_eq!(drop_frame.frames_per_day(), 2589408);
+        assert_eq!(
+            TimecodeFormat::from_bytes(&drop_frame.to_bytes()),
+            drop_frame
+        );
+        assert!(drop_frame
+            .frame_number(&"00:01:00;01".parse().unwrap())
+            .is_err());
+
+        assert!("1:00:00:00".parse::<Timecode>().is_ok());
+        assert!("01:00:00".parse::<Timecode>().is_err());
+        assert!("01:60:00:00".parse::<Timecode>().is_err());
+        assert!("24:00:00:00".parse::<Timecode>().is_err());
+        assert!("01:00:00:0x".parse::<Timecode>().is_err());
+    }
+
+    #[test]
+    fn test_set_reel_name() {
+        let (file_path, mut file, mut video) =
+            testing::open_footage("1-1-1_2frames_prores422.mov", "reel_name.mov");
+        let original_len = file.metadata().unwrap().len();
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("    reel name: A_0005_12SO\n"));
+        assert!(matches!(
+            video.set_reel_name(&mut file, Selection::default(), "Bobine é"),
+            Err(Error::InvalidReelName(_))
+        ));
+
+        video
+            .set_reel_name(&mut file, Selection::default(), "A005C012_230101_R1AB")
+            .unwrap();
+        testing::redecode(&file_path, &video);
+        let description = &video.tracks[1].sample_descriptions[0];
+        assert_eq!(description.name_atom.reel_name, "A005C012_230101_R1AB");
+        assert_eq!(description.name_atom.size, 23 + 9);
+        assert_eq!(description.sample_entry.size, 57 + 9);
+        assert_eq!(file.metadata().unwrap().len(), original_len + 9);
+        assert_eq!(video.tracks[0].frames.len(), 2);
+
+        video
+            .remove_reel_name(&mut file, Selection::default())
+            .unwrap();
+        let description = &video.tracks[1].sample_descriptions[0];
+        assert!(!description.name_atom.matched);
+        assert_eq!(description.sample_entry.size, 34);
+
+        // Inserting the original name again must give back the original file.
+        video
+            .set_reel_name(&mut file, Selection::default(), "A_0005_12SO")
+            .unwrap();
+        assert_eq!(
+            std::fs::read(&file_path).unwrap(),
+            std::fs::read("tests/footages/1-1-1_2frames_prores422.mov").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_start_timecode() {
+        let (file_path, mut file, mut video) =
+            testing::open_footage("1-1-1_2frames_prores422.mov", "timecode.mov");
+        assert_eq!(
+            video.tracks[1].start_timecode().unwrap().to_string(),
+            "01:00:00:00"
+        );
+        assert!(matches!(
+            video.set_start_timecode(
+                &mut file,
+                Selection::default(),
+                &"00:00:00:24".parse().unwrap()
+            ),
+            Err(Error::InvalidTimecode(_))
+        ));
+        let video_track = Selection {
+            tracks: TrackSelector::Id(1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            video.set_start_timecode(&mut file, video_track, &"00:00:00:00".parse().unwrap()),
+            Err(Error::NotATimecodeTrack { track_id: 1, .. })
+        ));
+
+        let timecode: Timecode = "10:00:00:12".parse().unwrap();
+        video
+            .set_start_timecode(&mut file, Selection::default(), &timecode)
+            .unwrap();
+        let decoded = testing::redecode(&file_path, &video);
+        assert_eq!(decoded.tracks[1].start_timecode(), Some(timecode));
+        assert_eq!(video.tracks[1].timecode_samples[0].frame_number, 864012);
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("Track 2 ('tmcd'), starting at 10:00:00:12\n"));
+
+        // Only the 4 bytes of the sample are rewritten.
+        let original = std::fs::read("tests/footages/1-1-1_2frames_prores422.mov").unwrap();
+        let modified = std::fs::read(&file_path).unwrap();
+        let differing: Vec<usize> = (0..original.len())
+            .filter(|&i| original[i] != modified[i])
+            .collect();
+        assert!(differing.iter().all(|i| (36..40).contains(i)));
+    }
+}
@@ -0,0 +1,865 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::str::FromStr;
+
+use crate::atom::{self, AtomHeader};
+use crate::prores;
+
+/// How far the frame rate given by a time scale and a sample duration may be from a
+/// frame rate to still be taken for it, relatively. 24 fps is 1.001 × 23.976 fps.
+const FRAME_RATE_TOLERANCE: f64 = 0.0001;
+
+/// The duration of a frame in the time scale written for an integer frame rate, so
+/// that 24 fps gets a time scale of 24000 as 23.976 fps does (with frames of 1001).
+const INTEGER_FRAME_RATE_SAMPLE_DURATION: u32 = 1000;
+
+/// One of the frame rates a ProRes frame header can flag, as a fraction (numerator,
+/// denominator) of frames per second, e.g. 30000/1001 for 29.97 fps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRate {
+    numerator: u32,
+    denominator: u32,
+}
+
+impl FrameRate {
+    /// Decodes the frame_rate_code of a ProRes frame header. Unknown (0) and reserved
+    /// codes give `None`.
+    pub(crate) fn from_prores_code(code: u8) -> Option<Self> {
+        prores::frame_rate(code).map(|(numerator, denominator)| Self {
+            numerator,
+            denominator,
+        })
+    }
+
+    /// The frame_rate_code of a ProRes frame header.
+    pub(crate) fn prores_code(self) -> u8 {
+        prores::FRAME_RATES
+            .iter()
+            .position(|&rate| rate == (self.numerator, self.denominator))
+            .map_or(0, |index| index as u8 + 1)
+    }
+
+    fn fps(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// The number of frames in a second of timecode, e.g. 30 for 29.97 fps.
+    pub(crate) fn frames_per_second(self) -> u8 {
+        self.fps().round() as u8
+    }
+
+    /// Returns whether timecodes at this frame rate drop frame numbers to stay in sync
+    /// with the clock: the 1000/1001 rates counting a multiple of 30 frames per
+    /// second, i.e. 29.97, 59.94 and 119.88 fps.
+    pub(crate) fn drop_frame(self) -> bool {
+        self.denominator == 1001 && self.frames_per_second().is_multiple_of(30)
+    }
+
+    /// Returns the time scale of the media and the duration of each sample in that
+    /// time scale for this frame rate, as QuickTime writes them: 24000 and 1001 for
+    /// 23.976 fps, 24000 and 1000 for 24 fps.
+    pub(crate) fn media_timing(self) -> (u32, u32) {
+        if self.denominator == 1 {
+            (
+                self.numerator * INTEGER_FRAME_RATE_SAMPLE_DURATION,
+                INTEGER_FRAME_RATE_SAMPLE_DURATION,
+            )
+        } else {
+            (self.numerator, self.denominator)
+        }
+    }
+
+    /// Returns whether samples of `sample_duration` in a media of `timescale` units
+    /// per second are played at this frame rate.
+    pub(crate) fn matches(self, timescale: u32, sample_duration: u32) -> bool {
+        if sample_duration == 0 {
+            return false;
+        }
+        let fps = timescale as f64 / sample_duration as f64;
+        (fps / self.fps() - 1.0).abs() <= FRAME_RATE_TOLERANCE
+    }
+}
+
+/// Parses a frame rate given in frames per second as a decimal number or a fraction,
+/// e.g. `25`, `23.976`, `29.97` or `30000/1001`. Only the frame rates a ProRes frame
+/// header can flag are accepted.
+impl FromStr for FrameRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fps = match s.split_once('/') {
+            Some((numerator, denominator)) => {
+                match (
+                    numerator.trim().parse::<f64>(),
+                    denominator.trim().parse::<f64>(),
+                ) {
+                    (Ok(numerator), Ok(denominator)) if denominator > 0.0 => {
+                        Some(numerator / denominator)
+                    }
+                    _ => None,
+                }
+            }
+            None => s.trim().parse::<f64>().ok(),
+        };
+
+        // 23.98 is as common as 23.976, hence the tolerance of a hundredth.
+        fps.and_then(|fps| {
+            prores::FRAME_RATES.iter().find(|(numerator, denominator)| {
+                (fps - *numerator as f64 / *denominator as f64).abs() < 0.01
+            })
+        })
+        .map(|&(numerator, denominator)| Self {
+            numerator,
+            denominator,
+        })
+        .ok_or_else(|| {
+            "valid values are 23.976, 24, 25, 29.97, 30, 50, 59.94, 60, 100, 119.88 and 120"
+                .to_string()
+        })
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            prores::describe_frame_rate_code(self.prores_code())
+        )
+    }
+}
+
+/// The atoms of a trak holding its timing: the duration of the track in the movie
+/// (tkhd and the edit list), the time scale and duration of its media (mdhd), and the
+/// sample table holding the duration of each sample.
+pub(crate) struct TrakTiming {
+    pub(crate) tkhd: AtomHeader,
+    pub(crate) elst: Option<AtomHeader>,
+    pub(crate) mdhd: AtomHeader,
+    pub(crate) stbl: AtomHeader,
+    pub(crate) stbl_children: Vec<AtomHeader>,
+}
+
+impl TrakTiming {
+    /// Walks a trak atom down to its timing atoms. Returns `None` if it has no tkhd,
+    /// mdhd or stbl atom.
+    pub(crate) fn read(file: &mut File, trak: &AtomHeader) -> io::Result<Option<Self>> {
+        let trak_children = atom::read_children_of(file, trak)?;
+        let elst = match atom::find(&trak_children, atom::EDTS) {
+            Some(edts) => atom::find(&atom::read_children_of(file, &edts)?, atom::ELST),
+            None => None,
+        };
+        let mdia_children = match atom::find(&trak_children, atom::MDIA) {
+            Some(mdia) => atom::read_children_of(file, &mdia)?,
+            None => Vec::new(),
+        };
+        let stbl = match atom::find(&mdia_children, atom::MINF) {
+            Some(minf) => atom::find(&atom::read_children_of(file, &minf)?, atom::STBL),
+            None => None,
+        };
+
+        let (Some(tkhd), Some(mdhd), Some(stbl)) = (
+            atom::find(&trak_children, atom::TKHD),
+            atom::find(&mdia_children, atom::MDHD),
+            stbl,
+        ) else {
+            return Ok(None);
+        };
+        let stbl_children = atom::read_children_of(file, &stbl)?;
+
+        Ok(Some(Self {
+            tkhd,
+            elst,
+            mdhd,
+            stbl,
+            stbl_children,
+        }))
+    }
+
+    /// Returns the offset and size of the stts atom, or the end of stbl and 0 if there
+    /// is none, i.e. where a new stts atom goes.
+    pub(crate) fn stts_range(&self) -> (u64, u64) {
+        match atom::find(&self.stbl_children, atom::STTS) {
+            Some(stts) => (stts.offset, stts.size),
+            None => (self.stbl.end(), 0),
+        }
+    }
+}
+
+/// The time scale and duration of an mvhd or mdhd atom, which share their layout:
+/// version and flags (4 bytes), creation and modification times (4 bytes each, 8 bytes
+/// each in version 1), the time scale, then the duration (4 bytes, 8 bytes in version
+/// 1).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct HeaderTiming {
+    version: u8,
+    pub(crate) timescale: u32,
+    pub(crate) duration: u64,
+}
+
+impl HeaderTiming {
+    pub(crate) fn read(file: &mut File, header: &AtomHeader) -> io::Result<Self> {
+        let version = read_version(file, header)?;
+        let mut buf = [0; 12];
+        file.seek(io::SeekFrom::Start(
+            header.body_offset() + Self::timescale_offset(version),
+        ))?;
+        file.read_exact(&mut buf[..if version == 1 { 12 } else { 8 }])?;
+
+        let timescale = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let duration = if version == 1 {
+            u64::from_be_bytes([
+                buf[4], buf[5], buf[6], buf[7], buf[8], buf[9], buf[10], buf[11],
+            ])
+        } else {
+            u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as u64
+        };
+
+        Ok(Self {
+            version,
+            timescale,
+            duration,
+        })
+    }
+
+    /// Returns whether the duration fits the atom, i.e. its 32 bits in version 0.
+    pub(crate) fn fits(self) -> bool {
+        self.version == 1 || self.duration <= u32::MAX as u64
+    }
+
+    pub(crate) fn write(self, file: &mut File, header: &AtomHeader) -> io::Result<()> {
+        let mut bytes = self.timescale.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&duration_bytes(self.version, self.duration)?);
+        file.seek(io::SeekFrom::Start(
+            header.body_offset() + Self::timescale_offset(self.version),
+        ))?;
+        file.write_all(&bytes)
+    }
+
+    fn timescale_offset(version: u8) -> u64 {
+        if version == 1 {
+            20
+        } else {
+            12
+        }
+    }
+}
+
+/// The duration of a tkhd atom, in the time scale of the movie: after the version and
+/// flags (4 bytes), the creation and modification times (4 bytes each, 8 bytes each
+/// in version 1), the track ID and a reserved field (4 bytes each). It takes 4 bytes,
+/// 8 bytes in version 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TrackDuration {
+    version: u8,
+    pub(crate) duration: u64,
+}
+
+impl TrackDuration {
+    pub(crate) fn read(file: &mut File, header: &AtomHeader) -> io::Result<Self> {
+        let version = read_version(file, header)?;
+        let mut buf = [0; 8];
+        file.seek(io::SeekFrom::Start(
+            header.body_offset() + Self::duration_offset(version),
+        ))?;
+        let duration = if version == 1 {
+            file.read_exact(&mut buf)?;
+            u64::from_be_bytes(buf)
+        } else {
+            file.read_exact(&mut buf[..4])?;
+            u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as u64
+        };
+
+        Ok(Self { version, duration })
+    }
+
+    /// Returns whether the duration fits the atom, i.e. its 32 bits in version 0.
+    pub(crate) fn fits(self) -> bool {
+        self.version == 1 || self.duration <= u32::MAX as u64
+    }
+
+    pub(crate) fn write(self, file: &mut File, header: &AtomHeader) -> io::Result<()> {
+        file.seek(io::SeekFrom::Start(
+            header.body_offset() + Self::duration_offset(self.version),
+        ))?;
+        file.write_all(&duration_bytes(self.version, self.duration)?)
+    }
+
+    fn duration_offset(version: u8) -> u64 {
+        if version == 1 {
+            28
+        } else {
+            20
+        }
+    }
+}
+
+/// An entry of an edit list: the duration of the segment in the time scale of the
+/// movie, where it starts in the time scale of the media (-1 for an empty edit), and
+/// the rate it is played at, as a 16.16 fixed-point number.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Edit {
+    pub(crate) segment_duration: u64,
+    pub(crate) media_time: i64,
+    pub(crate) media_rate: u32,
+}
+
+/// The entries of an elst atom: version and flags (4 bytes), the entry count, then the
+/// entries whose segment duration and media time take 4 bytes each (8 bytes each in
+/// version 1), followed by the media rate.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EditList {
+    version: u8,
+    pub(crate) edits: Vec<Edit>,
+}
+
+impl EditList {
+    pub(crate) fn read(file: &mut File, header: &AtomHeader) -> io::Result<Self> {
+        let body = atom::read_body(file, header)?;
+
+        let truncated = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "edit list has more entries than its atom can hold",
+            )
+        };
+        let version = *body.first().ok_or_else(truncated)?;
+        let field = |at: usize, size: usize| -> io::Result<u64> {
+            let bytes = body.get(at..at + size).ok_or_else(truncated)?;
+            Ok(bytes
+                .iter()
+                .fold(0, |value, &byte| value << 8 | byte as u64))
+        };
+        let count = field(4, 4)? as usize;
+        let entry_size = if version == 1 { 20 } else { 12 };
+        let time_size = if version == 1 { 8 } else { 4 };
+        let edits = (0..count)
+            .map(|i| {
+                let at = 8 + i * entry_size;
+                let media_time = field(at + time_size, time_size)?;
+                Ok(Edit {
+                    segment_duration: field(at, time_size)?,
+                    media_time: if version == 1 {
+                        media_time as i64
+                    } else {
+                        media_time as u32 as i32 as i64
+                    },
+                    media_rate: field(at + 2 * time_size, 4)? as u32,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { version, edits })
+    }
+
+    /// Returns whether the segment durations and media times fit the atom, i.e. their
+    /// 32 bits in version 0.
+    pub(crate) fn fits(&self) -> bool {
+        self.version == 1
+            || self.edits.iter().all(|edit| {
+                edit.segment_duration <= u32::MAX as u64 && i32::try_from(edit.media_time).is_ok()
+            })
+    }
+
+    /// Writes the entries over the ones read, which they must be as many as.
+    pub(crate) fn write(&self, file: &mut File, header: &AtomHeader) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        for edit in self.edits.iter() {
+            bytes.extend_from_slice(&duration_bytes(self.version, edit.segment_duration)?);
+            if self.version == 1 {
+                bytes.extend_from_slice(&edit.media_time.to_be_bytes());
+            } else {
+                let media_time = i32::try_from(edit.media_time)
+                    .map_err(|_| doesnt_fit(edit.media_time.unsigned_abs()))?;
+                bytes.extend_from_slice(&media_time.to_be_bytes());
+            }
+            bytes.extend_from_slice(&edit.media_rate.to_be_bytes());
+        }
+        file.seek(io::SeekFrom::Start(header.body_offset() + 8))?;
+        file.write_all(&bytes)
+    }
+}
+
+/// The sample rate of a sound sample entry, in Hz. After the reserved bytes and data
+/// reference index (8 bytes), the version and revision (2 bytes each), the vendor (4
+/// bytes), the channel count, sample size, compression ID and packet size (2 bytes
+/// each), it takes 4 bytes as a 16.16 fixed-point number, as in the ISO audio sample
+/// entry. A QuickTime version 2 entry holds it instead as a 64-bit float after that
+/// field and the size of the fields of the entry (4 bytes each).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SampleRate {
+    version: u16,
+    pub(crate) rate: f64,
+}
+
+impl SampleRate {
+    /// Returns `None` if the sample entry is too short to hold a sample rate.
+    pub(crate) fn read(file: &mut File, header: &AtomHeader) -> io::Result<Option<Self>> {
+        let Some(payload) = atom::read_payload::<28>(file, header)? else {
+            return Ok(None);
+        };
+        let version = u16::from_be_bytes([payload[8], payload[9]]);
+        let rate = if version == 2 {
+            match atom::read_payload::<40>(file, header)? {
+                Some(payload) => f64::from_be_bytes([
+                    payload[32],
+                    payload[33],
+                    payload[34],
+                    payload[35],
+                    payload[36],
+                    payload[37],
+                    payload[38],
+                    payload[39],
+                ]),
+                None => return Ok(None),
+            }
+        } else {
+            u32::from_be_bytes([payload[24], payload[25], payload[26], payload[27]]) as f64
+                / 65536.0
+        };
+
+        Ok(Some(Self { version, rate }))
+    }
+
+    /// Returns whether the rate fits the entry, i.e. its 16.16 fixed-point field
+    /// unless it is a version 2 entry.
+    pub(crate) fn fits(self) -> bool {
+        self.version == 2 || (self.rate * 65536.0).round() <= u32::MAX as f64
+    }
+
+    pub(crate) fn write(self, file: &mut File, header: &AtomHeader) -> io::Result<()> {
+        if self.version == 2 {
+            file.seek(io::SeekFrom::Start(header.body_offset() + 32))?;
+            file.write_all(&self.rate.to_be_bytes())
+        } else {
+            let fixed = (self.rate * 65536.0).round() as u64;
+            let fixed = u32::try_from(fixed).map_err(|_| doesnt_fit(fixed))?;
+            file.seek(io::SeekFrom::Start(header.body_offset() + 24))?;
+            file.write_all(&fixed.to_be_bytes())
+        }
+    }
+}
+
+/// Multiplies a duration by `factor`, rounding to the nearest time unit.
+pub(crate) fn rescale(value: u64, factor: f64) -> u64 {
+    (value as f64 * factor).round() as u64
+}
+
+fn read_version(file: &mut File, header: &AtomHeader) -> io::Result<u8> {
+    let mut version_buf = [0; 1];
+    file.seek(io::SeekFrom::Start(header.body_offset()))?;
+    file.read_exact(&mut version_buf)?;
+    Ok(version_buf[0])
+}
+
+fn duration_bytes(version: u8, duration: u64) -> io::Result<Vec<u8>> {
+    if version == 1 {
+        Ok(duration.to_be_bytes().to_vec())
+    } else {
+        let duration = u32::try_from(duration).map_err(|_| doesnt_fit(duration))?;
+        Ok(duration.to_be_bytes().to_vec())
+    }
+}
+
+fn doesnt_fit(value: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} doesn't fit in a 32-bit field", value),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{AudioConform, ColrPolicy, Error, Selection};
+
+    #[test]
+    fn test_frame_rate() {
+        let ntsc_film: FrameRate = "23.976".parse().unwrap();
+        assert_eq!("23.98".parse(), Ok(ntsc_film));
+        assert_eq!("24000/1001".parse(), Ok(ntsc_film));
+        assert_eq!(ntsc_film.prores_code(), 1);
+        assert_eq!(FrameRate::from_prores_code(1), Some(ntsc_film));
+        assert_eq!(FrameRate::from_prores_code(0), None);
+        assert_eq!(ntsc_film.media_timing(), (24000, 1001));
+        assert_eq!(ntsc_film.to_string(), "23.976 fps");
+        assert!(!ntsc_film.drop_frame());
+        assert!("29.97".parse::<FrameRate>().unwrap().drop_frame());
+
+        let film: FrameRate = "24".parse().unwrap();
+        assert_eq!(film.prores_code(), 2);
+        assert_eq!(film.media_timing(), (24000, 1000));
+        assert!(film.matches(12288, 512));
+        assert!(!film.matches(24000, 1001));
+        assert!(ntsc_film.matches(24000, 1001));
+        assert!(!ntsc_film.matches(24000, 0));
+
+        assert!("23".parse::<FrameRate>().is_err());
+        assert!("24/0".parse::<FrameRate>().is_err());
+    }
+
+    #[test]
+    fn test_set_frame_rate() {
+        // 23.976 fps frames, whose sample lasts 1001 in a time scale of 24000.
+        let mut frame = testing::prores_frame(400, [1, 1, 1]);
+        frame[21] = 0x31; // 4:3, 23.976 fps
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(b"apcn", &[])].to_vec(),
+            samples: [frame.clone(), frame].to_vec(),
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) =
+            testing::open_mov("frame_rate.mov", &[track], Default::default());
+        assert_eq!(video.tracks[0].timescale, 24000);
+        assert_eq!(video.tracks[0].frames[1].duration, 1001);
+        assert!(!video
+            .prores_frame_mismatches()
+            .iter()
+            .any(|(_, _, mismatch)| mismatch.contains("frame rate")));
+
+        // Only the frame rate code changes: the timing now contradicts it.
+        let film: FrameRate = "24".parse().unwrap();
+        video
+            .set_frame_rate(&mut file, Selection::default(), film, false)
+            .unwrap();
+        assert_eq!(
+            video.prores_frame_mismatches(),
+            [(
+                1,
+                1,
+                "2 of 2 ProRes frames have a frame rate code contradicting their duration in the mdhd time scale of 24000"
+                    .to_string()
+            )]
+        );
+
+        // Along with the container timing, the stts atom keeps its size.
+        let size = std::fs::metadata(&file_path).unwrap().len();
+        video
+            .set_frame_rate(&mut file, Selection::default(), film, true)
+            .unwrap();
+        assert_eq!(std::fs::metadata(&file_path).unwrap().len(), size);
+        testing::redecode(&file_path, &video);
+        assert_eq!(video.tracks[0].timescale, 24000);
+        assert_eq!(video.tracks[0].frames[0].duration, 1000);
+        assert!(video.prores_frame_mismatches().is_empty());
+        let bytes = std::fs::read(&file_path).unwrap();
+        let frame_offset = video.tracks[0].frames[0].offset as usize;
+        assert_eq!(bytes[frame_offset + 21], 0x32);
+        assert!(video
+            .info(ColrPolicy::default())
+            .contains("    ProRes frame rate: 24 fps\n"));
+    }
+
+    #[test]
+    fn test_set_frame_rate_container_timing_selection() {
+        // Two sample descriptions sharing the timing of the track.
+        let track = testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [
+                testing::visual_sample_entry(b"apcn", &[]),
+                testing::visual_sample_entry(b"apch", &[]),
+            ]
+            .to_vec(),
+            samples: [
+                testing::prores_frame(400, [1, 1, 1]),
+                testing::prores_frame(500, [1, 1, 1]),
+            ]
+            .to_vec(),
+            sample_description_indexes: [1, 2].to_vec(),
+            ..Default::default()
+        };
+        let (file_path, mut file, mut video) = testing::open_mov(
+            "container_timing_selection.mov",
+            &[track],
+            Default::default(),
+        );
+        let film: FrameRate = "24".parse().unwrap();
+        let second = Selection {
+            sample_description_index: Some(2),
+            ..Default::default()
+        };
+
+        // The first frame would keep its frame rate code: nothing is written.
+        let bytes = std::fs::read(&file_path).unwrap();
+        assert!(matches!(
+            video.set_frame_rate(&mut file, second, film, true),
+            Err(Error::PartialContainerTiming { track_id: 1 })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), bytes);
+
+        // The frame rate code alone can be set in some of the frames.
+        video
+            .set_frame_rate(&mut file, second, film, false)
+            .unwrap();
+        assert_eq!(video.tracks[0].timescale, 24000);
+
+        video
+            .set_frame_rate(&mut file, Selection::default(), film, true)
+            .unwrap();
+        testing::redecode(&file_path, &video);
+        assert_eq!(video.tracks[0].timescale, 24000);
+        assert_eq!(video.tracks[0].frames[1].duration, 1000);
+        assert!(video.prores_frame_mismatches().is_empty());
+    }
+
+    #[test]
+    fn test_set_frame_rate_media_timing() {
+        // Samples of two durations: two stts entries, replaced with a single one.
+        let track = || testing::Track {
+            handler_type: *b"vide",
+            sample_entries: [testing::visual_sample_entry(b"apcn", &[])].to_vec(),
+            samples: [
+                testing::prores_frame(400, [1, 1, 1]),
+                testing::prores_frame(400, [1, 1, 1]),
+            ]
+            .to_vec(),
+            durations: [1001, 1002].to_vec(),
+            ..Default::default()
+        };
+        let film: FrameRate = "24".parse().unwrap();
+        let (file_path, mut file, mut video) =
+            testing::open_mov("media_timing.mov", &[track()], Default::default());
+        let size = std::fs::metadata(&file_path).unwrap().len();
+        video
+            .set_frame_rate(&mut file, Selection::default(), film, true)
+            .unwrap();
+        assert_eq!(std::fs::metadata(&file_path).unwrap().len(), size - 8);
+        testing::redecode(&file_path, &video);
+        assert_eq!(video.tracks[0].frames[1].duration, 1000);
+
+        // A sample count whose duration at 24 fps doesn't fit mdhd: the frames are left
+        // untouched too, and so are the other tracks when conforming.
+        let file_path = testing::write_mov("media_overflow.mov", &[track()], Default::default());
+        let mut bytes = std::fs::read(&file_path).unwrap();
+        let stsz = bytes.windows(4).position(|w| w == b"stsz").unwrap();
+        bytes[stsz + 8..stsz + 12].copy_from_slice(&400u32.to_be_bytes());
+        bytes[stsz + 12..stsz + 16].copy_from_slice(&5_000_000u32.to_be_bytes());
+        std::fs::write(&file_path, &bytes).unwrap();
+        let (file_path, mut file, mut video) = testing::open(file_path);
+        assert!(matches!(
+            video.set_frame_rate(&mut file, Selection::default(), film, true),
+            Err(Error::MediaDurationOverflow { track_id: 1 })
+        ));
+        assert!(matches!(
+            video.conform_frame_rate(&mut file, Selection::default(), film, AudioConform::Leave),
+            Err(Error::MediaDurationOverflow { track_id: 1 })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_set_frame_rate_container_timing() {
+        // 24 fps: a time scale of 12288 and frames of 512, with a timecode track.
+        let (file_path, mut file, mut video) =
+            testing::open_footage("1-1-1_2frames_prores422.mov", "container_timing.mov");
+
+        let pal: FrameRate = "25".parse().unwrap();
+        video
+            .set_frame_rate(&mut file, Selection::default(), pal, true)
+            .unwrap();
+        testing::redecode(&file_path, &video);
+        assert_eq!(video.tracks[0].timescale, 25000);
+        assert_eq!(video.tracks[1].timescale, 12288);
+
+        // The two frames now last 2/25 s, i.e. 983 in the movie time scale of 12288,
+        // while the timecode track still lasts 2/24 s.
+        let traks = atom::traks(&mut file).unwrap();
+        for (trak, duration) in traks.iter().zip([983, 1024]) {
+            let timing = TrakTiming::read(&mut file, trak).unwrap().unwrap();
+            assert_eq!(
+                TrackDuration::read(&mut file, &timing.tkhd)
+                    .unwrap()
+                    .duration,
+                duration
+            );
+            let edit_list = EditList::read(&mut file, &timing.elst.unwrap()).unwrap();
+            assert_eq!(edit_list.edits[0].segment_duration, duration);
+        }
+        let bytes = std::fs::read(&file_path).unwrap();
+        let mvhd = bytes.windows(4).position(|w| w == b"mvhd").unwrap();
+        assert_eq!(bytes[mvhd + 20..mvhd + 24], 1024u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_conform_frame_rate() {
+        // 24 fps: a time scale of 12288 and frames of 512, with a timecode track.
+        let (file_path, mut file, mut video) =
+            testing::open_footage("1-1-1_2frames_prores422.mov", "conform.mov");
+
+        let pal: FrameRate = "25".parse().unwrap();
+        let left_alone = video
+            .conform_frame_rate(&mut file, Selection::default(), pal, AudioConform::Leave)
+            .unwrap();
+        assert!(left_alone.is_empty());
+        testing::redecode(&file_path, &video);
+        assert_eq!(video.tracks[0].timescale, 25000);
+        assert_eq!(video.tracks[0].frames[1].duration, 1000);
+        assert_eq!(video.tracks[0].frames[1].frame_rate_code, 3);
+        assert_eq!(video.tracks[1].timescale, 25000);
+        assert!(video.prores_frame_mismatches().is_empty());
+        // The timecode track still starts at 01:00:00:00, now frame 90000.
+        assert_eq!(
+            video.tracks[1].start_timecode().unwrap().to_string(),
+            "01:00:00:00"
+        );
+        assert_eq!(video.tracks[1].timecode_samples[0].frame_number, 90000);
+
+        // The two frames now last 2/25 s, i.e. 983 in the movie time scale of 12288.
+        let traks = atom::traks(&mut file).unwrap();
+        for trak in traks.iter() {
+            let timing = TrakTiming::read(&mut file, trak).unwrap().unwrap();
+            assert_eq!(
+                TrackDuration::read(&mut file, &timing.tkhd)
+                    .unwrap()
+                    .duration,
+                983
+            );
+            let edit_list = EditList::read(&mut file, &timing.elst.unwrap()).unwrap();
+            assert_eq!(edit_list.edits[0].segment_duration, 983);
+            assert_eq!(edit_list.edits[0].media_time, 0);
+            assert_eq!(
+                HeaderTiming::read(&mut file, &timing.mdhd)
+                    .unwrap()
+                    .duration,
+                2000
+            );
+        }
+        let bytes = std::fs::read(&file_path).unwrap();
+        let mvhd = bytes.windows(4).position(|w| w == b"mvhd").unwrap();
+        assert_eq!(bytes[mvhd + 20..mvhd + 24], 983u32.to_be_bytes());
+        // The timecode sample description: time scale, frame duration, frames.
+        let tmcd = video.tracks[1].sample_descriptions[0].sample_entry;
+        let at = tmcd.body_offset() as usize + 16;
+        assert_eq!(bytes[at..at + 9], [0, 0, 0x61, 0xA8, 0, 0, 0x03, 0xE8, 25]);
+    }
+
+    #[test]
+    fn test_conform_frame_rate_timecode() {
+        // Timecodes drop frame numbers at 29.97 fps, but not at 25 fps.
+        let (file_path, mut file, mut video) =
+            testing::open_footage("1-1-1_2frames_prores422.mov", "conform_timecode.mov");
+        for (frame_rate, start_timecode, frame_number) in [
+            ("29.97", "01:00:00;00", 107892),
+            ("25", "01:00:00:00", 90000),
+        ] {
+            video
+                .conform_frame_rate(
+                    &mut file,
+                    Selection::default(),
+                    frame_rate.parse().unwrap(),
+                    AudioConform::Leave,
+                )
+                .unwrap();
+            testing::redecode(&file_path, &video);
+            assert_eq!(
+                video.tracks[1].start_timecode().unwrap().to_string(),
+                start_timecode
+            );
+            assert_eq!(
+                video.tracks[1].timecode_samples[0].frame_number,
+                frame_number
+            );
+        }
+
+        // A tape counter has no timecode to keep: nothing is written.
+        let entry = video.tracks[1].sample_descriptions[0].sample_entry;
+        let mut bytes = std::fs::read(&file_path).unwrap();
+        bytes[entry.body_offset() as usize + 15] |= 0x08;
+        std::fs::write(&file_path, &bytes).unwrap();
+        let mut video = testing::decode(&file_path);
+        assert!(matches!(
+            video.conform_frame_rate(
+                &mut file,
+                Selection::default(),
+                "24".parse().unwrap(),
+                AudioConform::Leave
+            ),
+            Err(Error::UnsupportedTimecodeFormat { track_id: 2 })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_conform_frame_rate_audio() {
+        // 23.976 fps video along with an audio track, both in a time scale of 24000. The
+        // audio track has two sample descriptions at 48000 Hz: a 16.16 fixed-point rate
+        // and a QuickTime version 2 one, whose rate is a 64-bit float.
+        let sound_entry = |version: u16, fixed_rate: u32| {
+            let mut payload = [0; 40];
+            payload[8..10].copy_from_slice(&version.to_be_bytes());
+            payload[24..28].copy_from_slice(&fixed_rate.to_be_bytes());
+            payload[32..40].copy_from_slice(&48000f64.to_be_bytes());
+            testing::atom(b"lpcm", &payload)
+        };
+        let mut tracks = [
+            testing::Track {
+                handler_type: *b"vide",
+                sample_entries: [testing::visual_sample_entry(b"apcn", &[])].to_vec(),
+                samples: [testing::prores_frame(400, [1, 1, 1])].to_vec(),
+                ..Default::default()
+            },
+            testing::Track {
+                handler_type: *b"soun",
+                sample_entries: [sound_entry(0, 48000 << 16), sound_entry(2, 0x10000)].to_vec(),
+                samples: [vec![0; 64]].to_vec(),
+                ..Default::default()
+            },
+        ];
+        let film: FrameRate = "24".parse().unwrap();
+
+        for (audio, audio_timescale, sample_rate) in [
+            (AudioConform::Leave, 24000, 48000.0),
+            (AudioConform::Rescale, 24024, 48048.0),
+        ] {
+            let (_file_path, mut file, mut video) =
+                testing::open_mov("conform_audio.mov", &tracks, Default::default());
+
+            let left_alone = video
+                .conform_frame_rate(&mut file, Selection::default(), film, audio)
+                .unwrap();
+            assert_eq!(left_alone.is_empty(), audio == AudioConform::Rescale);
+            assert_eq!(video.tracks[0].timescale, 24000);
+            assert_eq!(video.tracks[0].frames[0].duration, 1000);
+            assert_eq!(video.tracks[1].timescale, audio_timescale);
+            for description in video.tracks[1].sample_descriptions.iter() {
+                let entry = description.sample_entry;
+                let rate = SampleRate::read(&mut file, &entry).unwrap().unwrap().rate;
+                assert_eq!(rate, sample_rate);
+            }
+        }
+
+        // A fixed-point rate that doesn't fit its field once sped up: nothing is written.
+        tracks[1].sample_entries[0] = sound_entry(0, 0xFFFF << 16);
+        let (file_path, mut file, mut video) =
+            testing::open_mov("conform_audio_rate.mov", &tracks, Default::default());
+        let bytes = std::fs::read(&file_path).unwrap();
+        assert!(matches!(
+            video.conform_frame_rate(&mut file, Selection::default(), film, AudioConform::Rescale),
+            Err(Error::SampleRateOverflow { track_id: 2 })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), bytes);
+
+        // An audio time scale that doesn't fit mdhd once sped up: nothing is written.
+        let file_path =
+            testing::write_mov("conform_audio_overflow.mov", &tracks, Default::default());
+        let mut bytes = std::fs::read(&file_path).unwrap();
+        let mdhd = bytes
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == b"mdhd")
+            .nth(1)
+            .unwrap()
+            .0;
+        bytes[mdhd + 16..mdhd + 20].copy_from_slice(&u32::MAX.to_be_bytes());
+        std::fs::write(&file_path, &bytes).unwrap();
+        let (file_path, mut file, mut video) = testing::open(file_path);
+        assert!(matches!(
+            video.conform_frame_rate(&mut file, Selection::default(), film, AudioConform::Rescale),
+            Err(Error::MediaDurationOverflow { track_id: 2 })
+        ));
+        assert_eq!(std::fs::read(&file_path).unwrap(), bytes);
+    }
+}